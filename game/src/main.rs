@@ -1,6 +1,9 @@
 mod polygon;
 
 use bevy::prelude::*;
+use level_generator::level::{file, Level, LevelParams};
+
+use polygon::Polygon;
 
 fn main() {
     App::new()
@@ -11,8 +14,27 @@ fn main() {
 
 fn setup(
     mut commands: Commands,
-    mut _meshes: ResMut<Assets<Mesh>>,
-    mut _materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     commands.spawn(Camera2dBundle::default());
+
+    // the level saved in the file given on the command line, or a fresh one
+    let level = match std::env::args().nth(1) {
+        Some(path) => file::load(&path).unwrap_or_else(|e| panic!("can't load {path}: {e}")),
+        None => Level::generate(0, &LevelParams::default()),
+    };
+
+    for room in &level.rooms {
+        Polygon::from(room).draw(&mut commands, &mut meshes, &mut materials, Color::GRAY);
+    }
+
+    for outline in level.halls.iter().flat_map(|hall| &hall.outlines) {
+        Polygon::from(&outline[..]).draw(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            Color::DARK_GRAY,
+        );
+    }
 }
@@ -3,12 +3,14 @@ use bevy::prelude::*;
 use bevy::render::mesh::Indices;
 use bevy::render::render_resource::PrimitiveTopology;
 use bevy::sprite::MaterialMesh2dBundle;
+use level_generator::{geometry::triangulate::ear_clipping, level::Room, Point};
 
 /**
-    Convex polygon
+    Polygon mesh with its contours
 
-   The polygon is expected to be centred around (0,0) and convex.
-   No verification is performed on the convexity, this is left up to the user
+   Built from a slice of points, the polygon is expected to be centred around (0,0) and convex.
+   No verification is performed on the convexity, this is left up to the user.
+   Rooms and halls of a level can be concave, they are triangulated by ear clipping instead.
 */
 pub struct Polygon {
     _polygon: Mesh,
@@ -16,7 +18,42 @@ pub struct Polygon {
 }
 
 impl Polygon {
-    pub fn _draw(
+    /// Mesh of `points` filled with the triangles of `indices`, three indices per triangle
+    fn new(points: &[[f32; 2]], indices: Vec<u32>) -> Self {
+        assert!(points.len() > 2);
+
+        let mut points_3d: Vec<_> = Vec::with_capacity(points.len());
+        let mut normals: Vec<_> = Vec::with_capacity(points.len());
+        let mut uvs: Vec<_> = Vec::with_capacity(points.len());
+
+        for p in points {
+            points_3d.push([p[0], p[1], 0.0]);
+            normals.push([0.0, 0.0, 1.0]);
+            uvs.push([0.0, 0.0]);
+        }
+
+        let mut polygon = Mesh::new(PrimitiveTopology::TriangleList);
+        polygon.set_indices(Some(Indices::U32(indices)));
+        polygon.insert_attribute(Mesh::ATTRIBUTE_POSITION, points_3d.clone());
+        polygon.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals.clone());
+        polygon.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs.clone());
+
+        points_3d.push(points_3d[0]);
+        normals.push(normals[0]);
+        uvs.push(uvs[0]);
+
+        let mut contours = Mesh::new(PrimitiveTopology::LineStrip);
+        contours.insert_attribute(Mesh::ATTRIBUTE_POSITION, points_3d);
+        contours.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        contours.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+
+        Polygon {
+            _polygon: polygon,
+            _contours: contours,
+        }
+    }
+
+    pub fn draw(
         &self,
         commands: &mut Commands,
         meshes: &mut ResMut<Assets<Mesh>>,
@@ -39,20 +76,30 @@ impl Polygon {
     }
 }
 
+impl From<&[Point]> for Polygon {
+    fn from(outline: &[Point]) -> Self {
+        let points: Vec<[f32; 2]> = outline.iter().map(|p| [p.x as f32, p.y as f32]).collect();
+
+        let indices = ear_clipping(outline)
+            .into_iter()
+            .flatten()
+            .map(|i| i as u32)
+            .collect();
+
+        Polygon::new(&points, indices)
+    }
+}
+
+impl From<&Room> for Polygon {
+    fn from(room: &Room) -> Self {
+        Polygon::from(&room.outline[..])
+    }
+}
+
 impl From<&[[f32; 2]]> for Polygon {
     fn from(points: &[[f32; 2]]) -> Self {
         assert!(points.len() > 2);
 
-        let mut points_3d: Vec<_> = Vec::with_capacity(points.len());
-        let mut normals: Vec<_> = Vec::with_capacity(points.len());
-        let mut uvs: Vec<_> = Vec::with_capacity(points.len());
-
-        for p in points {
-            points_3d.push([p[0], p[1], 0.0]);
-            normals.push([0.0, 0.0, 1.0]);
-            uvs.push([0.0, 0.0]);
-        }
-
         let nb_triangles = points.len() - 2;
 
         let indices: Vec<_> = (1..)
@@ -61,25 +108,7 @@ impl From<&[[f32; 2]]> for Polygon {
             .flatten()
             .collect();
 
-        let mut polygon = Mesh::new(PrimitiveTopology::TriangleList);
-        polygon.set_indices(Some(Indices::U32(indices)));
-        polygon.insert_attribute(Mesh::ATTRIBUTE_POSITION, points_3d.clone());
-        polygon.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals.clone());
-        polygon.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs.clone());
-
-        points_3d.push(points_3d[0]);
-        normals.push(normals[0]);
-        uvs.push(uvs[0]);
-
-        let mut contours = Mesh::new(PrimitiveTopology::LineStrip);
-        contours.insert_attribute(Mesh::ATTRIBUTE_POSITION, points_3d);
-        contours.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-        contours.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-
-        Polygon {
-            _polygon: polygon,
-            _contours: contours,
-        }
+        Polygon::new(points, indices)
     }
 }
 
@@ -91,7 +120,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn panics_if_less_than_three_points() {
-        let points = vec![];
+        let points: Vec<[f32; 2]> = vec![];
 
         let _: Polygon = (&points[..]).into();
     }
@@ -148,4 +177,39 @@ mod tests {
         let points = [[-0.5, -0.5], [0.5, -0.5], [0.5, 0.5], [-0.5, 0.5]];
         test_shape(&points);
     }
+
+    #[test]
+    fn concave_room_stays_inside() {
+        use level_generator::level::RoomId;
+
+        // an L, the fan from its first vertex would cover its missing corner
+        let outline = [
+            [1.0, 1.0],
+            [1.0, 2.0],
+            [0.0, 2.0],
+            [0.0, 0.0],
+            [2.0, 0.0],
+            [2.0, 1.0],
+        ];
+        let room = Room {
+            id: RoomId(0),
+            outline: outline.iter().map(|&[x, y]| Point { x, y }).collect(),
+            center: Point { x: 0.5, y: 0.5 },
+        };
+
+        let shape = Polygon::from(&room);
+        let indices: Vec<usize> = shape._polygon.indices().unwrap().iter().collect();
+        assert_eq!(indices.len(), 12);
+
+        // the triangles cover the area of the L and nothing more
+        let area: f64 = indices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [outline[t[0]], outline[t[1]], outline[t[2]]];
+                ((b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])) / 2.0
+            })
+            .inspect(|&area| assert!(area > 0.0))
+            .sum();
+        assert!((area - 3.0).abs() < 1e-12);
+    }
 }
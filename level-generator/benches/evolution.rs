@@ -0,0 +1,60 @@
+//! Throughput of the evolution with the traffic fitness, on one thread then on every core.
+//!
+//! Run with `cargo bench -p level-generator`.
+
+use std::time::{Duration, Instant};
+
+use level_generator::{
+    evolution::{flow::Flow, Evolution, Params},
+    sampling::Strategy,
+    Point, Polygon,
+};
+
+const ROOMS: usize = 60;
+const GENERATIONS: usize = 10;
+
+fn p(x: f64, y: f64) -> Point {
+    Point { x, y }
+}
+
+fn run(threads: usize) -> (Duration, f64) {
+    let bounds = Polygon::from_points(vec![p(-1.0, -1.0), p(1.0, -1.0), p(1.0, 1.0), p(-1.0, 1.0)]);
+    let flow = Flow::new(vec![p(-0.9, -0.9), p(0.9, 0.9), p(0.9, -0.9), p(-0.9, 0.9)]);
+
+    let params = Params {
+        population: 64,
+        generations: GENERATIONS,
+        seed: 42,
+        threads,
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    let mut evolution = Evolution::random(params, &bounds, flow, &Strategy::Uniform, ROOMS);
+    let best = evolution.run().fitness;
+
+    (start.elapsed(), best)
+}
+
+fn main() {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let individuals = (64 * (GENERATIONS + 1)) as f64;
+
+    let (serial, serial_best) = run(1);
+    let (parallel, parallel_best) = run(0);
+
+    // the same seed must give the same level, whatever the number of threads
+    assert_eq!(serial_best.to_bits(), parallel_best.to_bits());
+
+    for (name, time) in [("serial", serial), ("parallel", parallel)] {
+        println!(
+            "{name:>8}: {:>8.1} individuals/s ({:.2?})",
+            individuals / time.as_secs_f64(),
+            time
+        );
+    }
+    println!(
+        "speedup on {cores} cores: {:.2}x",
+        serial.as_secs_f64() / parallel.as_secs_f64()
+    );
+}
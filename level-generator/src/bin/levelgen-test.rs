@@ -1,100 +1,542 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{builder::RangedU64ValueParser, Args, Parser, Subcommand, ValueEnum};
+
 use level_generator::{
-    self,
-    voronoi::{self, generate_voronoi},
-    Point,
+    evolution::{
+        fitness::{Compactness, CriticalPath, EvenAreas, Loops, RoomCompactness},
+        Evolution, Fitness, Params,
+    },
+    geometry::signed_area,
+    graph::{corridors::CorridorParams, doors::DoorParams},
+    level::{file, Level, LevelParams, RoomId},
+    render::{
+        raster::{rasterise, Heatmap},
+        svg, Scene, Style,
+    },
+    sampling::Strategy,
+    Point, Polygon,
 };
 
-use graplot::Scatter;
+/// Generates, evolves and inspects organic levels
+#[derive(Parser)]
+#[command(name = "levelgen-test")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
 
-fn main() {
-    let points = level_generator::random_points(20);
+#[derive(Subcommand)]
+enum Command {
+    /// Generates a level from a seed
+    Generate {
+        #[command(flatten)]
+        sites: Sites,
+        #[command(flatten)]
+        shape: Shape,
+        #[command(flatten)]
+        openings: Openings,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Evolves the sites of a level with a genetic algorithm, and keeps the best one
+    Evolve {
+        #[command(flatten)]
+        sites: Sites,
+        #[command(flatten)]
+        openings: Openings,
+        /// Levels in each generation, the two best ones going on to the next
+        #[arg(long, default_value_t = 50, value_parser = RangedU64ValueParser::<usize>::new().range(2..))]
+        population: usize,
+        #[arg(long, default_value_t = 100)]
+        generations: usize,
+        /// What makes a level better than another
+        #[arg(long, value_enum, default_value_t = Objective::EvenAreas)]
+        fitness: Objective,
+        /// Threads evaluating the levels, 0 for one per core
+        #[arg(long, default_value_t = 0)]
+        threads: usize,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Converts a saved level to another format, or draws it
+    Render {
+        input: PathBuf,
+        /// Colors the rooms by this value, in PNG pictures only
+        #[arg(long, value_enum)]
+        heatmap: Option<Heat>,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Prints figures about a saved level
+    Stats { input: PathBuf },
+    /// Checks saved levels, and fails if any of them has a problem
+    Validate {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+}
 
-    let x_pos: Vec<_> = points.iter().map(|p| p.x).collect();
-    let y_pos: Vec<_> = points.iter().map(|p| p.y).collect();
+#[derive(Args)]
+struct Sites {
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Number of Voronoi sites, one per room before merging
+    #[arg(short = 'n', long, default_value_t = 50, value_parser = RangedU64ValueParser::<usize>::new().range(4..))]
+    points: usize,
+    /// uniform, poisson:<radius>, grid:<jitter>, halton, sobol or clusters:<count>:<spread>
+    #[arg(long, default_value = "uniform", value_parser = parse_strategy)]
+    strategy: Strategy,
+}
 
-    let mut plot = Scatter::new((x_pos, y_pos));
+#[derive(Args)]
+struct Shape {
+    /// Iterations of Lloyd relaxation
+    #[arg(long, default_value_t = 2)]
+    relaxation: usize,
+    /// Only keeps the rooms inside the alpha shape of the sites
+    #[arg(long, value_parser = parse_positive)]
+    alpha: Option<f64>,
+    /// Merges neighbouring rooms up to about this area
+    #[arg(long, value_parser = parse_positive)]
+    merge: Option<f64>,
+}
 
-    let alpha_shape = get_alpha_shape(&points);
-    plot.add((alpha_shape.0, alpha_shape.1, "r-o"));
+#[derive(Args)]
+struct Openings {
+    /// Share of the possible loops getting a door, from 0 to 1
+    #[arg(long, default_value_t = 0.0, value_parser = parse_ratio)]
+    loops: f64,
+    /// Pulls the rooms apart and links them with corridors
+    #[arg(long)]
+    corridors: bool,
+}
 
-    let cells = generate_voronoi(points);
+#[derive(Args)]
+struct Output {
+    /// File to write, the standard output when left out
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Guessed from the extension of the output when left out, RON otherwise
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+    /// Width of the pictures, in pixels
+    #[arg(long, default_value_t = 800, value_parser = clap::value_parser!(u32).range(1..))]
+    width: u32,
+}
 
-    for c in cells.iter_cells() {
-        let x_pos: Vec<_> = c
-            .points()
-            .iter()
-            .cycle()
-            .take(c.points().len() + 1)
-            .map(|p| p.x)
-            .collect();
-        let y_pos: Vec<_> = c
-            .points()
-            .iter()
-            .cycle()
-            .take(c.points().len() + 1)
-            .map(|p| p.y)
-            .collect();
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Json,
+    Ron,
+    Svg,
+    Png,
+}
 
-        plot.add((x_pos, y_pos, "b-o"))
+#[derive(Clone, Copy, ValueEnum)]
+enum Objective {
+    EvenAreas,
+    Loops,
+    CriticalPath,
+    /// Compactness of the outline of the level
+    Compactness,
+    /// Mean compactness of the rooms
+    RoomCompactness,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Heat {
+    /// Area of the room
+    Area,
+    /// Number of doors of the room
+    Doors,
+    /// Number of doors to go through from the first room
+    Depth,
+}
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Generate {
+            sites,
+            shape,
+            openings,
+            output,
+        } => generate(&sites, &shape, &openings, &output),
+        Command::Evolve {
+            sites,
+            openings,
+            population,
+            generations,
+            fitness,
+            threads,
+            output,
+        } => evolve(
+            &sites,
+            &openings,
+            Params {
+                population,
+                generations,
+                seed: sites.seed,
+                threads,
+                ..Default::default()
+            },
+            fitness,
+            &output,
+        ),
+        Command::Render {
+            input,
+            heatmap,
+            output,
+        } => load(&input).and_then(|level| write_level(&level, &output, heatmap)),
+        Command::Stats { input } => load(&input).map(|level| stats(&level)),
+        Command::Validate { inputs } => return validate(&inputs),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Loads a level, which must be valid, as hand-edited files may not be
+fn load(path: &Path) -> Result<Level, Box<dyn Error>> {
+    let level = file::load(path)?;
+    let problems = level.validate();
+
+    if problems.is_empty() {
+        return Ok(level);
     }
 
-    plot.show();
+    let mut message = format!("{} is not a valid level:", path.display());
+    for problem in problems {
+        message.push_str(&format!("\n  {problem}"));
+    }
+    Err(message.into())
 }
 
-fn get_alpha_shape(points: &[Point]) -> (Vec<f64>, Vec<f64>) {
-    let polygon = voronoi::alpha_shape(&points, f64::INFINITY);
+fn generate(
+    sites: &Sites,
+    shape: &Shape,
+    openings: &Openings,
+    output: &Output,
+) -> Result<(), Box<dyn Error>> {
+    let params = LevelParams {
+        relaxation: shape.relaxation,
+        alpha: shape.alpha,
+        merge_area: shape.merge,
+        ..level_params(sites, openings)
+    };
 
-    let barycenter = polygon
-        .points()
-        .iter()
-        .fold(Point { x: 0.0, y: 0.0 }, |a, point| Point {
-            x: a.x + point.x,
-            y: a.y + point.y,
-        });
-
-    let barycenter = Point {
-        x: barycenter.x / polygon.points().len() as f64,
-        y: barycenter.y / polygon.points().len() as f64,
+    write_level(&Level::generate(sites.seed, &params), output, None)
+}
+
+fn evolve(
+    sites: &Sites,
+    openings: &Openings,
+    params: Params,
+    objective: Objective,
+    output: &Output,
+) -> Result<(), Box<dyn Error>> {
+    let fitness: Box<dyn Fitness> = match objective {
+        Objective::EvenAreas => Box::new(EvenAreas),
+        Objective::Loops => Box::new(Loops),
+        Objective::CriticalPath => Box::new(CriticalPath),
+        Objective::Compactness => Box::new(Compactness),
+        Objective::RoomCompactness => Box::new(RoomCompactness),
     };
 
-    let scale_factor = 1.1;
+    let mut evolution = Evolution::random(
+        params,
+        &square(1.0),
+        |genome: &_, cells: &_| fitness.fitness(genome, cells),
+        &sites.strategy,
+        sites.points,
+    );
+
+    while evolution.generation() < evolution.params().generations {
+        let s = evolution.step();
+        eprintln!(
+            "generation {}: best {:.4}, mean {:.4}, worst {:.4}",
+            s.generation, s.best, s.mean, s.worst
+        );
+    }
+
+    let level = Level::from_cells(
+        &evolution.best_cells(),
+        sites.seed,
+        &LevelParams {
+            relaxation: 0,
+            ..level_params(sites, openings)
+        },
+    );
+
+    write_level(&level, output, None)
+}
+
+fn level_params(sites: &Sites, openings: &Openings) -> LevelParams {
+    LevelParams {
+        rooms: sites.points,
+        strategy: sites.strategy.clone(),
+        doors: DoorParams {
+            loop_ratio: openings.loops,
+            ..Default::default()
+        },
+        corridors: openings.corridors.then(CorridorParams::default),
+        ..Default::default()
+    }
+}
+
+fn write_level(level: &Level, output: &Output, heat: Option<Heat>) -> Result<(), Box<dyn Error>> {
+    let format = output
+        .format
+        .or_else(|| output.output.as_deref().and_then(format_of))
+        .unwrap_or(Format::Ron);
+
+    if heat.is_some() && format != Format::Png {
+        return Err("heatmaps are only drawn in PNG pictures".into());
+    }
+
+    let mut writer: Box<dyn Write> = match &output.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let mut scene = Scene::new();
+    scene.level(level);
+    let style = Style {
+        width: output.width,
+        ..Default::default()
+    };
+
+    match format {
+        Format::Json => writer.write_all(file::to_string(level, file::Format::Json)?.as_bytes())?,
+        Format::Ron => writer.write_all(file::to_string(level, file::Format::Ron)?.as_bytes())?,
+        Format::Svg => writer.write_all(svg::write(&scene, &style).as_bytes())?,
+        Format::Png => {
+            let values = heat.map(|heat| heat_values(level, heat));
+            let heatmap = values
+                .as_ref()
+                .map(|values| Heatmap::rooms(&level.rooms, values));
+
+            rasterise(&scene, &style, heatmap.as_ref()).write_png(&mut writer)?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn format_of(path: &Path) -> Option<Format> {
+    match path.extension()?.to_str()? {
+        "json" => Some(Format::Json),
+        "ron" => Some(Format::Ron),
+        "svg" => Some(Format::Svg),
+        "png" => Some(Format::Png),
+        _ => None,
+    }
+}
 
-    let polygon = level_generator::Polygon::from_points(
-        polygon
-            .points()
+fn heat_values(level: &Level, heat: Heat) -> Vec<f64> {
+    match heat {
+        Heat::Area => level
+            .rooms
             .iter()
-            .map(|p| {
-                let mut center = Point {
-                    x: p.x - barycenter.x,
-                    y: p.y - barycenter.y,
-                };
-
-                // rescale
-                center.x *= scale_factor;
-                center.y *= scale_factor;
-
-                // put back in "standard" coordinates
-                center.x += barycenter.x;
-                center.y += barycenter.y;
-                center
-            })
+            .map(|room| signed_area(&room.outline))
             .collect(),
-    );
+        Heat::Doors => level
+            .rooms
+            .iter()
+            .map(|room| level.room_doors(room.id).count() as f64)
+            .collect(),
+        Heat::Depth => depths(level, RoomId(0))
+            .into_iter()
+            .map(|depth| depth.map_or(0.0, |d| d as f64))
+            .collect(),
+    }
+}
+
+/// Number of doors to go through from `from` to each room, `None` for the rooms out of reach
+fn depths(level: &Level, from: RoomId) -> Vec<Option<usize>> {
+    let mut depths = vec![None; level.rooms.len()];
+    let mut queue = std::collections::VecDeque::from([(from, 0)]);
 
-    let x_pos = polygon
-        .points()
+    while let Some((room, depth)) = queue.pop_front() {
+        // rooms missing from the level, `from` included when there are none, lead nowhere
+        match depths.get_mut(room.0 as usize) {
+            Some(unvisited @ None) => *unvisited = Some(depth),
+            _ => continue,
+        }
+
+        for door in level.room_doors(room) {
+            let other = if door.rooms.0 == room {
+                door.rooms.1
+            } else {
+                door.rooms.0
+            };
+            queue.push_back((other, depth + 1));
+        }
+    }
+
+    depths
+}
+
+fn stats(level: &Level) {
+    let areas: Vec<f64> = level
+        .rooms
         .iter()
-        .cycle()
-        .take(polygon.points().len() + 1)
-        .map(|p| p.x)
+        .map(|room| signed_area(&room.outline))
         .collect();
-    let y_pos = polygon
-        .points()
+    let total = areas.iter().fold(0.0, |total, area| total + area);
+
+    let mut components = 0;
+    let mut reached = vec![false; level.rooms.len()];
+    let mut longest = 0;
+    for room in &level.rooms {
+        let depths = depths(level, room.id);
+        longest = longest.max(depths.iter().flatten().copied().max().unwrap_or(0));
+
+        if !reached[room.id.0 as usize] {
+            components += 1;
+            depths
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| d.is_some())
+                .for_each(|(i, _)| reached[i] = true);
+        }
+    }
+
+    let dead_ends = level
+        .rooms
         .iter()
-        .cycle()
-        .take(polygon.points().len() + 1)
-        .map(|p| p.y)
-        .collect();
+        .filter(|room| level.room_doors(room.id).count() == 1)
+        .count();
+
+    println!("version:      {}", level.version);
+    println!("seed:         {}", level.seed);
+    println!("rooms:        {}", level.rooms.len());
+    println!("walls:        {}", level.walls.len());
+    println!("doors:        {}", level.doors.len());
+    println!("halls:        {}", level.halls.len());
+    println!("total area:   {total:.4}");
+    if !areas.is_empty() {
+        println!(
+            "room area:    {:.4} min, {:.4} mean, {:.4} max",
+            areas.iter().copied().fold(f64::INFINITY, f64::min),
+            total / areas.len() as f64,
+            areas.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+        );
+    }
+    println!("pieces:       {components}");
+    println!(
+        "loops:        {}",
+        (level.doors.len() + components).saturating_sub(level.rooms.len())
+    );
+    println!("dead ends:    {dead_ends}");
+    println!("longest trip: {longest} doors");
+}
+
+fn validate(inputs: &[PathBuf]) -> ExitCode {
+    let mut valid = true;
+
+    for input in inputs {
+        match file::load(input) {
+            Ok(level) => {
+                let problems = level.validate();
+                if problems.is_empty() {
+                    println!("{}: ok", input.display());
+                }
+                for problem in &problems {
+                    println!("{}: {problem}", input.display());
+                }
+                valid &= problems.is_empty();
+            }
+            Err(e) => {
+                println!("{}: {e}", input.display());
+                valid = false;
+            }
+        }
+    }
+
+    if valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn parse_strategy(text: &str) -> Result<Strategy, String> {
+    let mut parts = text.split(':');
+    let name = parts.next().unwrap_or_default();
+    let mut value = |what: &str, rule: &str, valid: fn(f64) -> bool| -> Result<f64, String> {
+        let text = parts
+            .next()
+            .ok_or_else(|| format!("{name} needs a {what}"))?;
+
+        match text.parse() {
+            Ok(value) if valid(value) => Ok(value),
+            _ => Err(format!("the {what} of {name} must be {rule}, not {text}")),
+        }
+    };
+
+    let strategy = match name {
+        "uniform" => Strategy::Uniform,
+        // larger disks leave too few sites to make a level, smaller ones are no different from
+        // uniform points
+        "poisson" => Strategy::PoissonDisk {
+            radius: value("radius", "from 0.001 to 0.5", |r| {
+                (0.001..=0.5).contains(&r)
+            })?,
+        },
+        "grid" => Strategy::JitteredGrid {
+            jitter: value("jitter", "from 0 to 1", |j| (0.0..=1.0).contains(&j))?,
+        },
+        "halton" => Strategy::Halton,
+        "sobol" => Strategy::Sobol,
+        "clusters" => Strategy::Clusters {
+            clusters: value("count", "a whole number from 1 to 1000", |c| {
+                (1.0..=1000.0).contains(&c) && c.fract() == 0.0
+            })? as usize,
+            // wider clusters than the domain are no different from uniform points
+            spread: value("spread", "above 0 and at most 1", |s| s > 0.0 && s <= 1.0)?,
+        },
+        _ => return Err(format!("unknown strategy {name}")),
+    };
+
+    if parts.next().is_some() {
+        return Err(format!("too many values for {name}"));
+    }
+
+    Ok(strategy)
+}
+
+fn parse_positive(text: &str) -> Result<f64, String> {
+    match text.parse() {
+        Ok(value) if value > 0.0 && f64::is_finite(value) => Ok(value),
+        _ => Err(format!("{text} is not a number above 0")),
+    }
+}
+
+fn parse_ratio(text: &str) -> Result<f64, String> {
+    match text.parse() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        _ => Err(format!("{text} is not a number from 0 to 1")),
+    }
+}
 
-    (x_pos, y_pos)
+fn square(size: f64) -> Polygon<Point> {
+    Polygon::from_points(vec![
+        Point { x: -size, y: -size },
+        Point { x: size, y: -size },
+        Point { x: size, y: size },
+        Point { x: -size, y: size },
+    ])
 }
@@ -0,0 +1,657 @@
+//! Genetic algorithm evolving level layouts.
+//!
+//! An individual is a set of seed points with their weights, whose power diagram gives the rooms.
+//! Each generation keeps the best individuals as they are (elitism) and fills the rest of the
+//! population with children of selected parents, mixed by crossover then mutated. Everything is
+//! driven by a single seed, so the same parameters always evolve the same level.
+
+pub mod fitness;
+pub mod flow;
+pub mod nsga;
+
+use std::thread;
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use crate::{
+    geometry::{
+        containment::{locate_point, Location},
+        counter_clockwise,
+    },
+    sampling::{gaussian, sample, Strategy},
+    voronoi::{power, Cells},
+};
+
+/// Seed points of a level, with the weight of each of them in the power diagram
+#[derive(Clone, Debug, PartialEq)]
+pub struct Genome {
+    pub points: Vec<Point>,
+    pub weights: Vec<f64>,
+}
+
+impl Genome {
+    pub fn new(points: Vec<Point>, weights: Vec<f64>) -> Self {
+        assert_eq!(points.len(), weights.len());
+
+        Self { points, weights }
+    }
+
+    /// Genome where all the points weigh the same, giving plain Voronoi cells
+    pub fn unweighted(points: Vec<Point>) -> Self {
+        let weights = vec![0.0; points.len()];
+
+        Self { points, weights }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Rooms of the genome: its power diagram inside `bounds`, one cell per point
+    pub fn cells(&self, bounds: &Polygon<Point>) -> Cells {
+        power::compute(&self.points, &self.weights, bounds)
+    }
+}
+
+/// Score of a level, the higher the better.
+///
+/// Individuals are evaluated on several threads at once, hence the `Sync` bound.
+pub trait Fitness: Sync {
+    fn fitness(&self, genome: &Genome, cells: &Cells) -> f64;
+}
+
+impl<F: Fn(&Genome, &Cells) -> f64 + Sync> Fitness for F {
+    fn fitness(&self, genome: &Genome, cells: &Cells) -> f64 {
+        self(genome, cells)
+    }
+}
+
+/// How parents are picked
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selection {
+    /// Best of `size` individuals taken at random
+    Tournament { size: usize },
+    /// Random individual, with a probability growing with its fitness
+    Roulette,
+}
+
+/// How two parents are mixed into a child
+#[derive(Clone, Debug, PartialEq)]
+pub enum Crossover {
+    /// Each point comes from either parent, the number of points stays the same
+    Uniform,
+    /// A random line cuts the level in two, and each side comes from a different parent.
+    ///
+    /// Rooms stay together with their neighbours, but the number of points can change.
+    Spatial,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Params {
+    pub population: usize,
+    pub generations: usize,
+    pub selection: Selection,
+    pub crossover: Crossover,
+    /// Probability for a child to have two parents rather than being a copy of one
+    pub crossover_rate: f64,
+    /// Probability for each point, and each weight, to mutate
+    pub mutation_rate: f64,
+    /// Standard deviation of the moves of mutated points
+    pub mutation_distance: f64,
+    /// Standard deviation of the changes of mutated weights
+    pub weight_mutation: f64,
+    /// Number of best individuals kept as they are in the next generation
+    pub elitism: usize,
+    pub seed: u64,
+    /// Threads evaluating the individuals, 0 for one per core.
+    ///
+    /// The results don't depend on it: a seed gives the same evolution on any number of threads.
+    pub threads: usize,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            population: 50,
+            generations: 100,
+            selection: Selection::Tournament { size: 3 },
+            crossover: Crossover::Spatial,
+            crossover_rate: 0.8,
+            mutation_rate: 0.1,
+            mutation_distance: 0.05,
+            weight_mutation: 0.01,
+            elitism: 2,
+            seed: 0,
+            threads: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Individual {
+    pub genome: Genome,
+    pub fitness: f64,
+}
+
+/// Fitness of a whole generation
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statistics {
+    pub generation: usize,
+    pub best: f64,
+    pub mean: f64,
+    pub worst: f64,
+    pub deviation: f64,
+}
+
+pub struct Evolution<F> {
+    params: Params,
+    bounds: Polygon<Point>,
+    fitness: F,
+    rng: SmallRng,
+    /// Current generation, the best individual first
+    population: Vec<Individual>,
+    statistics: Vec<Statistics>,
+}
+
+impl<F: Fitness> Evolution<F> {
+    /// Starts from the `initial` genomes, which make the first generation.
+    ///
+    /// `bounds` is expected to be convex, like for [`power::compute`].
+    pub fn new(params: Params, bounds: &Polygon<Point>, fitness: F, initial: Vec<Genome>) -> Self {
+        assert!(!initial.is_empty());
+        assert!(params.elitism <= params.population);
+
+        let mut evolution = Self {
+            rng: SmallRng::seed_from_u64(params.seed),
+            params,
+            bounds: Polygon::from_points(counter_clockwise(bounds.points())),
+            fitness,
+            population: Vec::new(),
+            statistics: Vec::new(),
+        };
+
+        evolution.population = evolution.evaluate(initial);
+        evolution.record();
+
+        evolution
+    }
+
+    /// Starts from a population of `rooms` unweighted points, sampled in `bounds` with `strategy`
+    pub fn random(
+        params: Params,
+        bounds: &Polygon<Point>,
+        fitness: F,
+        strategy: &Strategy,
+        rooms: usize,
+    ) -> Self {
+        let initial = random_genomes(&params, bounds, strategy, rooms);
+
+        Self::new(params, bounds, fitness, initial)
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// Current generation, the best individual first
+    pub fn population(&self) -> &[Individual] {
+        &self.population
+    }
+
+    /// Number of generations evolved so far
+    pub fn generation(&self) -> usize {
+        self.statistics.len() - 1
+    }
+
+    /// Statistics of every generation so far, the initial one included
+    pub fn statistics(&self) -> &[Statistics] {
+        &self.statistics
+    }
+
+    pub fn best(&self) -> &Individual {
+        &self.population[0]
+    }
+
+    /// Rooms of the best individual
+    pub fn best_cells(&self) -> Cells {
+        self.best().genome.cells(&self.bounds)
+    }
+
+    /// Evolves one more generation
+    pub fn step(&mut self) -> &Statistics {
+        let elites = self.params.elitism.min(self.population.len());
+
+        let children: Vec<Genome> = (elites..self.params.population)
+            .map(|_| {
+                let first = self.select();
+
+                let mut child = if self.rng.gen::<f64>() < self.params.crossover_rate {
+                    let second = self.select();
+                    self.crossover(first, second)
+                } else {
+                    self.population[first].genome.clone()
+                };
+
+                self.mutate(&mut child);
+                child
+            })
+            .collect();
+
+        let mut next = self.population[..elites].to_vec();
+        next.extend(self.evaluate(children));
+        next.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        self.population = next;
+
+        self.record();
+        self.statistics.last().unwrap()
+    }
+
+    /// Evolves until `params.generations` generations, and returns the best individual
+    pub fn run(&mut self) -> &Individual {
+        while self.generation() < self.params.generations {
+            self.step();
+        }
+
+        self.best()
+    }
+
+    /// Individuals of `genomes`, the best first
+    fn evaluate(&self, genomes: Vec<Genome>) -> Vec<Individual> {
+        let (bounds, fitness) = (&self.bounds, &self.fitness);
+
+        let mut individuals = evaluate_all(genomes, self.params.threads, |genome| {
+            let fitness = fitness.fitness(&genome, &genome.cells(bounds));
+            Individual { genome, fitness }
+        });
+
+        individuals.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        individuals
+    }
+
+    fn record(&mut self) {
+        let fitness: Vec<f64> = self.population.iter().map(|i| i.fitness).collect();
+        let mean = fitness.iter().sum::<f64>() / fitness.len() as f64;
+        let variance =
+            fitness.iter().map(|f| (f - mean).powi(2)).sum::<f64>() / fitness.len() as f64;
+
+        self.statistics.push(Statistics {
+            generation: self.statistics.len(),
+            best: fitness[0],
+            mean,
+            worst: fitness[fitness.len() - 1],
+            deviation: variance.sqrt(),
+        });
+    }
+
+    /// Index of a parent
+    fn select(&mut self) -> usize {
+        let count = self.population.len();
+
+        match self.params.selection {
+            // the population is sorted, so the lowest index is the fittest
+            Selection::Tournament { size } => (0..size.max(1))
+                .map(|_| self.rng.gen_range(0..count))
+                .min()
+                .unwrap(),
+            Selection::Roulette => {
+                // shifted so that the worst individual still has a small chance
+                let worst = self.population[count - 1].fitness;
+                let spread = (self.population[0].fitness - worst).max(f64::MIN_POSITIVE);
+                let share = |i: &Individual| (i.fitness - worst) / spread + 1.0 / count as f64;
+
+                let total: f64 = self.population.iter().map(share).sum();
+                let mut ticket = self.rng.gen::<f64>() * total;
+
+                for (i, individual) in self.population.iter().enumerate() {
+                    ticket -= share(individual);
+                    if ticket < 0.0 {
+                        return i;
+                    }
+                }
+
+                count - 1
+            }
+        }
+    }
+
+    fn crossover(&mut self, first: usize, second: usize) -> Genome {
+        crossover(
+            &mut self.rng,
+            &self.params.crossover,
+            &self.population[first].genome,
+            &self.population[second].genome,
+        )
+    }
+
+    fn mutate(&mut self, genome: &mut Genome) {
+        mutate(&mut self.rng, genome, &self.bounds, &self.params);
+    }
+}
+
+/// Applies `evaluate` to all the `genomes` on `threads` threads, 0 for one per core.
+///
+/// Each thread takes a contiguous share of the genomes, and the results come back in the order of
+/// the genomes, so they are the same whatever the number of threads.
+fn evaluate_all<T: Send>(
+    genomes: Vec<Genome>,
+    threads: usize,
+    evaluate: impl Fn(Genome) -> T + Sync,
+) -> Vec<T> {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .min(genomes.len());
+
+    if threads <= 1 {
+        return genomes.into_iter().map(evaluate).collect();
+    }
+
+    let share = genomes.len().div_ceil(threads);
+    let mut genomes = genomes.into_iter();
+    let chunks: Vec<Vec<Genome>> = (0..threads)
+        .map(|_| genomes.by_ref().take(share).collect())
+        .collect();
+
+    let evaluate = &evaluate;
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| scope.spawn(move || chunk.into_iter().map(evaluate).collect::<Vec<T>>()))
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("fitness evaluation panicked"))
+            .collect()
+    })
+}
+
+/// First generation of `params.population` genomes of `rooms` unweighted points
+fn random_genomes(
+    params: &Params,
+    bounds: &Polygon<Point>,
+    strategy: &Strategy,
+    rooms: usize,
+) -> Vec<Genome> {
+    // a different stream than the one driving the evolution
+    let mut rng = SmallRng::seed_from_u64(params.seed ^ 0x5eed);
+
+    (0..params.population)
+        .map(|_| Genome::unweighted(sample(rng.gen(), bounds, strategy, rooms)))
+        .collect()
+}
+
+fn crossover(rng: &mut SmallRng, crossover: &Crossover, a: &Genome, b: &Genome) -> Genome {
+    match crossover {
+        Crossover::Uniform => uniform_crossover(rng, a, b),
+        Crossover::Spatial => spatial_crossover(rng, a, b),
+    }
+}
+
+fn uniform_crossover(rng: &mut SmallRng, a: &Genome, b: &Genome) -> Genome {
+    let mut child = a.clone();
+
+    for i in 0..a.len().min(b.len()) {
+        if rng.gen() {
+            child.points[i] = b.points[i].clone();
+            child.weights[i] = b.weights[i];
+        }
+    }
+
+    child
+}
+
+fn spatial_crossover(rng: &mut SmallRng, a: &Genome, b: &Genome) -> Genome {
+    // the cut goes through one of the points of the first parent
+    let pivot = a.points[rng.gen_range(0..a.len())].clone();
+    let angle = rng.gen::<f64>() * std::f64::consts::PI;
+    let side = |p: &Point| (p.x - pivot.x) * angle.sin() - (p.y - pivot.y) * angle.cos() >= 0.0;
+
+    let mut child = Genome::new(Vec::new(), Vec::new());
+    for (p, w) in a.points.iter().zip(&a.weights).filter(|(p, _)| side(p)) {
+        child.points.push(p.clone());
+        child.weights.push(*w);
+    }
+    for (p, w) in b.points.iter().zip(&b.weights).filter(|(p, _)| !side(p)) {
+        child.points.push(p.clone());
+        child.weights.push(*w);
+    }
+
+    // not enough points to make a level
+    if child.len() < 3 {
+        return a.clone();
+    }
+
+    child
+}
+
+fn mutate(rng: &mut SmallRng, genome: &mut Genome, bounds: &Polygon<Point>, params: &Params) {
+    for p in genome.points.iter_mut() {
+        if rng.gen::<f64>() < params.mutation_rate {
+            let (dx, dy) = gaussian(rng);
+            let moved = Point {
+                x: p.x + params.mutation_distance * dx,
+                y: p.y + params.mutation_distance * dy,
+            };
+
+            // points going out of the level stay where they are
+            if locate_point(&moved, bounds) != Location::Outside {
+                *p = moved;
+            }
+        }
+    }
+
+    for w in genome.weights.iter_mut() {
+        if rng.gen::<f64>() < params.mutation_rate {
+            *w = (*w + params.weight_mutation * gaussian(rng).0).max(0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{geometry::signed_area, test_util::square};
+
+    use fitness::EvenAreas;
+
+    fn params(seed: u64) -> Params {
+        Params {
+            population: 20,
+            generations: 15,
+            seed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn evolution_is_deterministic() {
+        let run = |seed| {
+            let mut evolution = Evolution::random(
+                params(seed),
+                &square(1.0),
+                EvenAreas,
+                &Strategy::Uniform,
+                15,
+            );
+            evolution.run();
+            (evolution.best().clone(), evolution.statistics().to_vec())
+        };
+
+        assert_eq!(run(3), run(3));
+        assert_ne!(run(3), run(4));
+    }
+
+    #[test]
+    fn threads_give_the_same_evolution() {
+        let run = |threads| {
+            let params = Params {
+                threads,
+                ..params(6)
+            };
+            let mut evolution =
+                Evolution::random(params, &square(1.0), EvenAreas, &Strategy::Uniform, 15);
+            evolution.run();
+            (
+                evolution.population().to_vec(),
+                evolution.statistics().to_vec(),
+            )
+        };
+
+        let serial = run(1);
+        for threads in [0, 2, 3, 7, 64] {
+            assert_eq!(run(threads), serial);
+        }
+    }
+
+    #[test]
+    fn evaluation_keeps_the_order() {
+        let genomes: Vec<Genome> = (1..=10)
+            .map(|n| Genome::unweighted(crate::random_points(n)))
+            .collect();
+
+        for threads in [1, 3, 4, 20] {
+            assert_eq!(
+                evaluate_all(genomes.clone(), threads, |g| g.len()),
+                (1..=10).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn elites_are_never_lost() {
+        let mut evolution =
+            Evolution::random(params(1), &square(1.0), EvenAreas, &Strategy::Uniform, 15);
+        evolution.run();
+
+        assert_eq!(evolution.generation(), 15);
+        assert_eq!(evolution.statistics().len(), 16);
+        assert_eq!(evolution.population().len(), 20);
+
+        for pair in evolution.statistics().windows(2) {
+            assert!(pair[1].best >= pair[0].best);
+        }
+        for s in evolution.statistics() {
+            assert!(s.worst <= s.mean && s.mean <= s.best);
+        }
+    }
+
+    #[test]
+    fn selections_improve_the_level() {
+        for selection in [Selection::Tournament { size: 3 }, Selection::Roulette] {
+            for crossover in [Crossover::Uniform, Crossover::Spatial] {
+                let params = Params {
+                    selection: selection.clone(),
+                    crossover: crossover.clone(),
+                    generations: 30,
+                    ..params(7)
+                };
+
+                let mut evolution =
+                    Evolution::random(params, &square(1.0), EvenAreas, &Strategy::Uniform, 15);
+                evolution.run();
+
+                let statistics = evolution.statistics();
+                assert!(statistics[30].best > statistics[0].best);
+                assert!(statistics[30].mean > statistics[0].mean);
+            }
+        }
+    }
+
+    #[test]
+    fn best_cells_cover_the_level() {
+        let mut evolution =
+            Evolution::random(params(2), &square(1.0), EvenAreas, &Strategy::Uniform, 15);
+        evolution.run();
+
+        let cells = evolution.best_cells();
+        assert_eq!(cells.len(), evolution.best().genome.len());
+
+        let total: f64 = cells.iter_cells().map(|c| signed_area(c.points())).sum();
+        assert!((total - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn closures_as_fitness() {
+        // points pulled towards the center
+        let centered = |genome: &Genome, _: &Cells| {
+            -genome
+                .points
+                .iter()
+                .map(|p| p.x.abs() + p.y.abs())
+                .sum::<f64>()
+        };
+
+        let params = Params {
+            crossover: Crossover::Uniform,
+            mutation_rate: 0.3,
+            ..params(5)
+        };
+        let mut evolution =
+            Evolution::random(params, &square(1.0), centered, &Strategy::Uniform, 10);
+        let before = evolution.best().fitness;
+
+        assert!(evolution.run().fitness > before);
+    }
+
+    #[test]
+    fn crossovers_mix_parents() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let a = Genome::unweighted(crate::random_points(20));
+        let b = Genome::new(
+            sample(1, &square(1.0), &Strategy::Uniform, 20),
+            vec![0.1; 20],
+        );
+
+        for _ in 0..20 {
+            let child = uniform_crossover(&mut rng, &a, &b);
+            assert_eq!(child.len(), 20);
+            for i in 0..20 {
+                assert!(
+                    (child.points[i] == a.points[i] && child.weights[i] == 0.0)
+                        || (child.points[i] == b.points[i] && child.weights[i] == 0.1)
+                );
+            }
+
+            let child = spatial_crossover(&mut rng, &a, &b);
+            assert!(child.len() >= 3);
+            for (p, w) in child.points.iter().zip(&child.weights) {
+                assert!((a.points.contains(p) && *w == 0.0) || (b.points.contains(p) && *w == 0.1));
+            }
+        }
+    }
+
+    #[test]
+    fn mutations_stay_in_bounds() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let bounds = square(1.0);
+        let params = Params {
+            mutation_rate: 1.0,
+            mutation_distance: 0.5,
+            weight_mutation: 0.1,
+            ..Default::default()
+        };
+
+        let mut genome = Genome::unweighted(crate::random_points(30));
+        let original = genome.clone();
+
+        for _ in 0..20 {
+            mutate(&mut rng, &mut genome, &bounds, &params);
+
+            for p in &genome.points {
+                assert_ne!(locate_point(p, &bounds), Location::Outside);
+            }
+            assert!(genome.weights.iter().all(|&w| w >= 0.0));
+        }
+
+        assert_ne!(genome, original);
+    }
+}
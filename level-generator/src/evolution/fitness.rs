@@ -0,0 +1,228 @@
+//! Ready-made fitness functions.
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use voronator::delaunator::Point;
+
+use super::{Fitness, Genome};
+use crate::{
+    geometry::{distance, signed_area},
+    voronoi::{
+        alpha::{alpha_shape, connected_alpha},
+        Cells,
+    },
+};
+
+/// Prefers rooms of the same size: minus the coefficient of variation of the room areas.
+///
+/// Empty rooms count as rooms of area 0, so overshadowed points are penalised.
+pub struct EvenAreas;
+
+impl Fitness for EvenAreas {
+    fn fitness(&self, _: &Genome, cells: &Cells) -> f64 {
+        let areas: Vec<f64> = cells
+            .iter_cells()
+            .map(|c| signed_area(c.points()).abs())
+            .collect();
+
+        if areas.is_empty() {
+            return f64::NEG_INFINITY;
+        }
+
+        let mean = areas.iter().sum::<f64>() / areas.len() as f64;
+        let variance = areas.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / areas.len() as f64;
+
+        -variance.sqrt() / mean
+    }
+}
+
+/// Number of independent loops in the room graph, the more the less linear the level
+pub struct Loops;
+
+impl Fitness for Loops {
+    fn fitness(&self, _: &Genome, cells: &Cells) -> f64 {
+        let graph = cells.room_graph();
+        let links: usize = (0..graph.room_count())
+            .map(|room| graph.neighbours(room).len())
+            .sum::<usize>()
+            / 2;
+
+        // cycle rank of the graph
+        (links + graph.connected_components().len()) as f64 - graph.room_count() as f64
+    }
+}
+
+/// Number of rooms on the longest of the shortest paths between two rooms
+pub struct CriticalPath;
+
+impl Fitness for CriticalPath {
+    fn fitness(&self, _: &Genome, cells: &Cells) -> f64 {
+        let graph = cells.room_graph();
+        let mut longest = 0;
+
+        for start in 0..graph.room_count() {
+            let mut rooms = vec![None; graph.room_count()];
+            let mut queue = VecDeque::from([start]);
+            rooms[start] = Some(1);
+
+            while let Some(room) = queue.pop_front() {
+                let depth = rooms[room].unwrap();
+                longest = longest.max(depth);
+
+                for next in graph.neighbours(room) {
+                    if rooms[next].is_none() {
+                        rooms[next] = Some(depth + 1);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        longest as f64
+    }
+}
+
+/// Compactness of the outline of the level, from 0 for sprawling levels to 1 for a disc.
+///
+/// The outline is the tightest alpha shape of the sites that keeps the level in one piece, as
+/// the cells themselves always fill the whole bounds. Its compactness is
+/// `4π area / perimeter²`, the holes counting in the perimeter.
+pub struct Compactness;
+
+impl Fitness for Compactness {
+    fn fitness(&self, genome: &Genome, _: &Cells) -> f64 {
+        // alpha shapes need a triangulation
+        if genome.len() < 4 {
+            return 0.0;
+        }
+
+        let shape = alpha_shape(&genome.points, connected_alpha(&genome.points));
+        let perimeter: f64 = shape
+            .components()
+            .iter()
+            .flat_map(|c| std::iter::once(&c.outer).chain(&c.holes))
+            .map(|ring| perimeter(ring.points()))
+            .sum();
+
+        compactness(shape.area(), perimeter)
+    }
+}
+
+/// Mean compactness of each of the rooms, from 0 for slivers to 1 for discs.
+///
+/// The compactness of a room is `4π area / perimeter²`; empty rooms count as 0.
+pub struct RoomCompactness;
+
+impl Fitness for RoomCompactness {
+    fn fitness(&self, _: &Genome, cells: &Cells) -> f64 {
+        if cells.is_empty() {
+            return 0.0;
+        }
+
+        let total: f64 = cells
+            .iter_cells()
+            .map(|cell| compactness(signed_area(cell.points()).abs(), perimeter(cell.points())))
+            .sum();
+
+        total / cells.len() as f64
+    }
+}
+
+fn perimeter(points: &[Point]) -> f64 {
+    (0..points.len())
+        .map(|i| distance(&points[i], &points[(i + 1) % points.len()]))
+        .sum()
+}
+
+fn compactness(area: f64, perimeter: f64) -> f64 {
+    if perimeter > 0.0 {
+        4.0 * PI * area / perimeter.powi(2)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::test_util::{p, square};
+
+    #[test]
+    fn even_areas() {
+        let even = Genome::unweighted(vec![p(-0.5, -0.5), p(0.5, -0.5), p(0.5, 0.5), p(-0.5, 0.5)]);
+        let uneven =
+            Genome::unweighted(vec![p(-0.9, -0.9), p(-0.8, -0.9), p(0.5, 0.5), p(0.0, 0.9)]);
+
+        let score = |g: &Genome| EvenAreas.fitness(g, &g.cells(&square(1.0)));
+
+        assert!(score(&even).abs() < 1e-12);
+        assert!(score(&uneven) < -0.1);
+    }
+
+    /// 3x3 grid of unit rooms
+    fn grid() -> (Genome, Cells) {
+        let genome = Genome::unweighted(
+            (0..9)
+                .map(|i| p((i % 3) as f64 - 1.0, (i / 3) as f64 - 1.0))
+                .collect(),
+        );
+        let cells = genome.cells(&square(1.5));
+
+        (genome, cells)
+    }
+
+    #[test]
+    fn graph_shape() {
+        let (genome, cells) = grid();
+
+        // 12 doors between 9 rooms
+        assert_eq!(Loops.fitness(&genome, &cells), 4.0);
+        // from one corner to the opposite one
+        assert_eq!(CriticalPath.fitness(&genome, &cells), 5.0);
+
+        let row = Genome::unweighted(vec![p(-1.0, 0.0), p(0.0, 0.0), p(1.0, 0.0)]);
+        let cells = row.cells(&square(1.5));
+        assert_eq!(Loops.fitness(&row, &cells), 0.0);
+        assert_eq!(CriticalPath.fitness(&row, &cells), 3.0);
+    }
+
+    #[test]
+    fn room_compactness() {
+        let (genome, cells) = grid();
+
+        // squares
+        assert!((RoomCompactness.fitness(&genome, &cells) - PI / 4.0).abs() < 1e-12);
+
+        // 1x3 strips are thinner
+        let row = Genome::unweighted(vec![p(-1.0, 0.0), p(0.0, 0.0), p(1.0, 0.0)]);
+        assert!(RoomCompactness.fitness(&row, &row.cells(&square(1.5))) < PI / 4.0);
+    }
+
+    #[test]
+    fn boundary_compactness() {
+        // sites in a disc against sites in a thin band, in the same bounds
+        let disc = Genome::unweighted(
+            (0..30)
+                .map(|i| {
+                    let (angle, radius) = (i as f64 * 2.4, (i as f64 / 30.0).sqrt() * 0.9);
+                    p(radius * angle.cos(), radius * angle.sin())
+                })
+                .collect(),
+        );
+        let band = Genome::unweighted(
+            (0..30)
+                .map(|i| p(i as f64 / 15.0 - 1.0, (i % 3) as f64 * 0.05))
+                .collect(),
+        );
+
+        let score = |g: &Genome| Compactness.fitness(g, &g.cells(&square(1.0)));
+
+        // the tightest outline is ragged, even around a disc
+        assert!(score(&disc) > 2.0 * score(&band));
+        assert!(score(&disc) < 1.0);
+        assert!(score(&band) > 0.0);
+    }
+}
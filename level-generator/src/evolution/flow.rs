@@ -0,0 +1,285 @@
+//! Traffic simulation, scoring levels by the way people would walk through them.
+//!
+//! Agents travel between designated rooms, each of them along its shortest path through the doors,
+//! like the occupants of Joel Simon's evolved floorplans. Their paths tell how crowded the rooms
+//! in between get, how long the trips are, and how many side rooms they walk by.
+
+use voronator::delaunator::Point;
+
+use super::{Fitness, Genome};
+use crate::{
+    geometry::{
+        containment::{locate_point, Location},
+        distance,
+    },
+    graph::{RoomGraph, Wall},
+    voronoi::Cells,
+};
+
+/// What the agents went through
+#[derive(Clone, Debug, PartialEq)]
+pub struct Traffic {
+    pub agents: usize,
+    /// Agents who found a way to their destination
+    pub arrived: usize,
+    /// Largest share of the arrived agents crossing the same room on their way, from 0 to 1.
+    ///
+    /// The rooms where the agents start and end are not counted.
+    pub congestion: f64,
+    /// Mean length of the paths of the arrived agents
+    pub average_path: f64,
+    /// Share of the rooms walked through which open onto a dead end off the path, from 0 to 1
+    pub dead_end_exposure: f64,
+}
+
+/// Sends one agent along each of the `routes`, going through the walls accepted by `door`
+pub fn simulate(
+    graph: &RoomGraph,
+    routes: &[(usize, usize)],
+    door: impl Fn(&Wall) -> bool,
+) -> Traffic {
+    let dead_end: Vec<bool> = (0..graph.room_count())
+        .map(|room| {
+            let mut doors = graph
+                .room_walls(room)
+                .filter(|w| door(w))
+                .map(|w| w.other(room));
+            doors
+                .next()
+                .is_some_and(|first| doors.all(|other| other == first))
+        })
+        .collect();
+
+    let mut load = vec![0; graph.room_count()];
+    let mut arrived = 0;
+    let mut length = 0.0;
+    let (mut visits, mut exposed) = (0, 0);
+
+    for &(from, to) in routes {
+        let Some(path) = graph.shortest_path_through(from, to, &door) else {
+            continue;
+        };
+
+        arrived += 1;
+
+        for pair in path.windows(2) {
+            length += step(graph, pair[0], pair[1], &door);
+        }
+
+        if path.len() > 2 {
+            path[1..path.len() - 1]
+                .iter()
+                .for_each(|&room| load[room] += 1);
+        }
+
+        for &room in &path {
+            visits += 1;
+
+            let opens_onto_dead_end = graph
+                .room_walls(room)
+                .filter(|w| door(w))
+                .map(|w| w.other(room))
+                .any(|other| dead_end[other] && !path.contains(&other));
+
+            if opens_onto_dead_end {
+                exposed += 1;
+            }
+        }
+    }
+
+    let share = |count: usize, total: usize| {
+        if total == 0 {
+            0.0
+        } else {
+            count as f64 / total as f64
+        }
+    };
+
+    Traffic {
+        agents: routes.len(),
+        arrived,
+        congestion: share(load.into_iter().max().unwrap_or(0), arrived),
+        average_path: if arrived == 0 {
+            0.0
+        } else {
+            length / arrived as f64
+        },
+        dead_end_exposure: share(exposed, visits),
+    }
+}
+
+/// Cost of going from `room` to its neighbour `next` through the cheapest door between them
+fn step(graph: &RoomGraph, room: usize, next: usize, door: impl Fn(&Wall) -> bool) -> f64 {
+    let centers = graph.centers();
+
+    graph
+        .room_walls(room)
+        .filter(|w| w.other(room) == next && door(w))
+        .map(|w| {
+            let middle = w.middle();
+            distance(&centers[room], &middle) + distance(&middle, &centers[next])
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Fitness from the traffic between the rooms holding the `targets`.
+///
+/// One agent goes between each pair of targets, and only walls at least `min_door` long let them
+/// through. The fitness is the sum of the traffic figures times their factor, minus `stranded`
+/// times the share of agents who could not arrive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Flow {
+    pub targets: Vec<Point>,
+    pub min_door: f64,
+    pub congestion: f64,
+    pub path_length: f64,
+    pub dead_ends: f64,
+    pub stranded: f64,
+}
+
+impl Flow {
+    /// Long and fluid routes, with a few side rooms along the way
+    pub fn new(targets: Vec<Point>) -> Self {
+        Self {
+            targets,
+            min_door: 0.0,
+            congestion: -1.0,
+            path_length: 1.0,
+            dead_ends: 0.5,
+            stranded: 10.0,
+        }
+    }
+
+    pub fn traffic(&self, cells: &Cells) -> Traffic {
+        let rooms: Vec<Option<usize>> = self
+            .targets
+            .iter()
+            .map(|target| {
+                cells
+                    .iter_cells()
+                    .position(|cell| locate_point(target, cell) != Location::Outside)
+            })
+            .collect();
+
+        let mut routes = Vec::new();
+        let mut lost = 0;
+        for i in 0..rooms.len() {
+            for j in i + 1..rooms.len() {
+                match (rooms[i], rooms[j]) {
+                    (Some(a), Some(b)) => routes.push((a, b)),
+                    _ => lost += 1,
+                }
+            }
+        }
+
+        let mut traffic = simulate(&cells.room_graph(), &routes, |w| {
+            w.length() >= self.min_door
+        });
+        // targets out of every room strand their agents too
+        traffic.agents += lost;
+
+        traffic
+    }
+}
+
+impl Fitness for Flow {
+    fn fitness(&self, _: &Genome, cells: &Cells) -> f64 {
+        let traffic = self.traffic(cells);
+
+        if traffic.agents == 0 {
+            return 0.0;
+        }
+
+        let stranded = 1.0 - traffic.arrived as f64 / traffic.agents as f64;
+
+        self.congestion * traffic.congestion
+            + self.path_length * traffic.average_path
+            + self.dead_ends * traffic.dead_end_exposure
+            - self.stranded * stranded
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::test_util::{p, square};
+
+    /// 3x3 grid of unit rooms, room `3 * row + column` centered on `(column - 1, row - 1)`
+    fn grid() -> Cells {
+        let points = (0..9)
+            .map(|i| p((i % 3) as f64 - 1.0, (i / 3) as f64 - 1.0))
+            .collect();
+
+        Genome::unweighted(points).cells(&square(1.5))
+    }
+
+    #[test]
+    fn corridor() {
+        let graph = grid().room_graph();
+
+        let traffic = simulate(&graph, &[(0, 2), (2, 0), (0, 1)], |_| true);
+
+        assert_eq!(traffic.agents, 3);
+        assert_eq!(traffic.arrived, 3);
+        assert!((traffic.average_path - 5.0 / 3.0).abs() < 1e-9);
+        // two of the three agents cross the middle room
+        assert!((traffic.congestion - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(traffic.dead_end_exposure, 0.0);
+    }
+
+    #[test]
+    fn dead_ends() {
+        let graph = grid().room_graph();
+
+        // the center only opens onto the room below it
+        let door = |w: &Wall| w.rooms.1 != 4 && w.rooms.0 != 4 || w.rooms == (1, 4);
+        let traffic = simulate(&graph, &[(0, 2)], door);
+
+        assert_eq!(traffic.arrived, 1);
+        assert!((traffic.average_path - 2.0).abs() < 1e-9);
+        assert!((traffic.dead_end_exposure - 1.0 / 3.0).abs() < 1e-9);
+
+        // going to the dead end doesn't walk by it
+        let traffic = simulate(&graph, &[(0, 4)], door);
+        assert_eq!(traffic.dead_end_exposure, 0.0);
+    }
+
+    #[test]
+    fn closed_doors() {
+        let graph = grid().room_graph();
+
+        let traffic = simulate(&graph, &[(0, 8), (0, 0)], |_| false);
+
+        assert_eq!(traffic.agents, 2);
+        assert_eq!(traffic.arrived, 1);
+        assert_eq!(traffic.average_path, 0.0);
+        assert_eq!(traffic.congestion, 0.0);
+    }
+
+    #[test]
+    fn flow_fitness() {
+        let cells = grid();
+        let flow = Flow::new(vec![p(-1.0, -1.0), p(1.0, 1.0), p(1.0, -1.0)]);
+
+        let traffic = flow.traffic(&cells);
+        assert_eq!(traffic.agents, 3);
+        assert_eq!(traffic.arrived, 3);
+
+        let genome = Genome::unweighted(Vec::new());
+        assert!(flow.fitness(&genome, &cells) > 0.0);
+
+        // walls are too short for any door
+        let closed = Flow {
+            min_door: 2.0,
+            ..flow.clone()
+        };
+        assert!(closed.fitness(&genome, &cells) < -9.0);
+
+        // a target out of the level can't be reached
+        let lost = Flow::new(vec![p(-1.0, -1.0), p(5.0, 5.0)]);
+        assert_eq!(lost.traffic(&cells).arrived, 0);
+        assert_eq!(lost.traffic(&cells).agents, 1);
+    }
+}
@@ -0,0 +1,429 @@
+//! Multi-objective evolution with NSGA-II.
+//!
+//! Instead of summing several criteria into one fitness, each individual gets one score per
+//! objective, and the evolution keeps the individuals no other one beats on every objective: the
+//! Pareto front. Among them, the ones far from the others are preferred, so the front spreads over
+//! all the trade-offs between the objectives.
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use super::{crossover, evaluate_all, mutate, random_genomes, Fitness, Genome, Params};
+use crate::{geometry::counter_clockwise, sampling::Strategy, voronoi::Cells};
+
+/// Scores of a level, the higher the better for each of them.
+///
+/// Like [`Fitness`], objectives are evaluated on several threads at once.
+pub trait Objectives: Sync {
+    fn objectives(&self, genome: &Genome, cells: &Cells) -> Vec<f64>;
+}
+
+impl<F: Fn(&Genome, &Cells) -> Vec<f64> + Sync> Objectives for F {
+    fn objectives(&self, genome: &Genome, cells: &Cells) -> Vec<f64> {
+        self(genome, cells)
+    }
+}
+
+/// One objective per fitness function
+impl Objectives for Vec<Box<dyn Fitness>> {
+    fn objectives(&self, genome: &Genome, cells: &Cells) -> Vec<f64> {
+        self.iter().map(|f| f.fitness(genome, cells)).collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub genome: Genome,
+    pub objectives: Vec<f64>,
+    /// Index of the front the candidate belongs to, 0 being the Pareto front
+    pub rank: usize,
+    /// Distance to the neighbours of the candidate in its front, infinite on the edges
+    pub crowding: f64,
+}
+
+/// Scores of `a` are all at least the ones of `b`, and one of them is higher
+pub fn dominates(a: &[f64], b: &[f64]) -> bool {
+    a.iter().zip(b).all(|(x, y)| x >= y) && a.iter().zip(b).any(|(x, y)| x > y)
+}
+
+/// Sorts `scores` into fronts: the first one is the indices of the non-dominated scores, the next
+/// one the non-dominated scores once the first front is removed, and so on.
+pub fn non_dominated_fronts(scores: &[Vec<f64>]) -> Vec<Vec<usize>> {
+    let mut dominated: Vec<Vec<usize>> = vec![Vec::new(); scores.len()];
+    let mut dominating = vec![0; scores.len()];
+
+    for i in 0..scores.len() {
+        for j in i + 1..scores.len() {
+            if dominates(&scores[i], &scores[j]) {
+                dominated[i].push(j);
+                dominating[j] += 1;
+            } else if dominates(&scores[j], &scores[i]) {
+                dominated[j].push(i);
+                dominating[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts = Vec::new();
+    let mut front: Vec<usize> = (0..scores.len()).filter(|&i| dominating[i] == 0).collect();
+
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for &i in &front {
+            for &j in &dominated[i] {
+                dominating[j] -= 1;
+                if dominating[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+
+        next.sort_unstable();
+        fronts.push(front);
+        front = next;
+    }
+
+    fronts
+}
+
+/// Crowding distance of each member of `front`, the indices of some `scores`
+pub fn crowding_distances(scores: &[Vec<f64>], front: &[usize]) -> Vec<f64> {
+    let mut crowding = vec![0.0; front.len()];
+    let objectives = front.first().map_or(0, |&i| scores[i].len());
+
+    let objective = |o: usize| front.iter().map(move |&i| scores[i][o]);
+
+    for values in (0..objectives).map(|o| objective(o).collect::<Vec<f64>>()) {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+
+        let (first, last) = (order[0], order[order.len() - 1]);
+        let range = values[last] - values[first];
+
+        crowding[first] = f64::INFINITY;
+        crowding[last] = f64::INFINITY;
+
+        if range > 0.0 {
+            for k in 1..order.len().saturating_sub(1) {
+                crowding[order[k]] += (values[order[k + 1]] - values[order[k - 1]]) / range;
+            }
+        }
+    }
+
+    crowding
+}
+
+/// Evolution towards the Pareto front of several objectives.
+///
+/// It takes the same parameters as [`super::Evolution`], except that parents are always picked
+/// by binary tournament on rank then crowding, and elitism comes from keeping the best fronts.
+pub struct ParetoEvolution<O> {
+    params: Params,
+    bounds: Polygon<Point>,
+    objectives: O,
+    rng: SmallRng,
+    /// Current generation, sorted by rank then crowding
+    population: Vec<Candidate>,
+    generation: usize,
+}
+
+impl<O: Objectives> ParetoEvolution<O> {
+    /// Starts from the `initial` genomes, which make the first generation.
+    ///
+    /// `bounds` is expected to be convex, like for [`crate::voronoi::power::compute`].
+    pub fn new(
+        params: Params,
+        bounds: &Polygon<Point>,
+        objectives: O,
+        initial: Vec<Genome>,
+    ) -> Self {
+        assert!(!initial.is_empty());
+
+        let mut evolution = Self {
+            rng: SmallRng::seed_from_u64(params.seed),
+            params,
+            bounds: Polygon::from_points(counter_clockwise(bounds.points())),
+            objectives,
+            population: Vec::new(),
+            generation: 0,
+        };
+
+        let size = initial.len();
+        let candidates = evolution.evaluate(initial);
+        evolution.population = survivors(candidates, size);
+
+        evolution
+    }
+
+    /// Starts from a population of `rooms` unweighted points, sampled in `bounds` with `strategy`
+    pub fn random(
+        params: Params,
+        bounds: &Polygon<Point>,
+        objectives: O,
+        strategy: &Strategy,
+        rooms: usize,
+    ) -> Self {
+        let initial = random_genomes(&params, bounds, strategy, rooms);
+
+        Self::new(params, bounds, objectives, initial)
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// Current generation, sorted by rank then crowding
+    pub fn population(&self) -> &[Candidate] {
+        &self.population
+    }
+
+    /// Number of generations evolved so far
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Non-dominated candidates of the current generation
+    pub fn front(&self) -> impl Iterator<Item = &Candidate> + '_ {
+        self.population.iter().take_while(|c| c.rank == 0)
+    }
+
+    /// Rooms of `genome` in the bounds of the evolution
+    pub fn cells(&self, genome: &Genome) -> Cells {
+        genome.cells(&self.bounds)
+    }
+
+    /// Evolves one more generation
+    pub fn step(&mut self) {
+        let size = self.population.len();
+
+        let children: Vec<Genome> = (0..size)
+            .map(|_| {
+                let first = self.select();
+
+                let mut child = if self.rng.gen::<f64>() < self.params.crossover_rate {
+                    let second = self.select();
+                    crossover(
+                        &mut self.rng,
+                        &self.params.crossover,
+                        &self.population[first].genome,
+                        &self.population[second].genome,
+                    )
+                } else {
+                    self.population[first].genome.clone()
+                };
+
+                mutate(&mut self.rng, &mut child, &self.bounds, &self.params);
+                child
+            })
+            .collect();
+
+        let mut candidates = std::mem::take(&mut self.population);
+        candidates.extend(self.evaluate(children));
+
+        self.population = survivors(candidates, size);
+        self.generation += 1;
+    }
+
+    /// Evolves until `params.generations` generations, and returns the Pareto front
+    pub fn run(&mut self) -> Vec<&Candidate> {
+        while self.generation < self.params.generations {
+            self.step();
+        }
+
+        self.front().collect()
+    }
+
+    fn evaluate(&self, genomes: Vec<Genome>) -> Vec<Candidate> {
+        let (bounds, objectives) = (&self.bounds, &self.objectives);
+
+        evaluate_all(genomes, self.params.threads, |genome| Candidate {
+            objectives: objectives.objectives(&genome, &genome.cells(bounds)),
+            genome,
+            rank: 0,
+            crowding: 0.0,
+        })
+    }
+
+    /// Index of a parent, the better of two candidates taken at random
+    fn select(&mut self) -> usize {
+        // the population is sorted, so the lowest index is the better one
+        let a = self.rng.gen_range(0..self.population.len());
+        let b = self.rng.gen_range(0..self.population.len());
+
+        a.min(b)
+    }
+}
+
+/// The `size` best `candidates`, sorted by rank then crowding
+fn survivors(candidates: Vec<Candidate>, size: usize) -> Vec<Candidate> {
+    let scores: Vec<Vec<f64>> = candidates.iter().map(|c| c.objectives.clone()).collect();
+    let mut candidates: Vec<Option<Candidate>> = candidates.into_iter().map(Some).collect();
+    let mut survivors = Vec::with_capacity(size);
+
+    for (rank, front) in non_dominated_fronts(&scores).into_iter().enumerate() {
+        if survivors.len() >= size {
+            break;
+        }
+
+        let crowding = crowding_distances(&scores, &front);
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        // the stable sort keeps ties in the order of the candidates
+        order.sort_by(|&a, &b| crowding[b].total_cmp(&crowding[a]));
+
+        for k in order.into_iter().take(size - survivors.len()) {
+            let mut candidate = candidates[front[k]].take().unwrap();
+            candidate.rank = rank;
+            candidate.crowding = crowding[k];
+            survivors.push(candidate);
+        }
+    }
+
+    survivors
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        evolution::fitness::{Compactness, EvenAreas, Loops},
+        test_util::square,
+    };
+
+    fn params(seed: u64) -> Params {
+        Params {
+            population: 20,
+            generations: 10,
+            seed,
+            ..Default::default()
+        }
+    }
+
+    fn mean_x(genome: &Genome) -> f64 {
+        genome.points.iter().map(|p| p.x).sum::<f64>() / genome.len() as f64
+    }
+
+    /// Points around x = 0 against points around x = 0.5
+    fn sides(genome: &Genome, _: &Cells) -> Vec<f64> {
+        let x = mean_x(genome);
+        vec![-x.powi(2), -(x - 0.5).powi(2)]
+    }
+
+    #[test]
+    fn domination() {
+        assert!(dominates(&[1.0, 2.0], &[1.0, 1.0]));
+        assert!(!dominates(&[1.0, 1.0], &[1.0, 1.0]));
+        assert!(!dominates(&[2.0, 0.0], &[1.0, 1.0]));
+    }
+
+    #[test]
+    fn fronts() {
+        let scores = vec![
+            vec![1.0, 1.0],
+            vec![3.0, 0.0],
+            vec![0.0, 3.0],
+            vec![2.0, 2.0],
+            vec![0.0, 0.0],
+            vec![1.0, 1.0],
+        ];
+
+        assert_eq!(
+            non_dominated_fronts(&scores),
+            vec![vec![1, 2, 3], vec![0, 5], vec![4]]
+        );
+
+        let crowding = crowding_distances(&scores, &[1, 2, 3]);
+        assert_eq!(crowding[0], f64::INFINITY);
+        assert_eq!(crowding[1], f64::INFINITY);
+        assert!((crowding[2] - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn front_is_non_dominated() {
+        let objectives: Vec<Box<dyn Fitness>> =
+            vec![Box::new(EvenAreas), Box::new(Loops), Box::new(Compactness)];
+        let mut evolution =
+            ParetoEvolution::random(params(1), &square(1.0), objectives, &Strategy::Uniform, 12);
+
+        let front: Vec<Candidate> = evolution.run().into_iter().cloned().collect();
+        assert!(!front.is_empty());
+        assert_eq!(evolution.population().len(), 20);
+
+        for a in &front {
+            assert_eq!(a.objectives.len(), 3);
+            for c in evolution.population() {
+                assert!(!dominates(&c.objectives, &a.objectives));
+            }
+        }
+
+        for pair in evolution.population().windows(2) {
+            assert!(pair[0].rank <= pair[1].rank);
+        }
+    }
+
+    #[test]
+    fn evolution_is_deterministic() {
+        let run = |seed| {
+            let mut evolution =
+                ParetoEvolution::random(params(seed), &square(1.0), sides, &Strategy::Uniform, 10);
+            evolution.run();
+            evolution.population().to_vec()
+        };
+
+        assert_eq!(run(2), run(2));
+        assert_ne!(run(2), run(3));
+
+        let mut parallel = ParetoEvolution::random(
+            Params {
+                threads: 3,
+                ..params(2)
+            },
+            &square(1.0),
+            sides,
+            &Strategy::Uniform,
+            10,
+        );
+        parallel.run();
+        assert_eq!(parallel.population(), run(2));
+    }
+
+    #[test]
+    fn front_spreads_over_trade_offs() {
+        let mut evolution = ParetoEvolution::random(
+            Params {
+                generations: 30,
+                ..params(4)
+            },
+            &square(1.0),
+            sides,
+            &Strategy::Uniform,
+            10,
+        );
+
+        let best = |e: &ParetoEvolution<_>, objective: usize| {
+            e.population()
+                .iter()
+                .map(|c: &Candidate| c.objectives[objective])
+                .fold(f64::NEG_INFINITY, f64::max)
+        };
+        let (mut right, mut left) = (best(&evolution, 0), best(&evolution, 1));
+
+        for _ in 0..30 {
+            evolution.step();
+
+            // the edges of the front are always kept
+            assert!(best(&evolution, 0) >= right);
+            assert!(best(&evolution, 1) >= left);
+            (right, left) = (best(&evolution, 0), best(&evolution, 1));
+        }
+
+        let front: Vec<&Candidate> = evolution.front().collect();
+        assert!(front.len() > 2);
+        assert!(front.iter().any(|c| mean_x(&c.genome) < 0.1));
+        assert!(front.iter().any(|c| mean_x(&c.genome) > 0.4));
+
+        let cells = evolution.cells(&front[0].genome);
+        assert_eq!(cells.len(), front[0].genome.len());
+    }
+}
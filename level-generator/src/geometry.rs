@@ -0,0 +1,814 @@
+//! Geometric primitives shared by the generator.
+//!
+//! Predicates (`orientation`, `in_circle`) are exact: a floating point filter answers the easy
+//! cases, and nearly degenerate inputs fall back to exact expansion arithmetic, so topological
+//! decisions never contradict each other. Constructions (intersection points, circumcenters) are
+//! computed with plain floats.
+
+pub mod clip;
+pub mod containment;
+pub mod coordinates;
+pub(crate) mod fill;
+pub mod offset;
+pub mod triangulate;
+
+use serde::{Deserialize, Serialize};
+use voronator::delaunator::Point;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    CounterClockwise,
+    Clockwise,
+    Collinear,
+}
+
+/// Which way `a`, `b`, `c` turn
+pub fn orientation(a: &Point, b: &Point, c: &Point) -> Orientation {
+    let left = (a.x - c.x) * (b.y - c.y);
+    let right = (a.y - c.y) * (b.x - c.x);
+    let det = left - right;
+
+    // error bound from Shewchuk's orient2d
+    let bound = (3.0 + 16.0 * f64::EPSILON) * f64::EPSILON / 2.0 * (left.abs() + right.abs());
+
+    let sign = if det.abs() > bound {
+        det
+    } else {
+        orientation_exact(a, b, c)
+    };
+
+    if sign > 0.0 {
+        Orientation::CounterClockwise
+    } else if sign < 0.0 {
+        Orientation::Clockwise
+    } else {
+        Orientation::Collinear
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `c`, positive if counter-clockwise.
+///
+/// This is a plain float computation, use `orientation` to decide on which side a point is.
+pub fn cross(a: &Point, b: &Point, c: &Point) -> f64 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+/// Whether `d` is strictly inside, on or outside the circle going through `a`, `b`, `c`.
+///
+/// Returns a positive value if `d` is inside and the triangle is counter-clockwise (negative if it
+/// is clockwise), and exactly zero if the four points are cocircular. Only the sign is meaningful.
+pub fn in_circle(a: &Point, b: &Point, c: &Point, d: &Point) -> f64 {
+    let (adx, ady) = (a.x - d.x, a.y - d.y);
+    let (bdx, bdy) = (b.x - d.x, b.y - d.y);
+    let (cdx, cdy) = (c.x - d.x, c.y - d.y);
+
+    let alift = adx * adx + ady * ady;
+    let blift = bdx * bdx + bdy * bdy;
+    let clift = cdx * cdx + cdy * cdy;
+
+    let det = alift * (bdx * cdy - cdx * bdy)
+        + blift * (cdx * ady - adx * cdy)
+        + clift * (adx * bdy - bdx * ady);
+
+    let permanent = alift * ((bdx * cdy).abs() + (cdx * bdy).abs())
+        + blift * ((cdx * ady).abs() + (adx * cdy).abs())
+        + clift * ((adx * bdy).abs() + (bdx * ady).abs());
+
+    // error bound from Shewchuk's incircle
+    let bound = (10.0 + 96.0 * f64::EPSILON) * f64::EPSILON / 2.0 * permanent;
+
+    if det.abs() > bound {
+        det
+    } else {
+        in_circle_exact(a, b, c, d)
+    }
+}
+
+/// Center of the circle going through `a`, `b` and `c`, if they are not collinear
+pub fn circumcenter(a: &Point, b: &Point, c: &Point) -> Option<Point> {
+    if orientation(a, b, c) == Orientation::Collinear {
+        return None;
+    }
+
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let (ex, ey) = (c.x - a.x, c.y - a.y);
+
+    let bl = dx * dx + dy * dy;
+    let cl = ex * ex + ey * ey;
+    let det = dx * ey - dy * ex;
+
+    Some(Point {
+        x: a.x + (ey * bl - dy * cl) / (2.0 * det),
+        y: a.y + (dx * cl - ex * bl) / (2.0 * det),
+    })
+}
+
+pub fn distance(a: &Point, b: &Point) -> f64 {
+    f64::sqrt((a.x - b.x).powi(2) + (a.y - b.y).powi(2))
+}
+
+/// Signed area of a polygon, positive if it is counter-clockwise
+pub fn signed_area(points: &[Point]) -> f64 {
+    (0..points.len())
+        .map(|i| {
+            let (a, b) = (&points[i], &points[(i + 1) % points.len()]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+/// Center of mass of a polygon, `None` if it has no area
+pub fn centroid(points: &[Point]) -> Option<Point> {
+    let area = signed_area(points);
+
+    if area == 0.0 {
+        return None;
+    }
+
+    let (x, y) = (0..points.len()).fold((0.0, 0.0), |(x, y), i| {
+        let (a, b) = (&points[i], &points[(i + 1) % points.len()]);
+        let cross = a.x * b.y - b.x * a.y;
+        (x + (a.x + b.x) * cross, y + (a.y + b.y) * cross)
+    });
+
+    Some(Point {
+        x: x / (6.0 * area),
+        y: y / (6.0 * area),
+    })
+}
+
+/// Lowest and highest corners of the box around `points`, infinite and inverted if there are none
+pub fn bounding_box<'a>(points: impl IntoIterator<Item = &'a Point>) -> (Point, Point) {
+    points.into_iter().fold(
+        (
+            Point {
+                x: f64::INFINITY,
+                y: f64::INFINITY,
+            },
+            Point {
+                x: f64::NEG_INFINITY,
+                y: f64::NEG_INFINITY,
+            },
+        ),
+        |(min, max), p| {
+            (
+                Point {
+                    x: min.x.min(p.x),
+                    y: min.y.min(p.y),
+                },
+                Point {
+                    x: max.x.max(p.x),
+                    y: max.y.max(p.y),
+                },
+            )
+        },
+    )
+}
+
+/// The points of a polygon, reversed if needed so that they turn counter-clockwise
+pub fn counter_clockwise(points: &[Point]) -> Vec<Point> {
+    if signed_area(points) < 0.0 {
+        points.iter().rev().cloned().collect()
+    } else {
+        points.to_vec()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    #[serde(with = "coordinates::point")]
+    pub start: Point,
+    #[serde(with = "coordinates::point")]
+    pub end: Point,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Intersection {
+    None,
+    /// Segments meet at a single point, found at `t` along the first one and `u` along the second
+    Point {
+        point: Point,
+        t: f64,
+        u: f64,
+    },
+    /// Segments are collinear and share a whole piece, oriented like the first segment
+    Overlap(Segment),
+}
+
+impl Segment {
+    pub fn new(start: &Point, end: &Point) -> Self {
+        Segment {
+            start: start.clone(),
+            end: end.clone(),
+        }
+    }
+
+    pub fn length(&self) -> f64 {
+        distance(&self.start, &self.end)
+    }
+
+    /// Point at `start + t * (end - start)`
+    pub fn at(&self, t: f64) -> Point {
+        Point {
+            x: self.start.x + t * (self.end.x - self.start.x),
+            y: self.start.y + t * (self.end.y - self.start.y),
+        }
+    }
+
+    /// Position of the projection of `p` on the segment line, 0 being `start` and 1 `end`
+    pub fn parameter(&self, p: &Point) -> f64 {
+        let (dx, dy) = (self.end.x - self.start.x, self.end.y - self.start.y);
+        let length2 = dx * dx + dy * dy;
+
+        if length2 == 0.0 {
+            return 0.0;
+        }
+
+        ((p.x - self.start.x) * dx + (p.y - self.start.y) * dy) / length2
+    }
+
+    /// Whether `p` lies exactly on the segment, ends included
+    pub fn contains(&self, p: &Point) -> bool {
+        orientation(&self.start, &self.end, p) == Orientation::Collinear && self.in_box(p)
+    }
+
+    /// Whether `p` is in the bounding box of the segment, which is enough to know if it is on the
+    /// segment once we know it is on its line
+    fn in_box(&self, p: &Point) -> bool {
+        p.x >= self.start.x.min(self.end.x)
+            && p.x <= self.start.x.max(self.end.x)
+            && p.y >= self.start.y.min(self.end.y)
+            && p.y <= self.start.y.max(self.end.y)
+    }
+
+    /// Intersection of two closed segments
+    pub fn intersection(&self, other: &Segment) -> Intersection {
+        let o1 = orientation(&self.start, &self.end, &other.start);
+        let o2 = orientation(&self.start, &self.end, &other.end);
+        let o3 = orientation(&other.start, &other.end, &self.start);
+        let o4 = orientation(&other.start, &other.end, &self.end);
+
+        use Orientation::Collinear;
+
+        if o1 == Collinear && o2 == Collinear {
+            return self.overlap(other);
+        }
+
+        if (o1 != Collinear && o1 == o2) || (o3 != Collinear && o3 == o4) {
+            // both ends of one segment are strictly on the same side of the other
+            return Intersection::None;
+        }
+
+        // the segments do cross, or touch at an end: take the exact end when there is one, the
+        // parametric intersection otherwise
+        let (point, t, u) = if o3 == Collinear {
+            (self.start.clone(), 0.0, other.parameter(&self.start))
+        } else if o4 == Collinear {
+            (self.end.clone(), 1.0, other.parameter(&self.end))
+        } else if o1 == Collinear {
+            (other.start.clone(), self.parameter(&other.start), 0.0)
+        } else if o2 == Collinear {
+            (other.end.clone(), self.parameter(&other.end), 1.0)
+        } else {
+            let d1 = cross(&other.start, &other.end, &self.start);
+            let d2 = cross(&other.start, &other.end, &self.end);
+            let d3 = cross(&self.start, &self.end, &other.start);
+            let d4 = cross(&self.start, &self.end, &other.end);
+
+            let (t, u) = (d1 / (d1 - d2), d3 / (d3 - d4));
+            (self.at(t), t, u)
+        };
+
+        Intersection::Point {
+            point,
+            t: t.clamp(0.0, 1.0),
+            u: u.clamp(0.0, 1.0),
+        }
+    }
+
+    fn overlap(&self, other: &Segment) -> Intersection {
+        if self.start == self.end {
+            return if other.contains(&self.start) {
+                Intersection::Point {
+                    point: self.start.clone(),
+                    t: 0.0,
+                    u: other.parameter(&self.start),
+                }
+            } else {
+                Intersection::None
+            };
+        }
+
+        let (a, b) = (self.parameter(&other.start), self.parameter(&other.end));
+        let (low, high) = (a.min(b), a.max(b));
+
+        // clip the other segment to this one, keeping exact points as much as possible
+        let (start, t_start) = if low <= 0.0 {
+            (self.start.clone(), 0.0)
+        } else if a < b {
+            (other.start.clone(), low)
+        } else {
+            (other.end.clone(), low)
+        };
+
+        let (end, t_end) = if high >= 1.0 {
+            (self.end.clone(), 1.0)
+        } else if a < b {
+            (other.end.clone(), high)
+        } else {
+            (other.start.clone(), high)
+        };
+
+        if t_start > t_end {
+            Intersection::None
+        } else if start == end {
+            let u = other.parameter(&start);
+            Intersection::Point {
+                point: start,
+                t: t_start,
+                u,
+            }
+        } else {
+            Intersection::Overlap(Segment { start, end })
+        }
+    }
+
+    /// Where `self` crosses from one side of `other` to the other, as positions along both.
+    ///
+    /// Points lying exactly on the other segment's line are considered to be on its left side, so
+    /// touching a segment without crossing it is not a crossing, and a crossing on a vertex of a
+    /// polygon is only counted once. Collinear segments never cross.
+    pub fn crossing(&self, other: &Segment) -> Option<(f64, f64)> {
+        let right =
+            |s: &Segment, p: &Point| orientation(&s.start, &s.end, p) == Orientation::Clockwise;
+
+        if right(other, &self.start) == right(other, &self.end)
+            || right(self, &other.start) == right(self, &other.end)
+        {
+            return None;
+        }
+
+        let d1 = cross(&other.start, &other.end, &self.start);
+        let d2 = cross(&other.start, &other.end, &self.end);
+        let d3 = cross(&self.start, &self.end, &other.start);
+        let d4 = cross(&self.start, &self.end, &other.end);
+
+        Some((
+            (d1 / (d1 - d2)).clamp(0.0, 1.0),
+            (d3 / (d3 - d4)).clamp(0.0, 1.0),
+        ))
+    }
+}
+
+// exact arithmetic, from "Adaptive Precision Floating-Point Arithmetic and Fast Robust Geometric
+// Predicates" by Jonathan Richard Shewchuk
+//
+// a number is represented as an expansion: a sum of non-overlapping floats of increasing
+// magnitude, so its sign is the sign of its last component
+
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let x = a + b;
+    let b_virtual = x - a;
+    let a_virtual = x - b_virtual;
+    let error = (a - a_virtual) + (b - b_virtual);
+    (x, error)
+}
+
+fn two_product(a: f64, b: f64) -> (f64, f64) {
+    let x = a * b;
+    (x, a.mul_add(b, -x))
+}
+
+fn grow(expansion: &[f64], b: f64) -> Vec<f64> {
+    let mut result = Vec::with_capacity(expansion.len() + 1);
+    let mut q = b;
+
+    for &e in expansion {
+        let (sum, error) = two_sum(q, e);
+        if error != 0.0 {
+            result.push(error);
+        }
+        q = sum;
+    }
+
+    if q != 0.0 {
+        result.push(q);
+    }
+
+    result
+}
+
+fn sum(e: &[f64], f: &[f64]) -> Vec<f64> {
+    f.iter().fold(e.to_vec(), |acc, &b| grow(&acc, b))
+}
+
+fn negate(e: &[f64]) -> Vec<f64> {
+    e.iter().map(|x| -x).collect()
+}
+
+fn product(e: &[f64], f: &[f64]) -> Vec<f64> {
+    let mut result = Vec::new();
+
+    for &a in e {
+        for &b in f {
+            let (x, error) = two_product(a, b);
+            result = grow(&grow(&result, error), x);
+        }
+    }
+
+    result
+}
+
+fn sign(e: &[f64]) -> f64 {
+    // zero components are never kept, so an empty expansion is zero
+    e.last().map_or(0.0, |x| x.signum())
+}
+
+fn orientation_exact(a: &Point, b: &Point, c: &Point) -> f64 {
+    // ax by - ay bx + bx cy - by cx + cx ay - cy ax, without any rounded difference
+    let terms = [
+        product(&[a.x], &[b.y]),
+        negate(&product(&[a.y], &[b.x])),
+        product(&[b.x], &[c.y]),
+        negate(&product(&[b.y], &[c.x])),
+        product(&[c.x], &[a.y]),
+        negate(&product(&[c.y], &[a.x])),
+    ];
+
+    sign(&terms.iter().fold(Vec::new(), |acc, t| sum(&acc, t)))
+}
+
+fn in_circle_exact(a: &Point, b: &Point, c: &Point, d: &Point) -> f64 {
+    // determinant of the rows (x, y, x² + y², 1), expanded along the last column
+    let lift = |p: &Point| sum(&product(&[p.x], &[p.x]), &product(&[p.y], &[p.y]));
+
+    let minor = |p: &Point, q: &Point, r: &Point| {
+        let (lp, lq, lr) = (lift(p), lift(q), lift(r));
+
+        // x_p (y_q l_r - y_r l_q) - y_p (x_q l_r - x_r l_q) + l_p (x_q y_r - x_r y_q)
+        let first = product(
+            &[p.x],
+            &sum(&product(&[q.y], &lr), &negate(&product(&[r.y], &lq))),
+        );
+        let second = product(
+            &[p.y],
+            &sum(&product(&[q.x], &lr), &negate(&product(&[r.x], &lq))),
+        );
+        let third = product(
+            &lp,
+            &sum(&product(&[q.x], &[r.y]), &negate(&product(&[r.x], &[q.y]))),
+        );
+
+        sum(&sum(&first, &negate(&second)), &third)
+    };
+
+    let det = sum(
+        &sum(&negate(&minor(b, c, d)), &minor(a, c, d)),
+        &sum(&negate(&minor(a, b, d)), &minor(a, b, c)),
+    );
+
+    sign(&det)
+}
+
+#[cfg(test)]
+mod test {
+
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+
+    use crate::test_util::p;
+
+    /// Exact orientation of points whose coordinates are integers
+    fn orientation_i128(a: (i128, i128), b: (i128, i128), c: (i128, i128)) -> i128 {
+        ((a.0 - c.0) * (b.1 - c.1) - (a.1 - c.1) * (b.0 - c.0)).signum()
+    }
+
+    fn in_circle_i128(pts: [(i128, i128); 4]) -> i128 {
+        let [a, b, c] = [0, 1, 2].map(|i| (pts[i].0 - pts[3].0, pts[i].1 - pts[3].1));
+        let lift = |p: (i128, i128)| p.0 * p.0 + p.1 * p.1;
+
+        (lift(a) * (b.0 * c.1 - c.0 * b.1)
+            + lift(b) * (c.0 * a.1 - a.0 * c.1)
+            + lift(c) * (a.0 * b.1 - b.0 * a.1))
+            .signum()
+    }
+
+    fn sign_of(o: Orientation) -> i128 {
+        match o {
+            Orientation::CounterClockwise => 1,
+            Orientation::Clockwise => -1,
+            Orientation::Collinear => 0,
+        }
+    }
+
+    #[test]
+    fn simple_orientations() {
+        let (a, b) = (p(0.0, 0.0), p(1.0, 0.0));
+
+        assert_eq!(
+            orientation(&a, &b, &p(0.5, 1.0)),
+            Orientation::CounterClockwise
+        );
+        assert_eq!(orientation(&a, &b, &p(0.5, -1.0)), Orientation::Clockwise);
+        assert_eq!(orientation(&a, &b, &p(3.0, 0.0)), Orientation::Collinear);
+    }
+
+    #[test]
+    fn orientation_near_degenerate() {
+        // classic failure case of the naive predicate: points within a few ulps of y = x
+        let ulp = 2f64.powi(-53);
+        let scale = 2i128.pow(53);
+
+        for i in 0..64 {
+            for j in 0..64 {
+                let a = p(0.5 + i as f64 * ulp, 0.5 + j as f64 * ulp);
+                let (b, c) = (p(12.0, 12.0), p(24.0, 24.0));
+
+                let exact = orientation_i128(
+                    (scale / 2 + i, scale / 2 + j),
+                    (12 * scale, 12 * scale),
+                    (24 * scale, 24 * scale),
+                );
+
+                assert_eq!(sign_of(orientation(&a, &b, &c)), exact);
+                // the predicate does not depend on the order of the points
+                assert_eq!(sign_of(orientation(&b, &c, &a)), exact);
+                assert_eq!(sign_of(orientation(&c, &b, &a)), -exact);
+            }
+        }
+    }
+
+    #[test]
+    fn in_circle_simple() {
+        let (a, b, c) = (p(-1.0, 0.0), p(1.0, 0.0), p(0.0, 1.0));
+
+        assert!(in_circle(&a, &b, &c, &p(0.0, 0.0)) > 0.0);
+        assert!(in_circle(&a, &b, &c, &p(0.0, -1.0)) == 0.0);
+        assert!(in_circle(&a, &b, &c, &p(0.0, -2.0)) < 0.0);
+        // clockwise triangle flips the sign
+        assert!(in_circle(&b, &a, &c, &p(0.0, 0.0)) < 0.0);
+    }
+
+    #[test]
+    fn in_circle_matches_exact_integers() {
+        let mut rng = SmallRng::seed_from_u64(7);
+        let unit = 2f64.powi(-20);
+
+        for _ in 0..2000 {
+            // a small grid makes cocircular and nearly cocircular points very common, and the
+            // scale makes the float computation inexact
+            let pts: [(i128, i128); 4] = [(); 4].map(|_| {
+                let x: i128 = rng.gen_range(-4..=4) * (1 << 20) + rng.gen_range(-2..=2);
+                let y: i128 = rng.gen_range(-4..=4) * (1 << 20) + rng.gen_range(-2..=2);
+                (x, y)
+            });
+            let points = pts.map(|(x, y)| p(x as f64 * unit, y as f64 * unit));
+
+            let expected = in_circle_i128(pts);
+            let result = in_circle(&points[0], &points[1], &points[2], &points[3]);
+
+            assert_eq!(result.signum() as i128 * (result != 0.0) as i128, expected);
+        }
+    }
+
+    #[test]
+    fn orientation_matches_exact_integers() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        let unit = 2f64.powi(-40);
+
+        for _ in 0..5000 {
+            let pts: [(i128, i128); 3] = [(); 3].map(|_| {
+                let t: i128 = rng.gen_range(-8..=8);
+                (
+                    t * (1 << 45) + rng.gen_range(-1..=1),
+                    t * (3 << 44) + rng.gen_range(-1..=1),
+                )
+            });
+            let points = pts.map(|(x, y)| p(x as f64 * unit, y as f64 * unit));
+
+            assert_eq!(
+                sign_of(orientation(&points[0], &points[1], &points[2])),
+                orientation_i128(pts[0], pts[1], pts[2])
+            );
+        }
+    }
+
+    #[test]
+    fn crossing_segments() {
+        let s1 = Segment::new(&p(0.0, 0.0), &p(2.0, 2.0));
+        let s2 = Segment::new(&p(0.0, 2.0), &p(2.0, 0.0));
+
+        assert_eq!(
+            s1.intersection(&s2),
+            Intersection::Point {
+                point: p(1.0, 1.0),
+                t: 0.5,
+                u: 0.5
+            }
+        );
+    }
+
+    #[test]
+    fn vertical_segments() {
+        // the old slope based helpers could not handle these
+        let s1 = Segment::new(&p(1.0, -1.0), &p(1.0, 1.0));
+        let s2 = Segment::new(&p(0.0, 0.5), &p(3.0, 0.5));
+
+        assert_eq!(
+            s1.intersection(&s2),
+            Intersection::Point {
+                point: p(1.0, 0.5),
+                t: 0.75,
+                u: 1.0 / 3.0
+            }
+        );
+
+        let s3 = Segment::new(&p(2.0, -1.0), &p(2.0, 1.0));
+        assert_eq!(s1.intersection(&s3), Intersection::None);
+    }
+
+    #[test]
+    fn touching_segments() {
+        let s1 = Segment::new(&p(0.0, 0.0), &p(2.0, 0.0));
+
+        // T junction
+        let s2 = Segment::new(&p(1.0, 0.0), &p(1.0, 3.0));
+        assert_eq!(
+            s1.intersection(&s2),
+            Intersection::Point {
+                point: p(1.0, 0.0),
+                t: 0.5,
+                u: 0.0
+            }
+        );
+
+        // shared end
+        let s3 = Segment::new(&p(2.0, 0.0), &p(4.0, 5.0));
+        assert_eq!(
+            s1.intersection(&s3),
+            Intersection::Point {
+                point: p(2.0, 0.0),
+                t: 1.0,
+                u: 0.0
+            }
+        );
+
+        // almost touching
+        let s4 = Segment::new(&p(1.0, 1e-300), &p(1.0, 3.0));
+        assert_eq!(s1.intersection(&s4), Intersection::None);
+    }
+
+    #[test]
+    fn collinear_segments() {
+        let s1 = Segment::new(&p(0.0, 0.0), &p(4.0, 0.0));
+
+        let inside = Segment::new(&p(3.0, 0.0), &p(1.0, 0.0));
+        assert_eq!(
+            s1.intersection(&inside),
+            Intersection::Overlap(Segment::new(&p(1.0, 0.0), &p(3.0, 0.0)))
+        );
+
+        let straddling = Segment::new(&p(-1.0, 0.0), &p(2.0, 0.0));
+        assert_eq!(
+            s1.intersection(&straddling),
+            Intersection::Overlap(Segment::new(&p(0.0, 0.0), &p(2.0, 0.0)))
+        );
+        assert_eq!(
+            straddling.intersection(&s1),
+            Intersection::Overlap(Segment::new(&p(0.0, 0.0), &p(2.0, 0.0)))
+        );
+
+        let touching = Segment::new(&p(4.0, 0.0), &p(6.0, 0.0));
+        assert_eq!(
+            s1.intersection(&touching),
+            Intersection::Point {
+                point: p(4.0, 0.0),
+                t: 1.0,
+                u: 0.0
+            }
+        );
+
+        let disjoint = Segment::new(&p(5.0, 0.0), &p(6.0, 0.0));
+        assert_eq!(s1.intersection(&disjoint), Intersection::None);
+
+        let parallel = Segment::new(&p(0.0, 1.0), &p(4.0, 1.0));
+        assert_eq!(s1.intersection(&parallel), Intersection::None);
+    }
+
+    #[test]
+    fn degenerate_segments() {
+        let s1 = Segment::new(&p(0.0, 0.0), &p(4.0, 4.0));
+        let on = Segment::new(&p(1.0, 1.0), &p(1.0, 1.0));
+        let off = Segment::new(&p(1.0, 2.0), &p(1.0, 2.0));
+
+        assert_eq!(
+            on.intersection(&s1),
+            Intersection::Point {
+                point: p(1.0, 1.0),
+                t: 0.0,
+                u: 0.25
+            }
+        );
+        assert_eq!(off.intersection(&s1), Intersection::None);
+    }
+
+    #[test]
+    fn random_intersections_are_consistent() {
+        let mut rng = SmallRng::seed_from_u64(12);
+        let mut point = || p(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+
+        for _ in 0..5000 {
+            let s1 = Segment::new(&point(), &point());
+            let s2 = Segment::new(&point(), &point());
+
+            match (s1.intersection(&s2), s2.intersection(&s1)) {
+                (Intersection::None, Intersection::None) => {
+                    assert!(s1.crossing(&s2).is_none());
+                }
+                (
+                    Intersection::Point { point, t, u },
+                    Intersection::Point {
+                        point: other,
+                        t: t2,
+                        u: u2,
+                    },
+                ) => {
+                    assert!(distance(&point, &other) < 1e-9);
+                    assert!((t - u2).abs() < 1e-9 && (u - t2).abs() < 1e-9);
+                    assert!(distance(&point, &s1.at(t)) < 1e-9);
+                    assert!(distance(&point, &s2.at(u)) < 1e-9);
+
+                    let (ct, cu) = s1.crossing(&s2).unwrap();
+                    assert!((ct - t).abs() < 1e-9 && (cu - u).abs() < 1e-9);
+                }
+                (a, b) => panic!("asymmetric intersection: {:?} and {:?}", a, b),
+            }
+        }
+    }
+
+    #[test]
+    fn crossing_counts_vertices_once() {
+        // a horizontal line going exactly through the vertex of a polyline
+        let line = Segment::new(&p(-2.0, 0.0), &p(2.0, 0.0));
+        let before = Segment::new(&p(0.0, -1.0), &p(0.0, 0.0));
+        let after = Segment::new(&p(0.0, 0.0), &p(0.0, 1.0));
+
+        let count = [&before, &after]
+            .iter()
+            .filter(|s| s.crossing(&line).is_some())
+            .count();
+        assert_eq!(count, 1);
+
+        // and bouncing on it does not cross at all
+        let back = Segment::new(&p(0.0, 0.0), &p(1.0, -1.0));
+        let count = [&before, &back]
+            .iter()
+            .filter(|s| s.crossing(&line).is_some())
+            .count();
+        assert_eq!(count % 2, 0);
+    }
+
+    #[test]
+    fn polygon_orientation() {
+        let square = [p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0), p(0.0, 1.0)];
+        assert_eq!(signed_area(&square), 1.0);
+
+        let reversed: Vec<_> = square.iter().rev().cloned().collect();
+        assert_eq!(signed_area(&reversed), -1.0);
+        assert_eq!(signed_area(&counter_clockwise(&reversed)), 1.0);
+    }
+
+    #[test]
+    fn centroids() {
+        let square = [p(0.0, 0.0), p(2.0, 0.0), p(2.0, 2.0), p(0.0, 2.0)];
+        assert_eq!(centroid(&square), Some(p(1.0, 1.0)));
+
+        // same for both orientations
+        let triangle = [p(0.0, 0.0), p(0.0, 3.0), p(3.0, 0.0)];
+        assert_eq!(centroid(&triangle), Some(p(1.0, 1.0)));
+
+        // an L is not centered on its vertex average
+        let l = [
+            p(0.0, 0.0),
+            p(2.0, 0.0),
+            p(2.0, 1.0),
+            p(1.0, 1.0),
+            p(1.0, 2.0),
+            p(0.0, 2.0),
+        ];
+        let center = centroid(&l).unwrap();
+        assert!((center.x - 5.0 / 6.0).abs() < 1e-12 && (center.y - 5.0 / 6.0).abs() < 1e-12);
+
+        assert_eq!(centroid(&[p(0.0, 0.0), p(1.0, 1.0), p(2.0, 2.0)]), None);
+    }
+
+    #[test]
+    fn circumcenters() {
+        let center = circumcenter(&p(-1.0, 0.0), &p(1.0, 0.0), &p(0.0, 1.0)).unwrap();
+        assert!(distance(&center, &p(0.0, 0.0)) < 1e-12);
+
+        assert!(circumcenter(&p(0.0, 0.0), &p(1.0, 1.0), &p(2.0, 2.0)).is_none());
+    }
+}
@@ -0,0 +1,544 @@
+//! Polygon clipping.
+//!
+//! `sutherland_hodgman` is the cheap option when the clip polygon is convex, `weiler_atherton`
+//! handles concave polygons on both sides and returns every piece of the intersection.
+
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use super::containment::{locate, Location};
+use super::{counter_clockwise, orientation, signed_area, Orientation, Segment};
+
+/// Pieces smaller than this are the result of polygons touching without overlapping
+const AREA_EPSILON: f64 = 1e-12;
+
+/// Clips `subject` against `clip`, using Sutherland–Hodgman when both are convex and
+/// Weiler–Atherton otherwise
+pub fn clip(subject: &Polygon<Point>, clip: &Polygon<Point>) -> Vec<Polygon<Point>> {
+    if is_convex(clip.points()) && is_convex(subject.points()) {
+        let result = sutherland_hodgman(subject, clip);
+
+        if signed_area(result.points()).abs() > AREA_EPSILON {
+            vec![result]
+        } else {
+            Vec::new()
+        }
+    } else {
+        weiler_atherton(subject, clip)
+    }
+}
+
+/// Whether the polygon is convex, whatever its orientation
+pub fn is_convex(points: &[Point]) -> bool {
+    let n = points.len();
+    let mut turn = Orientation::Collinear;
+
+    for i in 0..n {
+        match orientation(&points[i], &points[(i + 1) % n], &points[(i + 2) % n]) {
+            Orientation::Collinear => {}
+            o if turn == Orientation::Collinear => turn = o,
+            o if o != turn => return false,
+            _ => {}
+        }
+    }
+
+    true
+}
+
+/// Clips `subject` against the convex polygon `clip`, one edge after the other.
+///
+/// The result is counter-clockwise. If `subject` is concave and the intersection has several
+/// pieces, they stay linked by zero-width edges along the clip boundary.
+pub fn sutherland_hodgman(subject: &Polygon<Point>, clip: &Polygon<Point>) -> Polygon<Point> {
+    let clip = counter_clockwise(clip.points());
+    let mut output = counter_clockwise(subject.points());
+
+    for k in 0..clip.len() {
+        if output.is_empty() {
+            break;
+        }
+
+        let edge = Segment::new(&clip[k], &clip[(k + 1) % clip.len()]);
+        let input = std::mem::take(&mut output);
+
+        for i in 0..input.len() {
+            let (current, next) = (&input[i], &input[(i + 1) % input.len()]);
+
+            let current_in = orientation(&edge.start, &edge.end, current) != Orientation::Clockwise;
+            let next_in = orientation(&edge.start, &edge.end, next) != Orientation::Clockwise;
+
+            if current_in {
+                output.push(current.clone());
+            }
+
+            if current_in != next_in {
+                output.push(line_intersection(current, next, &edge));
+            }
+        }
+    }
+
+    Polygon::from_points(dedup(output))
+}
+
+/// Intersection of the segment `a`, `b` with the line going through `edge`
+fn line_intersection(a: &Point, b: &Point, edge: &Segment) -> Point {
+    let da = super::cross(&edge.start, &edge.end, a);
+    let db = super::cross(&edge.start, &edge.end, b);
+
+    Segment::new(a, b).at(da / (da - db))
+}
+
+/// Clips `subject` against `clip`, both being any simple polygon.
+///
+/// The subject is walked counter-clockwise, and every time it leaves the clip polygon we follow
+/// the clip boundary until the subject comes back in. Each time the subject enters the clip
+/// polygon without having been visited yet starts a new piece. Pieces are counter-clockwise, and
+/// pieces of zero area (where the polygons merely touch) are dropped.
+///
+/// Vertices of one polygon lying on an edge of the other are handled, but vertices of both
+/// polygons exactly on top of each other are not.
+pub fn weiler_atherton(subject: &Polygon<Point>, clip: &Polygon<Point>) -> Vec<Polygon<Point>> {
+    let subject = counter_clockwise(subject.points());
+    let clip = counter_clockwise(clip.points());
+
+    if subject.len() < 3 || clip.len() < 3 {
+        return Vec::new();
+    }
+
+    let mut walk = walk_with_crossings(&subject, &clip);
+
+    let nb_crossings = walk
+        .iter()
+        .filter(|s| matches!(s, Step::Crossing(..)))
+        .count();
+
+    let start = subject
+        .iter()
+        .enumerate()
+        .find_map(|(i, p)| match locate(p, &clip) {
+            Location::Inside => Some((i, true)),
+            Location::Outside => Some((i, false)),
+            Location::Boundary => None,
+        });
+
+    if nb_crossings == 0 {
+        let pieces = match start {
+            Some((_, true)) | None => vec![subject],
+            Some((_, false))
+                if clip
+                    .iter()
+                    .all(|p| locate(p, &subject) != Location::Outside) =>
+            {
+                vec![clip]
+            }
+            Some((_, false)) => Vec::new(),
+        };
+
+        return pieces.into_iter().map(Polygon::from_points).collect();
+    }
+
+    // whether each step is inside the clip polygon, crossings being the point where it changes,
+    // starting from a vertex we know for sure is inside or outside
+    let (first_vertex, first_in) = start.unwrap_or((0, true));
+    let first_step = walk
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| matches!(s, Step::Vertex(_)))
+        .nth(first_vertex)
+        .unwrap()
+        .0;
+
+    let mut is_in = vec![false; walk.len()];
+    let mut current_in = first_in;
+
+    for k in 0..walk.len() {
+        let s = (first_step + k) % walk.len();
+
+        if let Step::Crossing(..) = walk[s] {
+            current_in = !current_in;
+        }
+        is_in[s] = current_in;
+    }
+
+    pair_touching_crossings(&mut walk, &is_in, clip.len());
+
+    let mut visited = vec![false; walk.len()];
+    let mut pieces = Vec::new();
+
+    while let Some(start) =
+        (0..walk.len()).find(|&s| is_in[s] && !visited[s] && matches!(walk[s], Step::Crossing(..)))
+    {
+        let mut piece: Vec<Point> = Vec::new();
+        let mut current = start;
+
+        loop {
+            visited[current] = true;
+            piece.push(walk[current].point().clone());
+
+            let next = (current + 1) % walk.len();
+
+            current = match &walk[next] {
+                Step::Crossing(p, exit) if !is_in[next] => {
+                    // we are leaving the clip polygon, follow it until the subject comes back
+                    visited[next] = true;
+                    piece.push(p.clone());
+
+                    let entry = next_entry(&walk, &is_in, exit, clip.len());
+
+                    if let Step::Crossing(_, crossing) = &walk[entry] {
+                        let mut k = exit.edge;
+
+                        if !(crossing.edge == exit.edge && crossing.along > exit.along) {
+                            loop {
+                                k = (k + 1) % clip.len();
+                                piece.push(clip[k].clone());
+
+                                if k == crossing.edge {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+
+                    entry
+                }
+                _ => next,
+            };
+
+            if current == start || visited[current] {
+                break;
+            }
+        }
+
+        pieces.push(piece);
+    }
+
+    pieces
+        .into_iter()
+        .map(dedup)
+        .filter(|piece| piece.len() >= 3 && signed_area(piece) > AREA_EPSILON)
+        .map(Polygon::from_points)
+        .collect()
+}
+
+enum Step {
+    Vertex(Point),
+    Crossing(Point, Crossing),
+}
+
+impl Step {
+    fn point(&self) -> &Point {
+        match self {
+            Step::Vertex(p) | Step::Crossing(p, _) => p,
+        }
+    }
+}
+
+/// Where the walk around the subject crosses the clip polygon
+struct Crossing {
+    /// index of the edge of the clip polygon that is crossed
+    edge: usize,
+    /// position along that edge, from 0 (start) to 1 (end)
+    along: f64,
+}
+
+/// All the points of the walk around the subject, with the crossings of the clip polygon
+/// inbetween, in order
+fn walk_with_crossings(subject: &[Point], clip: &[Point]) -> Vec<Step> {
+    let mut walk = Vec::new();
+
+    for i in 0..subject.len() {
+        walk.push(Step::Vertex(subject[i].clone()));
+
+        let edge = Segment::new(&subject[i], &subject[(i + 1) % subject.len()]);
+
+        let mut crossings: Vec<_> = (0..clip.len())
+            .filter_map(|k| {
+                let clip_edge = Segment::new(&clip[k], &clip[(k + 1) % clip.len()]);
+
+                edge.crossing(&clip_edge)
+                    .map(|(t, u)| (t, Crossing { edge: k, along: u }))
+            })
+            .collect();
+
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+        walk.extend(
+            crossings
+                .into_iter()
+                .map(|(t, crossing)| Step::Crossing(edge.at(t), crossing)),
+        );
+    }
+
+    walk
+}
+
+/// Distance travelled along the clip boundary, counter-clockwise, to go from `from` to `to`
+fn distance_along(from: &Crossing, to: &Crossing, nb_edges: usize) -> f64 {
+    let edges = (to.edge + nb_edges - from.edge) % nb_edges;
+
+    if edges == 0 && to.along < from.along {
+        nb_edges as f64 + to.along - from.along
+    } else {
+        edges as f64 + to.along - from.along
+    }
+}
+
+/// When the subject goes through a vertex of the clip polygon, it crosses both edges of that
+/// vertex at the same point. Whatever order they were sorted in, the exit must lead right back to
+/// the entry, so swap them if needed.
+fn pair_touching_crossings(walk: &mut [Step], is_in: &[bool], nb_edges: usize) {
+    for (s, &inside) in is_in.iter().enumerate() {
+        let next = (s + 1) % walk.len();
+
+        let (exit, entry) = if inside { (next, s) } else { (s, next) };
+
+        let swap = match (&walk[exit], &walk[entry]) {
+            (Step::Crossing(p, a), Step::Crossing(q, b)) if p == q => {
+                distance_along(a, b, nb_edges) > distance_along(b, a, nb_edges)
+            }
+            _ => false,
+        };
+
+        if swap {
+            let (a, b) = if s < next { (s, next) } else { (next, s) };
+            let (left, right) = walk.split_at_mut(b);
+
+            if let (Step::Crossing(_, first), Step::Crossing(_, second)) =
+                (&mut left[a], &mut right[0])
+            {
+                std::mem::swap(first, second);
+            }
+        }
+    }
+}
+
+/// Finds the step where the subject comes back inside the clip polygon, after leaving it at `exit`
+fn next_entry(walk: &[Step], is_in: &[bool], exit: &Crossing, nb_edges: usize) -> usize {
+    (0..walk.len())
+        .filter(|&s| is_in[s])
+        .filter_map(|s| match &walk[s] {
+            Step::Crossing(_, c) => Some((s, distance_along(exit, c, nb_edges))),
+            Step::Vertex(_) => None,
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("the subject has left the clip polygon but never comes back")
+        .0
+}
+
+/// Removes consecutive duplicate points, including between the last and the first one
+fn dedup(mut points: Vec<Point>) -> Vec<Point> {
+    points.dedup();
+
+    while points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::test_util::{p, square};
+
+    fn translated(polygon: &Polygon<Point>, x: f64, y: f64) -> Polygon<Point> {
+        Polygon::from_points(
+            polygon
+                .points()
+                .iter()
+                .map(|q| p(q.x + x, q.y + y))
+                .collect(),
+        )
+    }
+
+    fn assert_same_polygon(result: &Polygon<Point>, expected: &[Point]) {
+        assert_eq!(
+            result.points().len(),
+            expected.len(),
+            "{:?}",
+            result.points()
+        );
+        assert!((signed_area(result.points()) - signed_area(expected)).abs() < 1e-12);
+
+        for q in expected {
+            assert!(result
+                .points()
+                .iter()
+                .any(|r| (q.x - r.x).abs() < 1e-12 && (q.y - r.y).abs() < 1e-12));
+        }
+    }
+
+    /// Comb with `teeth` teeth pointing up, between x = 0 and x = 2 * teeth - 1
+    fn comb(teeth: usize) -> Polygon<Point> {
+        let mut points = vec![p(0.0, 0.0), p(2.0 * teeth as f64 - 1.0, 0.0)];
+
+        for i in (0..teeth).rev() {
+            let x = 2.0 * i as f64;
+            points.push(p(x + 1.0, 3.0));
+            points.push(p(x, 3.0));
+
+            if i > 0 {
+                points.push(p(x, 1.0));
+                points.push(p(x - 1.0, 1.0));
+            }
+        }
+
+        Polygon::from_points(points)
+    }
+
+    #[test]
+    fn convexity() {
+        assert!(is_convex(square(1.0).points()));
+        assert!(is_convex(
+            &counter_clockwise(square(1.0).points())
+                .into_iter()
+                .rev()
+                .collect::<Vec<_>>()
+        ));
+        assert!(!is_convex(comb(2).points()));
+    }
+
+    #[test]
+    fn sutherland_hodgman_squares() {
+        let result = sutherland_hodgman(&square(1.0), &translated(&square(1.0), 1.0, 1.0));
+
+        assert_same_polygon(
+            &result,
+            &[p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0), p(0.0, 1.0)],
+        );
+    }
+
+    #[test]
+    fn sutherland_hodgman_disjoint() {
+        let result = sutherland_hodgman(&square(1.0), &translated(&square(1.0), 5.0, 0.0));
+        assert!(result.points().is_empty());
+    }
+
+    #[test]
+    fn algorithms_agree_on_convex_polygons() {
+        let hexagon = Polygon::from_points(
+            (0..6)
+                .map(|i| {
+                    let a = i as f64 * std::f64::consts::PI / 3.0;
+                    p(1.5 * a.cos(), 1.5 * a.sin())
+                })
+                .collect(),
+        );
+
+        for (x, y) in [(0.0, 0.0), (0.5, 0.3), (1.7, -0.2), (-2.0, 2.0)] {
+            let subject = translated(&square(1.0), x, y);
+
+            let expected = sutherland_hodgman(&subject, &hexagon);
+            let pieces = weiler_atherton(&subject, &hexagon);
+
+            if expected.points().is_empty() {
+                assert!(pieces.is_empty());
+            } else {
+                assert_eq!(pieces.len(), 1);
+                assert_same_polygon(&pieces[0], expected.points());
+            }
+        }
+    }
+
+    #[test]
+    fn concave_clip_gives_several_pieces() {
+        // a wide rectangle across the teeth of the comb
+        let subject =
+            Polygon::from_points(vec![p(-1.0, 2.0), p(6.0, 2.0), p(6.0, 2.5), p(-1.0, 2.5)]);
+
+        let pieces = weiler_atherton(&subject, &comb(3));
+
+        assert_eq!(pieces.len(), 3);
+        for piece in &pieces {
+            assert!((signed_area(piece.points()) - 0.5).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn concave_subject() {
+        // clipping is symmetric, the same teeth come out with a concave subject
+        let clip = Polygon::from_points(vec![p(-1.0, 2.0), p(6.0, 2.0), p(6.0, 2.5), p(-1.0, 2.5)]);
+
+        let pieces = weiler_atherton(&comb(3), &clip);
+
+        assert_eq!(pieces.len(), 3);
+        for piece in &pieces {
+            assert!((signed_area(piece.points()) - 0.5).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn concave_against_concave() {
+        // two combs facing each other, teeth interleaving between y = 1 and y = 3
+        let up = comb(3);
+        let facing = |shift: f64| {
+            Polygon::from_points(
+                up.points()
+                    .iter()
+                    .map(|q| p(q.x + shift, 4.0 - q.y))
+                    .rev()
+                    .collect(),
+            )
+        };
+
+        let pieces = weiler_atherton(&up, &facing(0.5));
+
+        assert_eq!(pieces.len(), 3);
+        for piece in &pieces {
+            assert!((signed_area(piece.points()) - 1.0).abs() < 1e-12);
+        }
+
+        // perfectly interleaved teeth only touch
+        assert!(weiler_atherton(&up, &facing(1.0)).is_empty());
+    }
+
+    #[test]
+    fn nested_polygons() {
+        let small = square(0.5);
+        let big = square(2.0);
+
+        assert_same_polygon(&weiler_atherton(&small, &big)[0], small.points());
+        assert_same_polygon(&weiler_atherton(&big, &small)[0], small.points());
+        assert!(weiler_atherton(&small, &translated(&big, 10.0, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn touching_polygons() {
+        // sharing an edge, or a single corner, has no area
+        assert!(clip(&square(1.0), &translated(&square(1.0), 2.0, 0.0)).is_empty());
+        assert!(weiler_atherton(&square(1.0), &translated(&square(1.0), 2.0, 2.0)).is_empty());
+
+        // going through a vertex of the clip polygon without entering it
+        let diamond =
+            Polygon::from_points(vec![p(2.0, 0.0), p(3.0, -1.0), p(4.0, 0.0), p(3.0, 1.0)]);
+        let subject =
+            Polygon::from_points(vec![p(0.0, -1.0), p(2.0, -1.0), p(2.0, 1.0), p(0.0, 1.0)]);
+        assert!(weiler_atherton(&subject, &diamond).is_empty());
+
+        // and through two of them while being inside
+        let subject =
+            Polygon::from_points(vec![p(0.0, -2.0), p(3.0, -2.0), p(3.0, 2.0), p(0.0, 2.0)]);
+        let pieces = weiler_atherton(&subject, &diamond);
+        assert_eq!(pieces.len(), 1);
+        assert!((signed_area(pieces[0].points()) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn clockwise_inputs() {
+        let reversed = |polygon: &Polygon<Point>| {
+            Polygon::from_points(polygon.points().iter().rev().cloned().collect())
+        };
+
+        let pieces = weiler_atherton(
+            &reversed(&square(1.0)),
+            &reversed(&translated(&square(1.0), 1.0, 1.0)),
+        );
+
+        assert_eq!(pieces.len(), 1);
+        assert_same_polygon(
+            &pieces[0],
+            &[p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0), p(0.0, 1.0)],
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Point and polygon containment queries.
+//!
+//! Everything is decided with exact orientation predicates, so a point on an edge is always
+//! reported as being on the boundary, whatever the edge direction.
+
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use super::{orientation, Intersection, Orientation, Segment};
+
+/// Where something is relative to a polygon
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    Inside,
+    Boundary,
+    Outside,
+}
+
+/// Number of times the boundary of `polygon` turns around `p`, counter-clockwise.
+///
+/// It is 1 (or -1 for a clockwise polygon) inside a simple polygon, and 0 outside. The result is
+/// meaningless for points on the boundary.
+pub fn winding_number(p: &Point, polygon: &[Point]) -> i32 {
+    let mut winding = 0;
+
+    for i in 0..polygon.len() {
+        let (a, b) = (&polygon[i], &polygon[(i + 1) % polygon.len()]);
+
+        if a.y <= p.y {
+            if b.y > p.y && orientation(a, b, p) == Orientation::CounterClockwise {
+                // upward edge with p on its left
+                winding += 1;
+            }
+        } else if b.y <= p.y && orientation(a, b, p) == Orientation::Clockwise {
+            // downward edge with p on its right
+            winding -= 1;
+        }
+    }
+
+    winding
+}
+
+/// Index of the first edge of `polygon` (going from point `i` to point `i + 1`) that `p` lies on
+pub fn boundary_edge(p: &Point, polygon: &[Point]) -> Option<usize> {
+    (0..polygon.len())
+        .find(|&i| Segment::new(&polygon[i], &polygon[(i + 1) % polygon.len()]).contains(p))
+}
+
+/// Locates `p` relative to `polygon`, which can be oriented either way
+pub fn locate_point(p: &Point, polygon: &Polygon<Point>) -> Location {
+    locate(p, polygon.points())
+}
+
+pub(crate) fn locate(p: &Point, polygon: &[Point]) -> Location {
+    if boundary_edge(p, polygon).is_some() {
+        Location::Boundary
+    } else if winding_number(p, polygon) != 0 {
+        Location::Inside
+    } else {
+        Location::Outside
+    }
+}
+
+/// Locates `inner` relative to `outer`.
+///
+/// * `Inside` if `inner` is fully contained in `outer`, possibly touching its boundary
+/// * `Outside` if they do not overlap, possibly touching each other
+/// * `Boundary` if `inner` is partly inside and partly outside `outer`
+pub fn locate_polygon(inner: &Polygon<Point>, outer: &Polygon<Point>) -> Location {
+    let inner = inner.points();
+    let outer = outer.points();
+
+    let inner_boundary = boundary_locations(inner, outer);
+
+    let any_inside = inner_boundary.contains(&Location::Inside);
+    let any_outside = inner_boundary.contains(&Location::Outside);
+
+    match (any_inside, any_outside) {
+        (true, true) => Location::Boundary,
+        (true, false) => Location::Inside,
+        // both boundaries are the same
+        (false, false) => Location::Inside,
+        (false, true) => {
+            // either disjoint, or `inner` goes around `outer`
+            if boundary_locations(outer, inner).contains(&Location::Inside) {
+                Location::Boundary
+            } else {
+                Location::Outside
+            }
+        }
+    }
+}
+
+/// Locations of the vertices of `polygon` relative to `other`, like `locate_point` for each of them
+pub fn locate_vertices(polygon: &Polygon<Point>, other: &Polygon<Point>) -> Vec<Location> {
+    polygon
+        .points()
+        .iter()
+        .map(|p| locate(p, other.points()))
+        .collect()
+}
+
+/// Locations of enough points on the boundary of `polygon` to describe it relative to `other`:
+/// its vertices, and the middle of every piece of edge between two intersections with `other`
+fn boundary_locations(polygon: &[Point], other: &[Point]) -> Vec<Location> {
+    let mut locations = Vec::new();
+
+    for i in 0..polygon.len() {
+        let edge = Segment::new(&polygon[i], &polygon[(i + 1) % polygon.len()]);
+
+        let mut cuts: Vec<f64> = vec![0.0, 1.0];
+
+        for k in 0..other.len() {
+            let other_edge = Segment::new(&other[k], &other[(k + 1) % other.len()]);
+
+            match edge.intersection(&other_edge) {
+                Intersection::None => {}
+                Intersection::Point { t, .. } => cuts.push(t),
+                Intersection::Overlap(overlap) => {
+                    cuts.push(edge.parameter(&overlap.start));
+                    cuts.push(edge.parameter(&overlap.end));
+                }
+            }
+        }
+
+        cuts.sort_by(|a, b| a.total_cmp(b));
+
+        locations.push(locate(&edge.start, other));
+        locations.extend(
+            cuts.windows(2)
+                .filter(|w| w[1] > w[0])
+                .map(|w| locate(&edge.at((w[0] + w[1]) / 2.0), other)),
+        );
+    }
+
+    locations
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::test_util::{p, square};
+
+    fn translated(polygon: &Polygon<Point>, x: f64, y: f64) -> Polygon<Point> {
+        Polygon::from_points(
+            polygon
+                .points()
+                .iter()
+                .map(|q| p(q.x + x, q.y + y))
+                .collect(),
+        )
+    }
+
+    /// U shape opening upwards, with the gap between x = -1 and x = 1
+    fn u_shape() -> Polygon<Point> {
+        Polygon::from_points(vec![
+            p(-2.0, -2.0),
+            p(2.0, -2.0),
+            p(2.0, 2.0),
+            p(1.0, 2.0),
+            p(1.0, -1.0),
+            p(-1.0, -1.0),
+            p(-1.0, 2.0),
+            p(-2.0, 2.0),
+        ])
+    }
+
+    #[test]
+    fn points_in_square() {
+        let square = square(1.0);
+
+        assert_eq!(locate_point(&p(0.0, 0.0), &square), Location::Inside);
+        assert_eq!(locate_point(&p(2.0, 0.0), &square), Location::Outside);
+        // the old ray casting compared x with the y range of vertical edges
+        assert_eq!(locate_point(&p(-2.0, 0.5), &square), Location::Outside);
+        assert_eq!(locate_point(&p(0.0, 3.0), &square), Location::Outside);
+    }
+
+    #[test]
+    fn points_on_boundary() {
+        let square = square(1.0);
+
+        // vertical, horizontal edges and vertices
+        assert_eq!(locate_point(&p(1.0, 0.2), &square), Location::Boundary);
+        assert_eq!(locate_point(&p(-0.3, 1.0), &square), Location::Boundary);
+        assert_eq!(locate_point(&p(-0.3, -1.0), &square), Location::Boundary);
+        assert_eq!(locate_point(&p(1.0, 1.0), &square), Location::Boundary);
+
+        assert_eq!(boundary_edge(&p(1.0, 0.2), square.points()), Some(1));
+        assert_eq!(boundary_edge(&p(-0.3, 1.0), square.points()), Some(2));
+        assert_eq!(boundary_edge(&p(1.0, -1.0), square.points()), Some(0));
+        assert_eq!(boundary_edge(&p(0.0, 0.0), square.points()), None);
+    }
+
+    #[test]
+    fn points_level_with_vertices() {
+        // rays going exactly through vertices and along horizontal edges must not be counted twice
+        let u = u_shape();
+
+        assert_eq!(locate_point(&p(-1.5, -1.0), &u), Location::Inside);
+        assert_eq!(locate_point(&p(-3.0, -1.0), &u), Location::Outside);
+        assert_eq!(locate_point(&p(0.0, 2.0), &u), Location::Outside);
+        assert_eq!(locate_point(&p(0.0, 0.0), &u), Location::Outside);
+        assert_eq!(locate_point(&p(1.5, 0.0), &u), Location::Inside);
+        assert_eq!(locate_point(&p(3.0, -2.0), &u), Location::Outside);
+    }
+
+    #[test]
+    fn winding_follows_orientation() {
+        let square = square(1.0);
+        let reversed: Vec<_> = square.points().iter().rev().cloned().collect();
+
+        assert_eq!(winding_number(&p(0.0, 0.0), square.points()), 1);
+        assert_eq!(winding_number(&p(0.0, 0.0), &reversed), -1);
+        assert_eq!(winding_number(&p(5.0, 0.0), &reversed), 0);
+    }
+
+    #[test]
+    fn triangle_inside_square() {
+        let triangle = Polygon::from_points(vec![p(0.0, 0.5), p(-0.5, -0.5), p(0.5, -0.5)]);
+
+        assert_eq!(locate_polygon(&triangle, &square(1.0)), Location::Inside);
+    }
+
+    #[test]
+    fn triangle_inside_triangle() {
+        let t1 = Polygon::from_points(vec![p(0.0, 1.0), p(-1.0, -1.0), p(1.0, -1.0)]);
+        let t2 = Polygon::from_points(vec![p(0.0, 0.5), p(-0.5, -0.5), p(0.5, -0.5)]);
+
+        assert_eq!(locate_polygon(&t2, &t1), Location::Inside);
+        assert_eq!(locate_polygon(&t1, &t2), Location::Boundary);
+    }
+
+    #[test]
+    fn polygons_overlapping() {
+        let square = square(1.0);
+
+        assert_eq!(
+            locate_polygon(&translated(&square, 1.0, 0.5), &square),
+            Location::Boundary
+        );
+        assert_eq!(
+            locate_polygon(&translated(&square, 3.0, 0.0), &square),
+            Location::Outside
+        );
+
+        // touching is not overlapping
+        assert_eq!(
+            locate_polygon(&translated(&square, 2.0, 0.0), &square),
+            Location::Outside
+        );
+        assert_eq!(
+            locate_polygon(&translated(&square, 2.0, 2.0), &square),
+            Location::Outside
+        );
+        assert_eq!(locate_polygon(&square, &square), Location::Inside);
+    }
+
+    #[test]
+    fn polygons_across_concave_boundary() {
+        let u = u_shape();
+
+        // all vertices are inside the U, but the bar goes through the gap
+        let bar = Polygon::from_points(vec![p(-1.5, 0.0), p(1.5, 0.0), p(1.5, 0.5), p(-1.5, 0.5)]);
+        assert_eq!(locate_vertices(&bar, &u), vec![Location::Inside; 4]);
+        assert_eq!(locate_polygon(&bar, &u), Location::Boundary);
+
+        // fits exactly in the gap
+        let plug =
+            Polygon::from_points(vec![p(-1.0, -1.0), p(1.0, -1.0), p(1.0, 2.0), p(-1.0, 2.0)]);
+        assert_eq!(locate_polygon(&plug, &u), Location::Outside);
+
+        // fits in one arm, touching its sides
+        let arm = Polygon::from_points(vec![p(1.0, 0.0), p(2.0, 0.0), p(2.0, 2.0), p(1.0, 2.0)]);
+        assert_eq!(locate_polygon(&arm, &u), Location::Inside);
+    }
+}
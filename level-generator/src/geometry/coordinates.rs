@@ -0,0 +1,59 @@
+//! Points written as `(x, y)` pairs, for `#[serde(with = "...")]` on the fields holding them.
+//!
+//! Points come from voronator, which doesn't know about serde.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use voronator::delaunator::Point;
+
+pub mod point {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(p: &Point, serializer: S) -> Result<S::Ok, S::Error> {
+        (p.x, p.y).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Point, D::Error> {
+        let (x, y) = Deserialize::deserialize(deserializer)?;
+        Ok(Point { x, y })
+    }
+}
+
+/// A list of points
+pub mod points {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(points: &[Point], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(points.iter().map(|p| (p.x, p.y)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Point>, D::Error> {
+        let pairs: Vec<(f64, f64)> = Deserialize::deserialize(deserializer)?;
+        Ok(pairs.into_iter().map(|(x, y)| Point { x, y }).collect())
+    }
+}
+
+/// A list of rings
+pub mod rings {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        rings: &[Vec<Point>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            rings
+                .iter()
+                .map(|ring| ring.iter().map(|p| (p.x, p.y)).collect::<Vec<_>>()),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<Point>>, D::Error> {
+        let rings: Vec<Vec<(f64, f64)>> = Deserialize::deserialize(deserializer)?;
+        Ok(rings
+            .into_iter()
+            .map(|ring| ring.into_iter().map(|(x, y)| Point { x, y }).collect())
+            .collect())
+    }
+}
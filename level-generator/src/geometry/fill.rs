@@ -0,0 +1,358 @@
+//! Boundaries of regions described by rings that can overlap or cross themselves.
+//!
+//! The region is every point around which the rings turn counter-clockwise at least once
+//! (positive winding number). This turns raw offset curves, or several polygons laid on top of
+//! each other, into clean simple rings.
+
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::TAU;
+
+use voronator::delaunator::Point;
+
+use super::{orientation, signed_area, Intersection, Orientation, Segment};
+
+/// Distance under which two vertices are merged, relative to the size of the input
+const SNAP_EPSILON: f64 = 1e-9;
+
+/// Rings bounding the region of positive winding number of `rings`.
+///
+/// Outer boundaries are counter-clockwise, holes are clockwise.
+pub(crate) fn positive_fill(rings: &[Vec<Point>]) -> Vec<Vec<Point>> {
+    let segments: Vec<Segment> = rings
+        .iter()
+        .flat_map(|ring| {
+            (0..ring.len()).map(|i| Segment::new(&ring[i], &ring[(i + 1) % ring.len()]))
+        })
+        .filter(|s| s.length() > 0.0)
+        .collect();
+
+    if segments.is_empty() {
+        return Vec::new();
+    }
+
+    let scale = segments
+        .iter()
+        .flat_map(|s| [&s.start, &s.end])
+        .fold(0.0_f64, |m, p| m.max(p.x.abs()).max(p.y.abs()))
+        .max(1.0);
+    let tolerance = SNAP_EPSILON * scale;
+
+    // split every segment where it meets another one
+    let mut vertices: Vec<Point> = Vec::new();
+    let mut snap = |p: Point| match vertices
+        .iter()
+        .position(|v| (v.x - p.x).abs() <= tolerance && (v.y - p.y).abs() <= tolerance)
+    {
+        Some(i) => i,
+        None => {
+            vertices.push(p);
+            vertices.len() - 1
+        }
+    };
+
+    let mut edges = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let mut cuts = vec![0.0, 1.0];
+
+        for (j, other) in segments.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+
+            match segment.intersection(other) {
+                Intersection::None => {}
+                Intersection::Point { t, .. } => cuts.push(t),
+                Intersection::Overlap(overlap) => {
+                    cuts.push(segment.parameter(&overlap.start));
+                    cuts.push(segment.parameter(&overlap.end));
+                }
+            }
+        }
+
+        cuts.sort_by(|a, b| a.total_cmp(b));
+        cuts.dedup();
+
+        for w in cuts.windows(2) {
+            let (u, v) = (snap(segment.at(w[0])), snap(segment.at(w[1])));
+            if u != v {
+                edges.push((u, v));
+            }
+        }
+    }
+
+    // rounding can leave vertices right next to edges that were not split there
+    let edges: Vec<(usize, usize)> = edges
+        .into_iter()
+        .flat_map(|(u, v)| {
+            let segment = Segment::new(&vertices[u], &vertices[v]);
+            let length = segment.length();
+
+            let mut cuts: Vec<(f64, usize)> = (0..vertices.len())
+                .filter(|&w| w != u && w != v)
+                .filter_map(|w| {
+                    let t = segment.parameter(&vertices[w]);
+                    let closest = segment.at(t);
+                    let close = f64::hypot(closest.x - vertices[w].x, closest.y - vertices[w].y)
+                        <= tolerance;
+
+                    (t * length > tolerance && (1.0 - t) * length > tolerance && close)
+                        .then_some((t, w))
+                })
+                .collect();
+            cuts.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut chain = vec![u];
+            chain.extend(cuts.into_iter().map(|(_, w)| w));
+            chain.push(v);
+
+            chain.windows(2).map(|w| (w[0], w[1])).collect::<Vec<_>>()
+        })
+        .collect();
+
+    // an edge is on the boundary if the region is on its left only
+    let mut multiplicity: HashMap<(usize, usize), i32> = HashMap::new();
+    for &edge in &edges {
+        *multiplicity.entry(edge).or_default() += 1;
+    }
+
+    let boundary: HashSet<(usize, usize)> = multiplicity
+        .keys()
+        .filter(|&&(u, v)| {
+            // overlapping edges going the opposite way cancel out
+            let net = multiplicity[&(u, v)] - multiplicity.get(&(v, u)).copied().unwrap_or(0);
+            if net <= 0 {
+                return false;
+            }
+
+            // the winding of everything else is the same on both sides, and a ray going left from
+            // the middle only goes through the edge itself when starting on its right
+            let others = winding_left_of(&vertices, &edges, (u, v));
+            let (left, right) = (others, others - net);
+
+            left > 0 && right <= 0
+        })
+        .copied()
+        .collect();
+
+    trace_rings(&vertices, boundary)
+        .into_iter()
+        .map(|ring| without_collinear(&ring))
+        .filter(|ring| signed_area(ring).abs() > tolerance * scale)
+        .collect()
+}
+
+/// Removes the vertices in the middle of straight lines, left over by splitting edges
+fn without_collinear(ring: &[Point]) -> Vec<Point> {
+    let n = ring.len();
+
+    (0..n)
+        .filter(|&i| {
+            orientation(&ring[(i + n - 1) % n], &ring[i], &ring[(i + 1) % n])
+                != Orientation::Collinear
+        })
+        .map(|i| ring[i].clone())
+        .collect()
+}
+
+/// Winding number of the directed `edges` around the middle of `edge`, leaving out the ones
+/// between its ends.
+///
+/// The ray goes from the middle of the edge straight to its left, working in a frame aligned
+/// with the edge so that nearly horizontal edges are not a problem.
+fn winding_left_of(vertices: &[Point], edges: &[(usize, usize)], edge: (usize, usize)) -> i32 {
+    let (a, b) = (&vertices[edge.0], &vertices[edge.1]);
+    let length = f64::hypot(b.x - a.x, b.y - a.y);
+    let (tx, ty) = ((b.x - a.x) / length, (b.y - a.y) / length);
+    let middle = Point {
+        x: (a.x + b.x) / 2.0,
+        y: (a.y + b.y) / 2.0,
+    };
+
+    // the ray goes along +x in this frame, and the edge goes along -y
+    let frame = |p: &Point| {
+        let (dx, dy) = (p.x - middle.x, p.y - middle.y);
+        (-ty * dx + tx * dy, -(tx * dx + ty * dy))
+    };
+
+    edges
+        .iter()
+        .filter(|&&(u, v)| (u, v) != edge && (v, u) != edge)
+        .map(|&(u, v)| {
+            let (ax, ay) = frame(&vertices[u]);
+            let (bx, by) = frame(&vertices[v]);
+            // positive when the origin is on the left of the edge
+            let side = ax * by - ay * bx;
+
+            if ay <= 0.0 {
+                if by > 0.0 && side > 0.0 {
+                    return 1;
+                }
+            } else if by <= 0.0 && side < 0.0 {
+                return -1;
+            }
+
+            0
+        })
+        .sum()
+}
+
+/// Chains directed boundary `edges` between `points` into closed rings.
+///
+/// Every vertex must have as many incoming as outgoing edges. When several rings meet at the same
+/// vertex, the first edge clockwise from the incoming one is taken, so that rings never cross.
+pub(crate) fn trace_rings(
+    points: &[Point],
+    edges: impl IntoIterator<Item = (usize, usize)>,
+) -> Vec<Vec<Point>> {
+    let mut outgoing: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, j) in edges {
+        outgoing.entry(i).or_default().push(j);
+    }
+
+    let mut starts: Vec<(usize, usize)> = outgoing
+        .iter()
+        .flat_map(|(&i, targets)| targets.iter().map(move |&j| (i, j)))
+        .collect();
+    // keeps the output independent of the hash order
+    starts.sort_unstable();
+
+    let mut used = HashSet::new();
+    let mut rings = Vec::new();
+
+    for start in starts {
+        if used.contains(&start) {
+            continue;
+        }
+
+        let mut ring = Vec::new();
+        let mut edge = start;
+
+        loop {
+            used.insert(edge);
+            ring.push(points[edge.0].clone());
+
+            let next = next_edge(points, &outgoing, edge);
+            // a malformed input could otherwise loop forever
+            if next == start || used.contains(&next) {
+                break;
+            }
+            edge = next;
+        }
+
+        rings.push(ring);
+    }
+
+    rings
+}
+
+fn next_edge(
+    points: &[Point],
+    outgoing: &HashMap<usize, Vec<usize>>,
+    edge: (usize, usize),
+) -> (usize, usize) {
+    let (from, vertex) = edge;
+    let angle = |to: usize| {
+        let (v, p) = (&points[vertex], &points[to]);
+        f64::atan2(p.y - v.y, p.x - v.x)
+    };
+    let incoming = angle(from);
+
+    let targets = &outgoing[&vertex];
+    let next = if targets.len() == 1 {
+        targets[0]
+    } else {
+        *targets
+            .iter()
+            .min_by(|&&a, &&b| {
+                let a = (incoming - angle(a)).rem_euclid(TAU);
+                let b = (incoming - angle(b)).rem_euclid(TAU);
+                a.total_cmp(&b)
+            })
+            .unwrap()
+    };
+
+    (vertex, next)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::test_util::p;
+
+    fn square(x: f64, y: f64, size: f64) -> Vec<Point> {
+        vec![
+            p(x, y),
+            p(x + size, y),
+            p(x + size, y + size),
+            p(x, y + size),
+        ]
+    }
+
+    fn areas(rings: &[Vec<Point>]) -> Vec<f64> {
+        let mut areas: Vec<_> = rings.iter().map(|r| signed_area(r)).collect();
+        areas.sort_by(|a, b| a.total_cmp(b));
+        areas
+    }
+
+    #[test]
+    fn simple_ring_is_unchanged() {
+        let rings = positive_fill(&[square(0.0, 0.0, 1.0)]);
+
+        assert_eq!(rings.len(), 1);
+        assert_eq!(rings[0].len(), 4);
+        assert!((signed_area(&rings[0]) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn clockwise_ring_is_empty() {
+        let mut ring = square(0.0, 0.0, 1.0);
+        ring.reverse();
+
+        assert!(positive_fill(&[ring]).is_empty());
+    }
+
+    #[test]
+    fn overlapping_rings_are_merged() {
+        let rings = positive_fill(&[square(0.0, 0.0, 2.0), square(1.0, 1.0, 2.0)]);
+
+        assert_eq!(rings.len(), 1);
+        assert_eq!(rings[0].len(), 8);
+        assert!((signed_area(&rings[0]) - 7.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rings_sharing_an_edge() {
+        let rings = positive_fill(&[square(0.0, 0.0, 1.0), square(1.0, 0.0, 1.0)]);
+
+        assert_eq!(rings.len(), 1);
+        assert_eq!(rings[0].len(), 4);
+        assert!((signed_area(&rings[0]) - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn figure_eight() {
+        // the clockwise lobe is dropped
+        let ring = vec![p(0.0, 0.0), p(2.0, 2.0), p(2.0, 0.0), p(0.0, 2.0)];
+        let rings = positive_fill(&[ring]);
+
+        assert_eq!(rings.len(), 1);
+        assert!((signed_area(&rings[0]) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn frame_has_a_hole() {
+        // four overlapping bars around a courtyard
+        let bars = vec![
+            vec![p(0.0, 0.0), p(4.0, 0.0), p(4.0, 1.0), p(0.0, 1.0)],
+            vec![p(3.0, 0.0), p(4.0, 0.0), p(4.0, 4.0), p(3.0, 4.0)],
+            vec![p(0.0, 3.0), p(4.0, 3.0), p(4.0, 4.0), p(0.0, 4.0)],
+            vec![p(0.0, 0.0), p(1.0, 0.0), p(1.0, 4.0), p(0.0, 4.0)],
+        ];
+
+        let rings = positive_fill(&bars);
+
+        assert_eq!(areas(&rings), vec![-4.0, 16.0]);
+    }
+}
@@ -0,0 +1,328 @@
+//! Polygon offsetting: growing or shrinking a polygon by a fixed distance.
+//!
+//! Every edge is moved along its normal, the gaps opened at the corners are filled according to
+//! the `Join` style, and the self-intersections of the resulting curve are cleaned up, so that
+//! concave polygons keep their shape instead of being distorted like with a plain scaling.
+
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use super::{counter_clockwise, fill::positive_fill};
+
+/// How the corners of the offset polygon are shaped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Join {
+    /// Sharp corners, cut flat when they would stick out further than `limit` times the distance
+    Miter { limit: f64 },
+    /// Circular arcs, never further than `tolerance` from the exact curve
+    Round { tolerance: f64 },
+    /// Corners cut flat
+    Bevel,
+}
+
+/// Offsets `polygon` by `distance`, growing it if positive and shrinking it if negative.
+///
+/// The result can be several polygons: shrinking can split a polygon in pieces or make it vanish
+/// entirely, and growing can merge parts together. Outer boundaries are counter-clockwise, and the
+/// holes that growing can close up are clockwise.
+pub fn offset(polygon: &Polygon<Point>, distance: f64, join: Join) -> Vec<Polygon<Point>> {
+    let points = counter_clockwise(polygon.points());
+
+    if points.len() < 3 {
+        return Vec::new();
+    }
+    if distance == 0.0 {
+        return vec![Polygon::from_points(points)];
+    }
+
+    let n = points.len();
+    let normals: Vec<(f64, f64)> = (0..n)
+        .map(|i| outward_normal(&points[i], &points[(i + 1) % n]))
+        .collect();
+
+    let mut raw = Vec::new();
+
+    for i in 0..n {
+        let vertex = &points[i];
+        let before = normals[(i + n - 1) % n];
+        let after = normals[i];
+
+        let shifted = |(nx, ny): (f64, f64)| Point {
+            x: vertex.x + distance * nx,
+            y: vertex.y + distance * ny,
+        };
+
+        // signed angle going from one normal to the other
+        let turn = f64::atan2(
+            before.0 * after.1 - before.1 * after.0,
+            before.0 * after.0 + before.1 * after.1,
+        );
+
+        if turn * distance > 0.0 {
+            // the offset edges move apart
+            raw.push(shifted(before));
+            join_corner(&mut raw, vertex, before, turn, distance, join);
+            raw.push(shifted(after));
+        } else if turn != 0.0 {
+            // the offset edges overlap, going through the vertex makes a loop that the cleanup
+            // removes
+            raw.push(shifted(before));
+            raw.push(vertex.clone());
+            raw.push(shifted(after));
+        } else {
+            raw.push(shifted(after));
+        }
+    }
+
+    positive_fill(&[raw])
+        .into_iter()
+        .map(Polygon::from_points)
+        .collect()
+}
+
+/// Unit normal pointing to the outside of a counter-clockwise polygon
+fn outward_normal(a: &Point, b: &Point) -> (f64, f64) {
+    let length = f64::hypot(b.x - a.x, b.y - a.y);
+    ((b.y - a.y) / length, -(b.x - a.x) / length)
+}
+
+/// Adds the points strictly between the two shifted ends of the edges meeting at `vertex`
+fn join_corner(
+    raw: &mut Vec<Point>,
+    vertex: &Point,
+    before: (f64, f64),
+    turn: f64,
+    distance: f64,
+    join: Join,
+) {
+    let rotated = |angle: f64| {
+        let (sin, cos) = angle.sin_cos();
+        Point {
+            x: vertex.x + distance * (before.0 * cos - before.1 * sin),
+            y: vertex.y + distance * (before.0 * sin + before.1 * cos),
+        }
+    };
+
+    match join {
+        Join::Bevel => {}
+        Join::Miter { limit } => {
+            // the tip is at 1 / cos(turn / 2) times the distance
+            let ratio = 1.0 / (turn / 2.0).cos();
+
+            if ratio <= limit {
+                let tip = rotated(turn / 2.0);
+                raw.push(Point {
+                    x: vertex.x + (tip.x - vertex.x) * ratio,
+                    y: vertex.y + (tip.y - vertex.y) * ratio,
+                });
+            }
+        }
+        Join::Round { tolerance } => {
+            let radius = distance.abs();
+            // largest step keeping the chords within the tolerance
+            let step = if tolerance >= radius {
+                std::f64::consts::PI
+            } else {
+                2.0 * (1.0 - tolerance / radius).acos()
+            };
+            let steps = (turn.abs() / step).ceil() as usize;
+
+            for k in 1..steps {
+                raw.push(rotated(turn * k as f64 / steps as f64));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    use crate::{
+        geometry::{
+            containment::{locate_point, Location},
+            signed_area,
+        },
+        test_util::{p, square},
+    };
+
+    /// U shape opening upwards, with the gap between x = -1 and x = 1
+    fn u_shape() -> Polygon<Point> {
+        Polygon::from_points(vec![
+            p(-2.0, -2.0),
+            p(2.0, -2.0),
+            p(2.0, 2.0),
+            p(1.0, 2.0),
+            p(1.0, -1.0),
+            p(-1.0, -1.0),
+            p(-1.0, 2.0),
+            p(-2.0, 2.0),
+        ])
+    }
+
+    fn area(polygons: &[Polygon<Point>]) -> f64 {
+        polygons.iter().map(|p| signed_area(p.points())).sum()
+    }
+
+    #[test]
+    fn grow_square() {
+        let square = square(1.0);
+
+        let miter = offset(&square, 0.5, Join::Miter { limit: 2.0 });
+        assert_eq!(miter.len(), 1);
+        assert_eq!(miter[0].points().len(), 4);
+        assert!((area(&miter) - 9.0).abs() < 1e-12);
+
+        let bevel = offset(&square, 0.5, Join::Bevel);
+        assert_eq!(bevel[0].points().len(), 8);
+        assert!((area(&bevel) - (9.0 - 4.0 * 0.125)).abs() < 1e-12);
+
+        let round = offset(&square, 0.5, Join::Round { tolerance: 1e-4 });
+        assert!((area(&round) - (4.0 + 4.0 * 2.0 * 0.5 + PI * 0.25)).abs() < 1e-3);
+        for q in round[0].points() {
+            let (dx, dy) = ((q.x.abs() - 1.0).max(0.0), (q.y.abs() - 1.0).max(0.0));
+            assert!((f64::hypot(dx, dy) - 0.5).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn miter_limit() {
+        // the corners of a square stick out sqrt(2) times the distance
+        let cut = offset(&square(1.0), 0.5, Join::Miter { limit: 1.4 });
+        assert_eq!(cut[0].points().len(), 8);
+    }
+
+    #[test]
+    fn shrink_square() {
+        let shrunk = offset(&square(1.0), -0.25, Join::Round { tolerance: 1e-3 });
+
+        assert_eq!(shrunk.len(), 1);
+        assert_eq!(shrunk[0].points().len(), 4);
+        assert!((area(&shrunk) - 2.25).abs() < 1e-12);
+
+        assert!(offset(&square(1.0), -1.5, Join::Bevel).is_empty());
+    }
+
+    #[test]
+    fn clockwise_input() {
+        let clockwise = Polygon::from_points(square(1.0).points().iter().rev().cloned().collect());
+
+        let grown = offset(&clockwise, 0.5, Join::Miter { limit: 2.0 });
+        assert!((area(&grown) - 9.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn concave_keeps_its_shape() {
+        let u = u_shape();
+        let grown = offset(&u, 0.25, Join::Miter { limit: 2.0 });
+
+        assert_eq!(grown.len(), 1);
+        assert_eq!(grown[0].points().len(), 8);
+        // 4.5 x 4.5 minus the narrower gap
+        assert!((area(&grown) - (4.5 * 4.5 - 1.5 * 3.0)).abs() < 1e-12);
+
+        // unlike scaling, all the walls are the same distance away
+        assert_eq!(locate_point(&p(0.0, 0.0), &grown[0]), Location::Outside);
+        assert_eq!(locate_point(&p(0.8, 0.0), &grown[0]), Location::Inside);
+        assert_eq!(locate_point(&p(0.7, 0.0), &grown[0]), Location::Outside);
+    }
+
+    #[test]
+    fn growing_closes_gaps() {
+        // the arms are 2 apart, so growing by more than 1 fills the gap
+        let grown = offset(&u_shape(), 1.25, Join::Miter { limit: 2.0 });
+
+        assert_eq!(grown.len(), 1);
+        assert!((area(&grown) - 6.5 * 6.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn growing_makes_holes() {
+        // a C shape whose opening is narrower than its inside
+        let c = Polygon::from_points(vec![
+            p(0.0, 0.0),
+            p(5.0, 0.0),
+            p(5.0, 2.0),
+            p(4.0, 2.0),
+            p(4.0, 1.0),
+            p(1.0, 1.0),
+            p(1.0, 4.0),
+            p(4.0, 4.0),
+            p(4.0, 3.0),
+            p(5.0, 3.0),
+            p(5.0, 5.0),
+            p(0.0, 5.0),
+        ]);
+
+        let grown = offset(&c, 0.75, Join::Miter { limit: 2.0 });
+
+        assert_eq!(grown.len(), 2);
+        let hole = grown
+            .iter()
+            .find(|p| signed_area(p.points()) < 0.0)
+            .unwrap();
+        assert!((signed_area(hole.points()) + 1.5 * 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn shrinking_splits() {
+        // a dumbbell with a thin handle
+        let dumbbell = Polygon::from_points(vec![
+            p(0.0, 0.0),
+            p(2.0, 0.0),
+            p(2.0, 0.9),
+            p(4.0, 0.9),
+            p(4.0, 0.0),
+            p(6.0, 0.0),
+            p(6.0, 2.0),
+            p(4.0, 2.0),
+            p(4.0, 1.1),
+            p(2.0, 1.1),
+            p(2.0, 2.0),
+            p(0.0, 2.0),
+        ]);
+
+        let shrunk = offset(&dumbbell, -0.5, Join::Miter { limit: 2.0 });
+
+        assert_eq!(shrunk.len(), 2);
+        for piece in &shrunk {
+            assert!((signed_area(piece.points()) - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn random_star_polygons() {
+        use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+        let mut rng = SmallRng::seed_from_u64(8);
+
+        for _ in 0..50 {
+            let n = rng.gen_range(5..20);
+            let star = Polygon::from_points(
+                (0..n)
+                    .map(|i| {
+                        let angle = i as f64 / n as f64 * 2.0 * PI;
+                        let radius = rng.gen_range(0.3..1.0);
+                        p(radius * angle.cos(), radius * angle.sin())
+                    })
+                    .collect(),
+            );
+            let distance = rng.gen_range(0.01..0.2);
+
+            let grown = offset(&star, distance, Join::Round { tolerance: 1e-3 });
+            assert_eq!(grown.len(), 1);
+            for q in star.points() {
+                assert_eq!(locate_point(q, &grown[0]), Location::Inside);
+            }
+
+            for piece in offset(&star, -distance, Join::Miter { limit: 2.0 }) {
+                assert!(signed_area(piece.points()) > 0.0);
+                for q in piece.points() {
+                    assert_eq!(locate_point(q, &star), Location::Inside);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Triangulation of simple polygons by ear clipping.
+//!
+//! Unlike a fan from the first vertex, this works for concave polygons too, such as merged
+//! rooms, which is what meshes need.
+
+use voronator::delaunator::Point;
+
+use super::{orientation, signed_area, Orientation};
+
+/// Counter-clockwise triangles covering the simple polygon `points`, as indices in `points`.
+///
+/// The polygon can turn either way. Collinear vertices don't make triangles of their own, so
+/// there are at most `points.len() - 2` of them.
+pub fn ear_clipping(points: &[Point]) -> Vec<[usize; 3]> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    if signed_area(points) < 0.0 {
+        remaining.reverse();
+    }
+
+    let mut triangles = Vec::new();
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let corner = |i: usize| {
+            (
+                remaining[(i + n - 1) % n],
+                remaining[i],
+                remaining[(i + 1) % n],
+            )
+        };
+
+        // flat corners are dropped first, they would only make empty triangles
+        let flat = (0..n).find(|&i| {
+            let (a, b, c) = corner(i);
+            orientation(&points[a], &points[b], &points[c]) == Orientation::Collinear
+        });
+        if let Some(i) = flat {
+            remaining.remove(i);
+            continue;
+        }
+
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = corner(i);
+
+            orientation(&points[a], &points[b], &points[c]) == Orientation::CounterClockwise
+                && remaining.iter().all(|&q| {
+                    [a, b, c].contains(&q)
+                        || [a, b, c].iter().any(|&v| points[v] == points[q])
+                        || !inside_triangle(&points[q], &points[a], &points[b], &points[c])
+                })
+        });
+
+        // only self-intersecting polygons have no ear, the first convex corner is the best bet
+        let i = ear
+            .or_else(|| {
+                (0..n).find(|&i| {
+                    let (a, b, c) = corner(i);
+                    orientation(&points[a], &points[b], &points[c]) == Orientation::CounterClockwise
+                })
+            })
+            .unwrap_or(0);
+
+        let (a, b, c) = corner(i);
+        triangles.push([a, b, c]);
+        remaining.remove(i);
+    }
+
+    if let [a, b, c] = remaining[..] {
+        if orientation(&points[a], &points[b], &points[c]) == Orientation::CounterClockwise {
+            triangles.push([a, b, c]);
+        }
+    }
+
+    triangles
+}
+
+/// Whether `p` lies inside or on the counter-clockwise triangle `a`, `b`, `c`
+fn inside_triangle(p: &Point, a: &Point, b: &Point, c: &Point) -> bool {
+    [(a, b), (b, c), (c, a)]
+        .into_iter()
+        .all(|(start, end)| orientation(start, end, p) != Orientation::Clockwise)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::test_util::p;
+
+    fn area(points: &[Point], triangles: &[[usize; 3]]) -> f64 {
+        triangles
+            .iter()
+            .map(|t| {
+                let area = signed_area(&[
+                    points[t[0]].clone(),
+                    points[t[1]].clone(),
+                    points[t[2]].clone(),
+                ]);
+                assert!(area > 0.0);
+                area
+            })
+            .sum()
+    }
+
+    #[test]
+    fn convex_polygon() {
+        let square = [p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0), p(0.0, 1.0)];
+        let triangles = ear_clipping(&square);
+
+        assert_eq!(triangles.len(), 2);
+        assert!((area(&square, &triangles) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn concave_polygon() {
+        // an L, its reflex corner at (1, 1), clockwise
+        let mut l = vec![
+            p(0.0, 0.0),
+            p(2.0, 0.0),
+            p(2.0, 1.0),
+            p(1.0, 1.0),
+            p(1.0, 2.0),
+            p(0.0, 2.0),
+        ];
+        l.reverse();
+
+        let triangles = ear_clipping(&l);
+
+        assert_eq!(triangles.len(), 4);
+        assert!((area(&l, &triangles) - 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn collinear_vertices() {
+        let square = [
+            p(0.0, 0.0),
+            p(0.5, 0.0),
+            p(1.0, 0.0),
+            p(1.0, 1.0),
+            p(0.0, 1.0),
+        ];
+        let triangles = ear_clipping(&square);
+
+        assert_eq!(triangles.len(), 2);
+        assert!((area(&square, &triangles) - 1.0).abs() < 1e-12);
+    }
+}
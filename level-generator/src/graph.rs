@@ -0,0 +1,452 @@
+//! Adjacency between rooms.
+//!
+//! Two rooms are neighbours when their polygons share a piece of boundary, which becomes a wall
+//! between them. The walls are found from the geometry of the cells only, so the graph stays
+//! right after the cells are clipped, merged or built by something else than a Voronoi diagram.
+
+pub mod corridors;
+pub mod doors;
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use crate::{
+    geometry::{bounding_box, centroid, distance, Segment},
+    voronoi::Cells,
+};
+
+/// Distance under which two edges are considered to lie on the same line
+const WALL_EPSILON: f64 = 1e-7;
+
+/// Piece of boundary shared by two rooms
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wall {
+    /// The two rooms, the lowest index first
+    pub rooms: (usize, usize),
+    pub segment: Segment,
+}
+
+impl Wall {
+    pub fn length(&self) -> f64 {
+        self.segment.length()
+    }
+
+    /// The room on the other side of the wall from `room`
+    pub fn other(&self, room: usize) -> usize {
+        if self.rooms.0 == room {
+            self.rooms.1
+        } else {
+            self.rooms.0
+        }
+    }
+
+    pub fn middle(&self) -> Point {
+        self.segment.at(0.5)
+    }
+}
+
+pub struct RoomGraph {
+    centers: Vec<Point>,
+    walls: Vec<Wall>,
+    /// Walls around each room
+    room_walls: Vec<Vec<usize>>,
+}
+
+impl RoomGraph {
+    /// Finds the walls between `cells`, room `i` being the `i`-th cell
+    pub fn new(cells: &Cells) -> Self {
+        let polygons: Vec<&Polygon<Point>> = cells.iter_cells().collect();
+        let boxes: Vec<_> = polygons.iter().map(|p| bounding_box(p.points())).collect();
+
+        let mut walls = Vec::new();
+
+        for i in 0..polygons.len() {
+            for j in i + 1..polygons.len() {
+                if !boxes_touch(&boxes[i], &boxes[j]) {
+                    continue;
+                }
+
+                walls.extend(
+                    shared_segments(polygons[i].points(), polygons[j].points())
+                        .into_iter()
+                        .map(|segment| Wall {
+                            rooms: (i, j),
+                            segment,
+                        }),
+                );
+            }
+        }
+
+        let mut room_walls = vec![Vec::new(); polygons.len()];
+        for (w, wall) in walls.iter().enumerate() {
+            room_walls[wall.rooms.0].push(w);
+            room_walls[wall.rooms.1].push(w);
+        }
+
+        let centers = polygons
+            .iter()
+            .map(|p| {
+                centroid(p.points()).unwrap_or_else(|| {
+                    p.points()
+                        .first()
+                        .cloned()
+                        .unwrap_or(Point { x: 0.0, y: 0.0 })
+                })
+            })
+            .collect();
+
+        Self {
+            centers,
+            walls,
+            room_walls,
+        }
+    }
+
+    pub fn room_count(&self) -> usize {
+        self.centers.len()
+    }
+
+    /// Centroid of each room
+    pub fn centers(&self) -> &[Point] {
+        &self.centers
+    }
+
+    pub fn walls(&self) -> &[Wall] {
+        &self.walls
+    }
+
+    /// Walls around `room`
+    pub fn room_walls(&self, room: usize) -> impl Iterator<Item = &Wall> + '_ {
+        self.room_walls[room].iter().map(|&w| &self.walls[w])
+    }
+
+    /// Rooms sharing a wall with `room`, each of them once
+    pub fn neighbours(&self, room: usize) -> Vec<usize> {
+        let mut neighbours: Vec<usize> = self.room_walls(room).map(|w| w.other(room)).collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        neighbours
+    }
+
+    /// Longest wall between `a` and `b`, if they are neighbours
+    pub fn wall_between(&self, a: usize, b: usize) -> Option<&Wall> {
+        self.room_walls(a)
+            .filter(|w| w.other(a) == b)
+            .max_by(|x, y| x.length().total_cmp(&y.length()))
+    }
+
+    /// Shortest way from room `from` to room `to`, with both of them included.
+    ///
+    /// Going through a wall costs the distance from the center of the room to the middle of the
+    /// wall, then to the center of the next room.
+    pub fn shortest_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        self.shortest_path_through(from, to, |_| true)
+    }
+
+    /// Same as `shortest_path`, only going through the walls accepted by `passable`
+    pub fn shortest_path_through(
+        &self,
+        from: usize,
+        to: usize,
+        passable: impl Fn(&Wall) -> bool,
+    ) -> Option<Vec<usize>> {
+        let mut cost = vec![f64::INFINITY; self.room_count()];
+        let mut previous = vec![None; self.room_count()];
+        let mut queue = BinaryHeap::new();
+
+        cost[from] = 0.0;
+        queue.push(Visit {
+            cost: 0.0,
+            room: from,
+        });
+
+        while let Some(Visit { cost: c, room }) = queue.pop() {
+            if room == to {
+                break;
+            }
+            if c > cost[room] {
+                continue;
+            }
+
+            for wall in self.room_walls(room).filter(|w| passable(w)) {
+                let next = wall.other(room);
+                let middle = wall.middle();
+                let step =
+                    distance(&self.centers[room], &middle) + distance(&middle, &self.centers[next]);
+
+                if c + step < cost[next] {
+                    cost[next] = c + step;
+                    previous[next] = Some(room);
+                    queue.push(Visit {
+                        cost: c + step,
+                        room: next,
+                    });
+                }
+            }
+        }
+
+        if cost[to] == f64::INFINITY {
+            return None;
+        }
+
+        let mut path = vec![to];
+        while let Some(room) = previous[*path.last().unwrap()] {
+            path.push(room);
+        }
+        path.reverse();
+
+        Some(path)
+    }
+
+    /// Groups of rooms reachable from each other, each of them sorted
+    pub fn connected_components(&self) -> Vec<Vec<usize>> {
+        self.connected_components_through(|_| true)
+    }
+
+    /// Same as `connected_components`, only going through the walls accepted by `passable`
+    pub fn connected_components_through(
+        &self,
+        passable: impl Fn(&Wall) -> bool,
+    ) -> Vec<Vec<usize>> {
+        let mut component = vec![None; self.room_count()];
+        let mut components = Vec::new();
+
+        for start in 0..self.room_count() {
+            if component[start].is_some() {
+                continue;
+            }
+
+            let mut rooms = vec![start];
+            let mut stack = vec![start];
+            component[start] = Some(components.len());
+
+            while let Some(room) = stack.pop() {
+                for wall in self.room_walls(room).filter(|w| passable(w)) {
+                    let next = wall.other(room);
+
+                    if component[next].is_none() {
+                        component[next] = Some(components.len());
+                        rooms.push(next);
+                        stack.push(next);
+                    }
+                }
+            }
+
+            rooms.sort_unstable();
+            components.push(rooms);
+        }
+
+        components
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected_components().len() <= 1
+    }
+}
+
+/// Room waiting in Dijkstra's queue, the cheapest first
+struct Visit {
+    cost: f64,
+    room: usize,
+}
+
+impl PartialEq for Visit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Visit {}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.room.cmp(&self.room))
+    }
+}
+
+/// Pieces of boundary that `a` and `b` have in common
+fn shared_segments(a: &[Point], b: &[Point]) -> Vec<Segment> {
+    let mut shared = Vec::new();
+
+    for i in 0..a.len() {
+        let edge = Segment::new(&a[i], &a[(i + 1) % a.len()]);
+        let length = edge.length();
+
+        if length <= WALL_EPSILON {
+            continue;
+        }
+
+        for j in 0..b.len() {
+            let other = Segment::new(&b[j], &b[(j + 1) % b.len()]);
+
+            if line_distance(&edge, &other.start) > WALL_EPSILON
+                || line_distance(&edge, &other.end) > WALL_EPSILON
+            {
+                continue;
+            }
+
+            let (t, u) = (edge.parameter(&other.start), edge.parameter(&other.end));
+            let (start, end) = (t.min(u).max(0.0), t.max(u).min(1.0));
+
+            if (end - start) * length > WALL_EPSILON {
+                shared.push(Segment::new(&edge.at(start), &edge.at(end)));
+            }
+        }
+    }
+
+    shared
+}
+
+/// Distance from `p` to the line going through `segment`
+fn line_distance(segment: &Segment, p: &Point) -> f64 {
+    let (dx, dy) = (
+        segment.end.x - segment.start.x,
+        segment.end.y - segment.start.y,
+    );
+
+    ((p.x - segment.start.x) * dy - (p.y - segment.start.y) * dx).abs() / f64::hypot(dx, dy)
+}
+
+fn boxes_touch(a: &(Point, Point), b: &(Point, Point)) -> bool {
+    a.0.x <= b.1.x + WALL_EPSILON
+        && b.0.x <= a.1.x + WALL_EPSILON
+        && a.0.y <= b.1.y + WALL_EPSILON
+        && b.0.y <= a.1.y + WALL_EPSILON
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        random_points,
+        test_util::{p, square},
+        voronoi::{fortune, generate_voronoi},
+    };
+
+    /// Cells of a `size` x `size` grid of sites in the [-1, 1] square, row by row
+    fn grid(size: usize) -> Cells {
+        let step = 2.0 / size as f64;
+        let sites: Vec<Point> = (0..size * size)
+            .map(|i| {
+                p(
+                    -1.0 + step * ((i % size) as f64 + 0.5),
+                    -1.0 + step * ((i / size) as f64 + 0.5),
+                )
+            })
+            .collect();
+
+        fortune::compute(&sites).cells(&square(1.0))
+    }
+
+    #[test]
+    fn grid_walls() {
+        let graph = RoomGraph::new(&grid(4));
+
+        assert_eq!(graph.room_count(), 16);
+        assert_eq!(graph.walls().len(), 2 * 4 * 3);
+
+        for wall in graph.walls() {
+            assert!((wall.length() - 0.5).abs() < 1e-9);
+        }
+
+        assert_eq!(graph.neighbours(0), vec![1, 4]);
+        assert_eq!(graph.neighbours(5), vec![1, 4, 6, 9]);
+        // diagonal rooms only touch at a corner
+        assert!(graph.wall_between(0, 5).is_none());
+
+        let wall = graph.wall_between(5, 6).unwrap();
+        assert_eq!(wall.rooms, (5, 6));
+        assert!((wall.middle().x - 0.0).abs() < 1e-9);
+        assert!((wall.middle().y + 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn grid_paths() {
+        let graph = RoomGraph::new(&grid(4));
+
+        let path = graph.shortest_path(0, 15).unwrap();
+        assert_eq!(path.len(), 7);
+        assert_eq!(path[0], 0);
+        assert_eq!(path[6], 15);
+        for w in path.windows(2) {
+            assert!(graph.neighbours(w[0]).contains(&w[1]));
+        }
+
+        assert_eq!(graph.shortest_path(3, 3), Some(vec![3]));
+
+        // walling off the second column forces a detour through the top row
+        let blocked = |w: &Wall| !(w.rooms == (0, 1) || w.rooms == (4, 5) || w.rooms == (8, 9));
+        let detour = graph.shortest_path_through(0, 1, blocked).unwrap();
+        assert_eq!(detour, vec![0, 4, 8, 12, 13, 9, 5, 1]);
+    }
+
+    #[test]
+    fn components() {
+        let graph = RoomGraph::new(&grid(3));
+        assert!(graph.is_connected());
+
+        // cutting the middle column off
+        let components = graph
+            .connected_components_through(|w| w.rooms.1 - w.rooms.0 != 1 || w.rooms.0 % 3 == 1);
+        assert_eq!(components, vec![vec![0, 3, 6], vec![1, 2, 4, 5, 7, 8]]);
+
+        let nothing = graph.connected_components_through(|_| false);
+        assert_eq!(nothing.len(), 9);
+        assert!(graph.shortest_path_through(0, 8, |_| false).is_none());
+    }
+
+    #[test]
+    fn same_neighbours_as_the_diagram() {
+        let points = random_points(100);
+        let diagram = fortune::compute(&points);
+        let bounds = square(2.0);
+
+        let graph = RoomGraph::new(&diagram.cells(&bounds));
+
+        // no wall without an edge of the diagram
+        for wall in graph.walls() {
+            assert!(diagram.neighbours(wall.rooms.0).any(|n| n == wall.rooms.1));
+        }
+
+        // and every edge of the diagram inside the bounds is a wall
+        for edge in diagram.half_edges() {
+            let (Some(origin), Some(target)) = (edge.origin, edge.target) else {
+                continue;
+            };
+            let (origin, target) = (&diagram.vertices()[origin], &diagram.vertices()[target]);
+
+            let inside = |q: &Point| q.x.abs() < 2.0 && q.y.abs() < 2.0;
+            if inside(origin) && inside(target) && distance(origin, target) > 1e-6 {
+                let other = diagram.half_edges()[edge.twin].site;
+                let wall = graph.wall_between(edge.site, other).unwrap();
+
+                assert!((wall.length() - distance(origin, target)).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn clipped_level_is_connected() {
+        let cells = generate_voronoi(random_points(50));
+        let graph = RoomGraph::new(&cells);
+
+        assert!(graph.is_connected());
+        for wall in graph.walls() {
+            assert!(graph.neighbours(wall.rooms.0).contains(&wall.rooms.1));
+            assert!(graph.neighbours(wall.rooms.1).contains(&wall.rooms.0));
+        }
+    }
+}
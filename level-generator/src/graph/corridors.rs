@@ -0,0 +1,631 @@
+//! Corridors between rooms pulled apart from each other.
+//!
+//! Voronoi cells fill the whole level, leaving no room for halls. Shrinking every room opens gaps
+//! along the walls, and corridors can then run between the rooms, either in those gaps (along the
+//! Voronoi edges) or straight from one room to the next (along the Delaunay edges between them).
+
+use std::{
+    collections::{BinaryHeap, HashMap},
+    fmt,
+};
+
+use serde::{Deserialize, Serialize};
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use super::{RoomGraph, Visit, Wall};
+use crate::{
+    geometry::{
+        bounding_box, counter_clockwise, distance,
+        fill::positive_fill,
+        offset::{offset, Join},
+        signed_area,
+    },
+    voronoi::{delaunay::Triangulation, Cells},
+};
+
+/// Where corridors go
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Route {
+    /// Along the walls between the rooms, in the gaps left by shrinking them
+    Voronoi,
+    /// From the center of a room to the center of the next one
+    Delaunay,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CorridorParams {
+    /// How much the rooms shrink
+    pub inset: f64,
+    pub width: f64,
+    pub route: Route,
+}
+
+impl Default for CorridorParams {
+    fn default() -> Self {
+        Self {
+            inset: 0.02,
+            width: 0.04,
+            route: Route::Voronoi,
+        }
+    }
+}
+
+pub struct Corridor {
+    /// The rooms linked by the corridor
+    pub rooms: (usize, usize),
+    /// Middle line of the corridor
+    pub path: Vec<Point>,
+    /// The corridor itself, counter-clockwise, without the parts overlapping the rooms.
+    ///
+    /// A corridor running through other rooms on its way is cut in several pieces.
+    pub polygons: Vec<Polygon<Point>>,
+}
+
+/// Shrunk rooms with the corridors between them
+pub struct Layout {
+    /// Each of the rooms shrunk by `inset`, empty when it vanishes
+    pub rooms: Vec<Polygon<Point>>,
+    pub corridors: Vec<Corridor>,
+}
+
+// voronator's polygons are neither `Clone` nor `Debug`
+
+impl Clone for Corridor {
+    fn clone(&self) -> Self {
+        Self {
+            rooms: self.rooms,
+            path: self.path.clone(),
+            polygons: copy(&self.polygons),
+        }
+    }
+}
+
+impl fmt::Debug for Corridor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Corridor")
+            .field("rooms", &self.rooms)
+            .field("path", &self.path)
+            .field("polygons", &points(&self.polygons))
+            .finish()
+    }
+}
+
+impl Clone for Layout {
+    fn clone(&self) -> Self {
+        Self {
+            rooms: copy(&self.rooms),
+            corridors: self.corridors.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Layout")
+            .field("rooms", &points(&self.rooms))
+            .field("corridors", &self.corridors)
+            .finish()
+    }
+}
+
+fn copy(polygons: &[Polygon<Point>]) -> Vec<Polygon<Point>> {
+    polygons
+        .iter()
+        .map(|polygon| Polygon::from_points(polygon.points().to_vec()))
+        .collect()
+}
+
+fn points(polygons: &[Polygon<Point>]) -> Vec<&[Point]> {
+    polygons.iter().map(|polygon| polygon.points()).collect()
+}
+
+/// Shrinks `cells` and links the pairs of rooms of `links` with corridors.
+///
+/// The rooms of a link don't need to be neighbours, the corridor then goes through the rooms of
+/// the shortest path between them. With [`Route::Voronoi`], a `width` of at least twice the
+/// `inset` makes the corridors reach the rooms along their walls.
+pub fn corridors(cells: &Cells, links: &[(usize, usize)], params: &CorridorParams) -> Layout {
+    let graph = cells.room_graph();
+
+    let rooms: Vec<Polygon<Point>> = cells
+        .iter_cells()
+        .map(|cell| {
+            offset(cell, -params.inset, Join::Miter { limit: 2.0 })
+                .into_iter()
+                .max_by(|a, b| signed_area(a.points()).total_cmp(&signed_area(b.points())))
+                .unwrap_or_else(Polygon::new)
+        })
+        .collect();
+
+    let network = match params.route {
+        Route::Voronoi => Network::walls(&graph),
+        Route::Delaunay => Network::delaunay(&graph),
+    };
+
+    let corridors = links
+        .iter()
+        .filter_map(|&(from, to)| {
+            let path = match params.route {
+                Route::Voronoi => network.route(&graph, from, to)?,
+                Route::Delaunay => {
+                    let (from, to) = (&graph.centers()[from], &graph.centers()[to]);
+                    network.walk(from, to)?.1
+                }
+            };
+
+            // corridors along the walls stop flush at the edge of the level
+            let cap = |p: &Point| match params.route {
+                Route::Voronoi => network.degree(p) > 1,
+                Route::Delaunay => true,
+            };
+
+            let polygons = cut_rooms(thicken(&path, params.width, cap), &rooms);
+
+            Some(Corridor {
+                rooms: (from, to),
+                path,
+                polygons,
+            })
+        })
+        .collect();
+
+    Layout { rooms, corridors }
+}
+
+/// Rings covering the band of `width` around `path`, with a square around the vertices accepted
+/// by `cap`, which covers the joints
+fn thicken(path: &[Point], width: f64, cap: impl Fn(&Point) -> bool) -> Vec<Vec<Point>> {
+    let half = width / 2.0;
+    let mut rings = Vec::new();
+
+    for pair in path.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let length = distance(a, b);
+
+        if length == 0.0 {
+            continue;
+        }
+
+        // normal to the segment, on its left
+        let (nx, ny) = (-(b.y - a.y) / length * half, (b.x - a.x) / length * half);
+
+        rings.push(vec![
+            Point {
+                x: a.x - nx,
+                y: a.y - ny,
+            },
+            Point {
+                x: b.x - nx,
+                y: b.y - ny,
+            },
+            Point {
+                x: b.x + nx,
+                y: b.y + ny,
+            },
+            Point {
+                x: a.x + nx,
+                y: a.y + ny,
+            },
+        ]);
+    }
+
+    for (i, p) in path.iter().enumerate() {
+        let inside = i > 0 && i < path.len() - 1;
+
+        if inside || path.len() == 1 || cap(p) {
+            rings.push(vec![
+                Point {
+                    x: p.x - half,
+                    y: p.y - half,
+                },
+                Point {
+                    x: p.x + half,
+                    y: p.y - half,
+                },
+                Point {
+                    x: p.x + half,
+                    y: p.y + half,
+                },
+                Point {
+                    x: p.x - half,
+                    y: p.y + half,
+                },
+            ]);
+        }
+    }
+
+    rings
+}
+
+/// Union of the counter-clockwise `rings`, minus the `rooms`
+fn cut_rooms(rings: Vec<Vec<Point>>, rooms: &[Polygon<Point>]) -> Vec<Polygon<Point>> {
+    // overlapping rings would turn several times around the same places
+    let mut rings = positive_fill(&rings);
+    let (low, high) = bounding_box(rings.iter().flatten());
+
+    for room in rooms {
+        let (room_low, room_high) = bounding_box(room.points().iter());
+
+        let overlap = room_low.x <= high.x
+            && low.x <= room_high.x
+            && room_low.y <= high.y
+            && low.y <= room_high.y;
+
+        if overlap {
+            // turning the other way, the room takes its area out of the corridor
+            let mut ring = counter_clockwise(room.points());
+            ring.reverse();
+            rings.push(ring);
+        }
+    }
+
+    positive_fill(&rings)
+        .into_iter()
+        .filter(|ring| signed_area(ring) > 0.0)
+        .map(Polygon::from_points)
+        .collect()
+}
+
+/// Points linked by straight lines, which corridors follow
+struct Network {
+    vertices: Vec<Point>,
+    index: HashMap<(i64, i64), usize>,
+    edges: Vec<Vec<(usize, f64)>>,
+}
+
+impl Network {
+    fn empty() -> Self {
+        Self {
+            vertices: Vec::new(),
+            index: HashMap::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    /// The walls, linking their ends
+    fn walls(graph: &RoomGraph) -> Self {
+        let mut network = Self::empty();
+
+        for wall in graph.walls() {
+            network.link(&wall.segment.start, &wall.segment.end);
+        }
+
+        network
+    }
+
+    /// The Delaunay edges between the centers of the rooms, only kept between rooms sharing a
+    /// wall so that they stay inside the level
+    fn delaunay(graph: &RoomGraph) -> Self {
+        let mut network = Self::empty();
+
+        // rooms without walls are either empty or alone, with nowhere to go
+        let rooms: Vec<usize> = (0..graph.room_count())
+            .filter(|&room| graph.room_walls(room).next().is_some())
+            .collect();
+        let centers: Vec<Point> = rooms
+            .iter()
+            .map(|&room| graph.centers()[room].clone())
+            .collect();
+
+        for center in &centers {
+            network.insert(center);
+        }
+
+        let Some(triangulation) = Triangulation::new(&centers) else {
+            // fewer than three rooms, or all of them in a row: the walls are all there is
+            for wall in graph.walls() {
+                let (a, b) = wall.rooms;
+                network.link(&graph.centers()[a], &graph.centers()[b]);
+            }
+            return network;
+        };
+
+        for e in 0..triangulation.half_edge_count() {
+            // each inner edge once
+            if triangulation.twin(e).is_some_and(|twin| twin < e) {
+                continue;
+            }
+
+            let (a, b) = (triangulation.origin(e), triangulation.target(e));
+            if graph.wall_between(rooms[a], rooms[b]).is_some() {
+                network.link(&centers[a], &centers[b]);
+            }
+        }
+
+        network
+    }
+
+    fn link(&mut self, a: &Point, b: &Point) {
+        let (i, j) = (self.insert(a), self.insert(b));
+
+        if i != j {
+            let length = distance(a, b);
+            self.edges[i].push((j, length));
+            self.edges[j].push((i, length));
+        }
+    }
+
+    /// Walls ending at `p`
+    fn degree(&self, p: &Point) -> usize {
+        self.find(p).map_or(0, |v| self.edges[v].len())
+    }
+
+    fn key(p: &Point) -> (i64, i64) {
+        // walls of neighbouring rooms meet at the same vertices, up to rounding errors
+        ((p.x * 1e7).round() as i64, (p.y * 1e7).round() as i64)
+    }
+
+    fn find(&self, p: &Point) -> Option<usize> {
+        self.index.get(&Self::key(p)).copied()
+    }
+
+    fn insert(&mut self, p: &Point) -> usize {
+        *self.index.entry(Self::key(p)).or_insert_with(|| {
+            self.vertices.push(p.clone());
+            self.edges.push(Vec::new());
+            self.vertices.len() - 1
+        })
+    }
+
+    /// Path along the walls crossed on the way from room `from` to room `to`
+    fn route(&self, graph: &RoomGraph, from: usize, to: usize) -> Option<Vec<Point>> {
+        let rooms = graph.shortest_path(from, to)?;
+
+        let walls: Vec<&Wall> = rooms
+            .windows(2)
+            .filter_map(|pair| graph.wall_between(pair[0], pair[1]))
+            .collect();
+
+        let Some(first) = walls.first() else {
+            // the same room at both ends
+            return Some(vec![graph.centers()[from].clone()]);
+        };
+
+        // the first wall goes towards the second one
+        let (start, end) = (&first.segment.start, &first.segment.end);
+        let mut path = match walls.get(1) {
+            Some(next) if self.distance_to(start, next) < self.distance_to(end, next) => {
+                vec![end.clone(), start.clone()]
+            }
+            _ => vec![start.clone(), end.clone()],
+        };
+
+        for wall in &walls[1..] {
+            let last = path.last().unwrap().clone();
+            let walk = |end: &Point| self.walk(&last, end).map_or(f64::INFINITY, |(d, _)| d);
+
+            let (near, far) = if walk(&wall.segment.start) <= walk(&wall.segment.end) {
+                (&wall.segment.start, &wall.segment.end)
+            } else {
+                (&wall.segment.end, &wall.segment.start)
+            };
+
+            match self.walk(&last, near) {
+                Some((_, steps)) => path.extend(steps.into_iter().skip(1)),
+                None => path.push(near.clone()),
+            }
+            path.push(far.clone());
+        }
+
+        path.dedup_by(|a, b| distance(a, b) == 0.0);
+        Some(path)
+    }
+
+    /// Length of the shortest walk from `p` to one of the ends of `wall`
+    fn distance_to(&self, p: &Point, wall: &Wall) -> f64 {
+        [&wall.segment.start, &wall.segment.end]
+            .into_iter()
+            .filter_map(|end| self.walk(p, end))
+            .map(|(d, _)| d)
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Shortest walk along the network from `from` to `to`, with its length
+    fn walk(&self, from: &Point, to: &Point) -> Option<(f64, Vec<Point>)> {
+        let (from, to) = (self.find(from)?, self.find(to)?);
+
+        let mut cost = vec![f64::INFINITY; self.vertices.len()];
+        let mut previous = vec![None; self.vertices.len()];
+        let mut queue = BinaryHeap::new();
+
+        cost[from] = 0.0;
+        queue.push(Visit {
+            cost: 0.0,
+            room: from,
+        });
+
+        while let Some(Visit { cost: c, room: v }) = queue.pop() {
+            if v == to {
+                break;
+            }
+            if c > cost[v] {
+                continue;
+            }
+
+            for &(next, length) in &self.edges[v] {
+                if c + length < cost[next] {
+                    cost[next] = c + length;
+                    previous[next] = Some(v);
+                    queue.push(Visit {
+                        cost: c + length,
+                        room: next,
+                    });
+                }
+            }
+        }
+
+        if cost[to] == f64::INFINITY {
+            return None;
+        }
+
+        let mut steps = vec![to];
+        while let Some(v) = previous[*steps.last().unwrap()] {
+            steps.push(v);
+        }
+
+        Some((
+            cost[to],
+            steps
+                .into_iter()
+                .rev()
+                .map(|v| self.vertices[v].clone())
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        geometry::{
+            clip::clip,
+            containment::{locate_point, Location},
+        },
+        graph::doors::{place_doors, DoorParams},
+        random_points,
+        test_util::{p, square},
+        voronoi::fortune,
+    };
+
+    /// Cells of a 3 x 3 grid of unit squares, row by row
+    fn grid() -> Cells {
+        let sites: Vec<Point> = (0..9)
+            .map(|i| p((i % 3) as f64 - 1.0, (i / 3) as f64 - 1.0))
+            .collect();
+
+        fortune::compute(&sites).cells(&square(1.5))
+    }
+
+    fn params(route: Route) -> CorridorParams {
+        CorridorParams {
+            inset: 0.1,
+            width: 0.2,
+            route,
+        }
+    }
+
+    fn area(polygons: &[Polygon<Point>]) -> f64 {
+        polygons.iter().map(|p| signed_area(p.points())).sum()
+    }
+
+    #[test]
+    fn layout_copies() {
+        let layout = corridors(&grid(), &[(0, 2)], &params(Route::Voronoi));
+        let copy = layout.clone();
+
+        assert_eq!(copy.corridors[0].path, layout.corridors[0].path);
+        assert_eq!(format!("{copy:?}"), format!("{layout:?}"));
+        assert!(format!("{:?}", copy.corridors[0]).starts_with("Corridor { rooms: (0, 2)"));
+    }
+
+    #[test]
+    fn rooms_shrink() {
+        let layout = corridors(&grid(), &[], &params(Route::Voronoi));
+
+        assert_eq!(layout.rooms.len(), 9);
+        assert!(layout.corridors.is_empty());
+
+        // the rooms on the edge of the level shrink on every side too
+        for room in &layout.rooms {
+            assert!((signed_area(room.points()) - 0.64).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn corridor_along_a_wall() {
+        let layout = corridors(&grid(), &[(0, 1)], &params(Route::Voronoi));
+        let corridor = &layout.corridors[0];
+
+        assert_eq!(corridor.rooms, (0, 1));
+        assert_eq!(corridor.path.len(), 2);
+
+        // along the wall, with a square where it meets the other walls
+        assert_eq!(corridor.polygons.len(), 1);
+        assert!((area(&corridor.polygons) - 0.22).abs() < 1e-9);
+        assert_eq!(
+            locate_point(&p(-0.5, -1.0), &corridor.polygons[0]),
+            Location::Inside
+        );
+    }
+
+    #[test]
+    fn corridor_around_a_room() {
+        let layout = corridors(&grid(), &[(0, 2)], &params(Route::Voronoi));
+        let corridor = &layout.corridors[0];
+
+        assert_eq!(
+            corridor.path,
+            vec![p(-0.5, -1.5), p(-0.5, -0.5), p(0.5, -0.5), p(0.5, -1.5)]
+        );
+        assert_eq!(corridor.polygons.len(), 1);
+        assert!((area(&corridor.polygons) - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn corridor_through_rooms() {
+        let layout = corridors(&grid(), &[(0, 2)], &params(Route::Delaunay));
+        let corridor = &layout.corridors[0];
+
+        assert_eq!(
+            corridor.path,
+            vec![p(-1.0, -1.0), p(0.0, -1.0), p(1.0, -1.0)]
+        );
+
+        // only the pieces between the rooms are left
+        assert_eq!(corridor.polygons.len(), 2);
+        assert!((area(&corridor.polygons) - 0.08).abs() < 1e-9);
+    }
+
+    #[test]
+    fn delaunay_corridors_follow_the_triangulation() {
+        let cells = fortune::compute(&random_points(40)).cells(&square(1.0));
+        let graph = cells.room_graph();
+        let triangulation = Triangulation::new(graph.centers()).unwrap();
+
+        let room = |q: &Point| graph.centers().iter().position(|c| c == q).unwrap();
+        let layout = corridors(&cells, &[(0, 39), (5, 17)], &params(Route::Delaunay));
+        assert_eq!(layout.corridors.len(), 2);
+
+        for corridor in &layout.corridors {
+            for step in corridor.path.windows(2) {
+                let (a, b) = (room(&step[0]), room(&step[1]));
+
+                assert!(graph.wall_between(a, b).is_some());
+                assert!(triangulation.neighbours(a).contains(&b));
+            }
+        }
+    }
+
+    #[test]
+    fn corridors_between_doors() {
+        let cells = fortune::compute(&random_points(60)).cells(&square(1.0));
+        let graph = cells.room_graph();
+        let links: Vec<(usize, usize)> = place_doors(&graph, &DoorParams::default())
+            .iter()
+            .map(|door| door.rooms())
+            .collect();
+
+        for route in [Route::Voronoi, Route::Delaunay] {
+            let params = CorridorParams {
+                route,
+                ..Default::default()
+            };
+            let layout = corridors(&cells, &links, &params);
+            assert_eq!(layout.corridors.len(), links.len());
+
+            for corridor in &layout.corridors {
+                assert!(!corridor.polygons.is_empty(), "{route:?}");
+
+                // corridors don't go into the rooms
+                for room in layout.rooms.iter().filter(|r| !r.points().is_empty()) {
+                    for polygon in &corridor.polygons {
+                        assert!(area(&clip(polygon, room)) < 1e-9);
+                    }
+                }
+            }
+        }
+    }
+}
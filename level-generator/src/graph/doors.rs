@@ -0,0 +1,281 @@
+//! Doors between rooms.
+//!
+//! Doors go through the walls of a [`RoomGraph`]: first along a random spanning tree, so that
+//! every room can be reached, then on a share of the remaining walls to make loops.
+
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::{RoomGraph, Wall};
+use crate::geometry::Segment;
+
+/// Opening in a wall
+#[derive(Clone, Debug, PartialEq)]
+pub struct Door {
+    pub wall: Wall,
+    /// Part of the wall left open
+    pub opening: Segment,
+}
+
+impl Door {
+    pub fn rooms(&self) -> (usize, usize) {
+        self.wall.rooms
+    }
+
+    pub fn width(&self) -> f64 {
+        self.opening.length()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DoorParams {
+    pub width: f64,
+    /// Smallest distance between a door and the ends of its wall
+    pub corner_margin: f64,
+    /// Share of the possible loops to open, from 0 for a tree of rooms to 1 for a door on every
+    /// wall long enough
+    pub loop_ratio: f64,
+    pub seed: u64,
+}
+
+impl Default for DoorParams {
+    fn default() -> Self {
+        Self {
+            width: 0.05,
+            corner_margin: 0.02,
+            loop_ratio: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Places doors between the rooms of `graph`.
+///
+/// There is at most one door between two rooms, on the longest wall between them, at a random
+/// place away from the ends of the wall. The doors connect all the rooms of each connected piece
+/// of the graph: when the only way to a room is through walls too short for a door, the door is
+/// made narrower to fit.
+///
+/// Once all the rooms are connected, each extra door makes a loop, and `params.loop_ratio` of the
+/// walls long enough for a door get one.
+pub fn place_doors(graph: &RoomGraph, params: &DoorParams) -> Vec<Door> {
+    assert!((0.0..=1.0).contains(&params.loop_ratio));
+
+    let mut rng = SmallRng::seed_from_u64(params.seed);
+    let needed = params.width + 2.0 * params.corner_margin;
+
+    // the longest wall between each pair of rooms
+    let mut candidates: Vec<&Wall> = (0..graph.room_count())
+        .flat_map(|room| {
+            graph
+                .neighbours(room)
+                .into_iter()
+                .filter(move |&other| other > room)
+                .filter_map(move |other| graph.wall_between(room, other))
+        })
+        .collect();
+    candidates.shuffle(&mut rng);
+
+    let (wide, narrow): (Vec<&Wall>, Vec<&Wall>) =
+        candidates.into_iter().partition(|w| w.length() >= needed);
+
+    let mut components = UnionFind::new(graph.room_count());
+    let mut doors = Vec::new();
+    let mut spare = Vec::new();
+
+    // narrow walls only when there is no other way
+    for wall in wide.iter().chain(&narrow) {
+        if components.union(wall.rooms.0, wall.rooms.1) {
+            doors.push(door(&mut rng, wall, params));
+        } else if wall.length() >= needed {
+            spare.push(wall);
+        }
+    }
+
+    let loops = (params.loop_ratio * spare.len() as f64).round() as usize;
+    for wall in spare.into_iter().take(loops) {
+        doors.push(door(&mut rng, wall, params));
+    }
+
+    doors
+}
+
+/// Whether there is a door in a wall, to walk through the graph with
+/// [`RoomGraph::shortest_path_through`] or [`RoomGraph::connected_components_through`]
+pub fn opens(doors: &[Door]) -> impl Fn(&Wall) -> bool + '_ {
+    move |wall| doors.iter().any(|door| door.wall == *wall)
+}
+
+fn door(rng: &mut SmallRng, wall: &Wall, params: &DoorParams) -> Door {
+    let length = wall.length();
+
+    let (width, margin) = if length >= params.width + 2.0 * params.corner_margin {
+        (params.width, params.corner_margin)
+    } else {
+        // as wide as possible while keeping the margins, but at least half the wall
+        let width = (length - 2.0 * params.corner_margin).max(length / 2.0);
+        (width, (length - width) / 2.0)
+    };
+
+    let start = if length - width - 2.0 * margin > 0.0 {
+        rng.gen_range(margin..=length - width - margin)
+    } else {
+        margin
+    };
+
+    Door {
+        wall: wall.clone(),
+        opening: Segment::new(
+            &wall.segment.at(start / length),
+            &wall.segment.at((start + width) / length),
+        ),
+    }
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(size: usize) -> Self {
+        Self {
+            parents: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    /// Joins the sets of `a` and `b`, returns whether they were apart
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a] = b;
+        a != b
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        geometry::distance,
+        random_points,
+        test_util::{p, square},
+        voronoi::{fortune, Cells},
+    };
+
+    use voronator::delaunator::Point;
+
+    /// Cells of a 4 x 4 grid of sites in the [-1, 1] square, walls are 0.5 long
+    fn grid() -> Cells {
+        let sites: Vec<Point> = (0..16)
+            .map(|i| p(-0.75 + 0.5 * (i % 4) as f64, -0.75 + 0.5 * (i / 4) as f64))
+            .collect();
+
+        fortune::compute(&sites).cells(&square(1.0))
+    }
+
+    fn connected(graph: &RoomGraph, doors: &[Door]) -> bool {
+        graph.connected_components_through(opens(doors)).len() == 1
+    }
+
+    #[test]
+    fn tree_of_rooms() {
+        let graph = grid().room_graph();
+        let doors = place_doors(&graph, &DoorParams::default());
+
+        assert_eq!(doors.len(), 15);
+        assert!(connected(&graph, &doors));
+    }
+
+    #[test]
+    fn loops() {
+        let graph = grid().room_graph();
+
+        for (ratio, count) in [(0.5, 20), (1.0, 24)] {
+            let params = DoorParams {
+                loop_ratio: ratio,
+                ..Default::default()
+            };
+
+            assert_eq!(place_doors(&graph, &params).len(), count);
+        }
+    }
+
+    #[test]
+    fn doors_are_away_from_corners() {
+        let graph = grid().room_graph();
+        let params = DoorParams {
+            width: 0.2,
+            corner_margin: 0.1,
+            loop_ratio: 1.0,
+            seed: 5,
+        };
+
+        for door in place_doors(&graph, &params) {
+            assert!((door.width() - 0.2).abs() < 1e-9);
+
+            let wall = &door.wall.segment;
+            for end in [&door.opening.start, &door.opening.end] {
+                assert!(distance(end, &wall.start) >= 0.1 - 1e-9);
+                assert!(distance(end, &wall.end) >= 0.1 - 1e-9);
+                assert!(wall.parameter(end) >= 0.0 && wall.parameter(end) <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn short_walls_get_narrow_doors() {
+        let graph = grid().room_graph();
+        let params = DoorParams {
+            width: 0.45,
+            corner_margin: 0.05,
+            loop_ratio: 1.0,
+            seed: 0,
+        };
+
+        // no wall is long enough, yet every room can be reached
+        let doors = place_doors(&graph, &params);
+        assert_eq!(doors.len(), 15);
+        assert!(connected(&graph, &doors));
+
+        for door in &doors {
+            assert!((door.width() - 0.4).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn voronoi_level() {
+        let graph = fortune::compute(&random_points(100))
+            .cells(&square(1.0))
+            .room_graph();
+        let params = DoorParams {
+            loop_ratio: 0.3,
+            seed: 2,
+            ..Default::default()
+        };
+
+        let doors = place_doors(&graph, &params);
+        assert!(connected(&graph, &doors));
+        assert!(doors.len() > graph.room_count() - 1);
+
+        assert_eq!(doors, place_doors(&graph, &params));
+        assert_ne!(
+            doors,
+            place_doors(
+                &graph,
+                &DoorParams {
+                    seed: 3,
+                    ..params.clone()
+                }
+            )
+        );
+    }
+}
@@ -0,0 +1,721 @@
+//! Complete levels, as handed over to the game.
+//!
+//! A [`Level`] gathers everything the generation pipeline produced: rooms with stable ids, the
+//! walls between them, their doors, the corridors and the outline of the whole level, along with
+//! the seed and parameters it came from. The game only ever reads levels, and never needs to
+//! know about cells, graphs or any other step of the generation.
+
+pub mod file;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use crate::{
+    geometry::{
+        bounding_box, centroid,
+        containment::{locate, Location},
+        coordinates, counter_clockwise, cross, distance,
+        fill::positive_fill,
+        signed_area,
+        triangulate::ear_clipping,
+        Segment,
+    },
+    graph::{
+        corridors::{corridors, CorridorParams},
+        doors::{place_doors, DoorParams},
+    },
+    sampling::{sample, Strategy},
+    voronoi::{
+        alpha::connected_alpha, alpha_shape, generate_voronoi, lloyd::relax, merge::merge_random,
+        Cells,
+    },
+};
+
+/// Version of the [`Level`] layout, bumped whenever its content changes
+pub const VERSION: u32 = 1;
+
+/// Identifier of a room, which stays the same for the whole life of the level
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RoomId(pub u32);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Room {
+    pub id: RoomId,
+    /// Outline of the room, counter-clockwise
+    #[serde(with = "coordinates::points")]
+    pub outline: Vec<Point>,
+    #[serde(with = "coordinates::point")]
+    pub center: Point,
+}
+
+impl Room {
+    pub fn polygon(&self) -> Polygon<Point> {
+        Polygon::from_points(self.outline.clone())
+    }
+
+    /// Triangles covering the room, as indices in `outline`, merged rooms being concave
+    pub fn triangles(&self) -> Vec<[usize; 3]> {
+        ear_clipping(&self.outline)
+    }
+}
+
+/// Piece of boundary shared by two rooms
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Wall {
+    /// The two rooms, the lowest id first
+    pub rooms: (RoomId, RoomId),
+    pub segment: Segment,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Door {
+    pub rooms: (RoomId, RoomId),
+    /// Index in [`Level::walls`] of the wall the door goes through
+    pub wall: usize,
+    /// Part of the wall left open
+    pub opening: Segment,
+}
+
+/// Corridor between two rooms
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Hall {
+    pub rooms: (RoomId, RoomId),
+    /// Middle line of the corridor
+    #[serde(with = "coordinates::points")]
+    pub path: Vec<Point>,
+    /// Floor of the corridor, counter-clockwise, without the parts overlapping the rooms
+    #[serde(with = "coordinates::rings")]
+    pub outlines: Vec<Vec<Point>>,
+}
+
+/// How a level is generated, see [`Level::generate`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LevelParams {
+    /// Number of Voronoi sites, which is also the number of rooms before any of them is dropped
+    /// or merged
+    pub rooms: usize,
+    pub strategy: Strategy,
+    /// Iterations of Lloyd relaxation
+    pub relaxation: usize,
+    /// Keeps only the cells whose center is in the alpha shape of the sites, when set. See
+    /// [`crate::voronoi::alpha::concavity_alpha`] to pick one.
+    pub alpha: Option<f64>,
+    /// Merges neighbouring cells into rooms of about this area, when set
+    pub merge_area: Option<f64>,
+    /// Doors between the rooms, whose seed is derived from the seed of the level
+    pub doors: DoorParams,
+    /// Pulls the rooms apart and links them with corridors wherever there is a door, when set
+    pub corridors: Option<CorridorParams>,
+}
+
+impl Default for LevelParams {
+    fn default() -> Self {
+        Self {
+            rooms: 50,
+            strategy: Strategy::Uniform,
+            relaxation: 2,
+            alpha: None,
+            merge_area: None,
+            doors: DoorParams::default(),
+            corridors: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    /// Layout version the level was made with, see [`VERSION`]
+    pub version: u32,
+    pub seed: u64,
+    pub params: LevelParams,
+    /// Rooms in the order of their ids
+    pub rooms: Vec<Room>,
+    pub walls: Vec<Wall>,
+    pub doors: Vec<Door>,
+    pub halls: Vec<Hall>,
+    /// Outline of the whole level: outer boundaries counter-clockwise, holes clockwise
+    #[serde(with = "coordinates::rings")]
+    pub boundary: Vec<Vec<Point>>,
+}
+
+impl Level {
+    /// Runs the whole pipeline: sampling the sites in the [-1, 1] square, relaxing them, cutting
+    /// the level to its silhouette, merging the cells, then placing doors and corridors.
+    ///
+    /// The same seed and parameters always give the same level.
+    pub fn generate(seed: u64, params: &LevelParams) -> Level {
+        let domain = Polygon::from_points(vec![
+            Point { x: -1.0, y: -1.0 },
+            Point { x: 1.0, y: -1.0 },
+            Point { x: 1.0, y: 1.0 },
+            Point { x: -1.0, y: 1.0 },
+        ]);
+
+        let sites = sample(seed, &domain, &params.strategy, params.rooms);
+        let sites = relax(&sites, &domain, params.relaxation);
+        let mut cells = generate_voronoi(sites.clone());
+
+        if let Some(alpha) = params.alpha {
+            cells = silhouette(&cells, &sites, alpha);
+        }
+
+        if let Some(area) = params.merge_area {
+            cells = merge_random(&cells, seed.wrapping_add(1), area).cells;
+        }
+
+        Self::from_cells(&cells, seed, params)
+    }
+
+    /// Level made of `cells`, room `i` being the `i`-th non-empty cell, with doors and corridors
+    /// placed according to `params`.
+    ///
+    /// This is where cells coming out of an evolution or any other generator become a level.
+    pub fn from_cells(cells: &Cells, seed: u64, params: &LevelParams) -> Level {
+        let graph = cells.room_graph();
+
+        // empty cells don't make rooms
+        let mut ids = vec![None; cells.len()];
+        let mut rooms = Vec::new();
+        for (i, cell) in cells.iter_cells().enumerate() {
+            if cell.points().len() < 3 {
+                continue;
+            }
+
+            let id = RoomId(rooms.len() as u32);
+            ids[i] = Some(id);
+            rooms.push(Room {
+                id,
+                outline: counter_clockwise(cell.points()),
+                center: graph.centers()[i].clone(),
+            });
+        }
+
+        let pair = |(a, b): (usize, usize)| (ids[a].unwrap(), ids[b].unwrap());
+
+        let walls = graph
+            .walls()
+            .iter()
+            .map(|wall| Wall {
+                rooms: pair(wall.rooms),
+                segment: wall.segment.clone(),
+            })
+            .collect();
+
+        let door_params = DoorParams {
+            seed: seed.wrapping_add(2),
+            ..params.doors.clone()
+        };
+        let placed = place_doors(&graph, &door_params);
+
+        let doors = placed
+            .iter()
+            .map(|door| Door {
+                rooms: pair(door.rooms()),
+                wall: graph.walls().iter().position(|w| *w == door.wall).unwrap(),
+                opening: door.opening.clone(),
+            })
+            .collect();
+
+        let boundary = positive_fill(
+            &rooms
+                .iter()
+                .map(|room| room.outline.clone())
+                .collect::<Vec<_>>(),
+        );
+
+        let mut halls = Vec::new();
+        if let Some(corridor_params) = &params.corridors {
+            let links: Vec<(usize, usize)> = placed.iter().map(|door| door.rooms()).collect();
+            let layout = corridors(cells, &links, corridor_params);
+
+            // rooms too small to shrink keep their whole cell
+            for (i, shrunk) in layout.rooms.iter().enumerate() {
+                if let Some(id) = ids[i] {
+                    if shrunk.points().len() >= 3 {
+                        rooms[id.0 as usize].outline = counter_clockwise(shrunk.points());
+                    }
+                }
+            }
+
+            halls = layout
+                .corridors
+                .into_iter()
+                .map(|corridor| Hall {
+                    rooms: pair(corridor.rooms),
+                    path: corridor.path,
+                    outlines: corridor
+                        .polygons
+                        .iter()
+                        .map(|p| p.points().to_vec())
+                        .collect(),
+                })
+                .collect();
+        }
+
+        Level {
+            version: VERSION,
+            seed,
+            params: params.clone(),
+            rooms,
+            walls,
+            doors,
+            halls,
+            boundary,
+        }
+    }
+
+    pub fn room(&self, id: RoomId) -> Option<&Room> {
+        self.rooms.get(id.0 as usize)
+    }
+
+    /// Walls around the room `id`
+    pub fn room_walls(&self, id: RoomId) -> impl Iterator<Item = &Wall> + '_ {
+        self.walls
+            .iter()
+            .filter(move |w| w.rooms.0 == id || w.rooms.1 == id)
+    }
+
+    /// Doors of the room `id`
+    pub fn room_doors(&self, id: RoomId) -> impl Iterator<Item = &Door> + '_ {
+        self.doors
+            .iter()
+            .filter(move |d| d.rooms.0 == id || d.rooms.1 == id)
+    }
+
+    /// Everything wrong with the level, for levels edited by hand or coming from a file.
+    ///
+    /// A generated level has no problem.
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        if self.version != VERSION {
+            problems.push(Problem::Version(self.version));
+        }
+
+        if self.rooms.is_empty() {
+            problems.push(Problem::Empty);
+        }
+
+        for (index, room) in self.rooms.iter().enumerate() {
+            if room.id != RoomId(index as u32) {
+                problems.push(Problem::RoomId { index, id: room.id });
+            }
+            if room.outline.len() < 3 || signed_area(&room.outline) <= 0.0 {
+                problems.push(Problem::Outline(room.id));
+            }
+        }
+
+        let boxes: Vec<_> = self
+            .rooms
+            .iter()
+            .map(|r| bounding_box(&r.outline))
+            .collect();
+        for i in 0..self.rooms.len() {
+            for j in i + 1..self.rooms.len() {
+                let ((a_min, a_max), (b_min, b_max)) = (&boxes[i], &boxes[j]);
+                if a_max.x <= b_min.x
+                    || b_max.x <= a_min.x
+                    || a_max.y <= b_min.y
+                    || b_max.y <= a_min.y
+                {
+                    continue;
+                }
+
+                if overlap(&self.rooms[i].outline, &self.rooms[j].outline) {
+                    problems.push(Problem::Overlap(self.rooms[i].id, self.rooms[j].id));
+                }
+            }
+        }
+
+        let exists = |id: RoomId| (id.0 as usize) < self.rooms.len();
+
+        for (w, wall) in self.walls.iter().enumerate() {
+            if !exists(wall.rooms.0) || !exists(wall.rooms.1) {
+                problems.push(Problem::Wall(w));
+            }
+        }
+
+        for (d, door) in self.doors.iter().enumerate() {
+            let on_wall = self.walls.get(door.wall).is_some_and(|wall| {
+                wall.rooms == door.rooms
+                    && [&door.opening.start, &door.opening.end].iter().all(|end| {
+                        let t = wall.segment.parameter(end);
+                        (-ON_WALL_EPSILON..=1.0 + ON_WALL_EPSILON).contains(&t)
+                            && distance(end, &wall.segment.at(t)) < ON_WALL_EPSILON
+                    })
+            });
+
+            if !on_wall {
+                problems.push(Problem::Door(d));
+            }
+        }
+
+        // every room can be reached from the first one through the doors
+        let mut reached = vec![false; self.rooms.len()];
+        let mut stack = vec![0];
+        while let Some(room) = stack.pop() {
+            if room >= reached.len() || reached[room] {
+                continue;
+            }
+            reached[room] = true;
+
+            for door in self.room_doors(RoomId(room as u32)) {
+                stack.push(door.rooms.0 .0 as usize);
+                stack.push(door.rooms.1 .0 as usize);
+            }
+        }
+        problems.extend(
+            reached
+                .iter()
+                .enumerate()
+                .filter(|(_, &r)| !r)
+                .map(|(i, _)| Problem::Unreachable(RoomId(i as u32))),
+        );
+
+        problems
+    }
+}
+
+/// Distance under which two rooms are only touching
+const OVERLAP_EPSILON: f64 = 1e-7;
+
+/// Distance under which a door is on its wall
+const ON_WALL_EPSILON: f64 = 1e-7;
+
+/// Something wrong with a level, see [`Level::validate`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    /// The level is not of the current [`VERSION`]
+    Version(u32),
+    /// Room found at `index` in the list of rooms
+    RoomId {
+        index: usize,
+        id: RoomId,
+    },
+    /// Outline too small or clockwise
+    Outline(RoomId),
+    Overlap(RoomId, RoomId),
+    /// Wall, by its index, between rooms that don't exist
+    Wall(usize),
+    /// Door, by its index, which is not on its wall
+    Door(usize),
+    /// Room that can't be reached from the first one
+    Unreachable(RoomId),
+    /// Level without any room
+    Empty,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Version(version) => {
+                write!(f, "version {version} instead of {VERSION}")
+            }
+            Problem::RoomId { index, id } => write!(f, "room {} found at index {index}", id.0),
+            Problem::Outline(id) => write!(f, "room {} has no proper outline", id.0),
+            Problem::Overlap(a, b) => write!(f, "rooms {} and {} overlap", a.0, b.0),
+            Problem::Wall(w) => write!(f, "wall {w} is between rooms that don't exist"),
+            Problem::Door(d) => write!(f, "door {d} is not on its wall"),
+            Problem::Unreachable(id) => write!(f, "room {} can't be reached", id.0),
+            Problem::Empty => write!(f, "the level has no rooms"),
+        }
+    }
+}
+
+/// Whether the insides of two counter-clockwise outlines meet, rather than just their sides.
+///
+/// Neighbouring rooms don't share their vertices exactly, so anything closer than
+/// `OVERLAP_EPSILON` to the other outline is only touching it.
+fn overlap(a: &[Point], b: &[Point]) -> bool {
+    let edges = |ring: &[Point]| -> Vec<Segment> {
+        (0..ring.len())
+            .map(|i| Segment::new(&ring[i], &ring[(i + 1) % ring.len()]))
+            .collect()
+    };
+    let (a_edges, b_edges) = (edges(a), edges(b));
+
+    let off_line =
+        |p: &Point, s: &Segment| cross(&s.start, &s.end, p).abs() > OVERLAP_EPSILON * s.length();
+    let crossing = a_edges.iter().any(|e| {
+        b_edges.iter().any(|f| {
+            off_line(&f.start, e)
+                && off_line(&f.end, e)
+                && off_line(&e.start, f)
+                && off_line(&e.end, f)
+                && e.crossing(f).is_some()
+        })
+    });
+
+    let deep_inside = |p: &Point, ring: &[Point], edges: &[Segment]| {
+        locate(p, ring) == Location::Inside
+            && edges
+                .iter()
+                .all(|s| distance(p, &s.at(s.parameter(p).clamp(0.0, 1.0))) > OVERLAP_EPSILON)
+    };
+    let inside = |points: &[Point], ring: &[Point], edges: &[Segment]| {
+        points.iter().any(|p| deep_inside(p, ring, edges))
+    };
+
+    // the same outline twice has neither crossings nor vertices inside the other one
+    let same =
+        centroid(a).is_some_and(|c| deep_inside(&c, a, &a_edges) && deep_inside(&c, b, &b_edges));
+
+    crossing || inside(a, b, &b_edges) || inside(b, a, &a_edges) || same
+}
+
+/// Keeps the cells whose center lies in the alpha shape of `sites`, or at least the largest
+/// connected group of them so that the level stays in one piece.
+///
+/// An alpha too small to keep any cell is replaced by the smallest one keeping all the sites
+/// together, so that the level is never empty.
+fn silhouette(cells: &Cells, sites: &[Point], alpha: f64) -> Cells {
+    let graph = cells.room_graph();
+
+    let largest = |alpha: f64| {
+        let shape = alpha_shape(sites, alpha);
+
+        let kept: Vec<bool> = cells
+            .iter_cells()
+            .map(|cell| {
+                centroid(cell.points()).is_some_and(|c| shape.locate(&c) != Location::Outside)
+            })
+            .collect();
+
+        graph
+            .connected_components_through(|w| kept[w.rooms.0] && kept[w.rooms.1])
+            .into_iter()
+            .filter(|group| kept[group[0]])
+            .max_by_key(|group| group.len())
+            .unwrap_or_default()
+    };
+
+    let mut largest = match largest(alpha) {
+        group if group.is_empty() => largest(connected_alpha(sites)),
+        group => group,
+    };
+
+    largest.sort_unstable();
+
+    let polygons: Vec<&Polygon<Point>> = cells.iter_cells().collect();
+
+    largest
+        .into_iter()
+        .map(|i| Polygon::from_points(polygons[i].points().to_vec()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::geometry::clip::clip;
+
+    #[test]
+    fn rooms_cover_the_level() {
+        let level = Level::generate(1, &LevelParams::default());
+
+        assert_eq!(level.version, VERSION);
+        assert_eq!(level.rooms.len(), 50);
+
+        for (i, room) in level.rooms.iter().enumerate() {
+            assert_eq!(room.id, RoomId(i as u32));
+            assert!(signed_area(&room.outline) > 0.0);
+        }
+
+        // a single piece without holes
+        assert_eq!(level.boundary.len(), 1);
+        let total: f64 = level.rooms.iter().map(|r| signed_area(&r.outline)).sum();
+        assert!((signed_area(&level.boundary[0]) - total).abs() < 1e-9);
+    }
+
+    #[test]
+    fn doors_connect_every_room() {
+        let level = Level::generate(3, &LevelParams::default());
+
+        // a tree of rooms
+        assert_eq!(level.doors.len(), level.rooms.len() - 1);
+
+        for door in &level.doors {
+            let wall = &level.walls[door.wall];
+            assert_eq!(wall.rooms, door.rooms);
+            for end in [&door.opening.start, &door.opening.end] {
+                let t = wall.segment.parameter(end);
+                assert!((0.0..=1.0).contains(&t));
+                assert!(distance(end, &wall.segment.at(t)) < 1e-9);
+            }
+        }
+
+        let mut reached = vec![RoomId(0)];
+        let mut next = 0;
+        while next < reached.len() {
+            for door in level.room_doors(reached[next]) {
+                for room in [door.rooms.0, door.rooms.1] {
+                    if !reached.contains(&room) {
+                        reached.push(room);
+                    }
+                }
+            }
+            next += 1;
+        }
+        assert_eq!(reached.len(), level.rooms.len());
+    }
+
+    #[test]
+    fn same_seed_same_level() {
+        let params = LevelParams {
+            merge_area: Some(0.2),
+            ..Default::default()
+        };
+
+        let level = Level::generate(7, &params);
+        assert_eq!(level, Level::generate(7, &params));
+        assert_ne!(level, Level::generate(8, &params));
+
+        // merged rooms
+        assert!(level.rooms.len() < 50);
+    }
+
+    #[test]
+    fn merged_rooms_triangulate() {
+        let params = LevelParams {
+            merge_area: Some(0.2),
+            ..Default::default()
+        };
+
+        let level = Level::generate(7, &params);
+
+        for room in &level.rooms {
+            let area: f64 = room
+                .triangles()
+                .iter()
+                .map(|t| signed_area(&t.map(|i| room.outline[i].clone())))
+                .sum();
+
+            assert!((area - signed_area(&room.outline)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn silhouette() {
+        let params = LevelParams {
+            strategy: Strategy::Clusters {
+                clusters: 2,
+                spread: 0.2,
+            },
+            alpha: Some(0.2),
+            ..Default::default()
+        };
+
+        let level = Level::generate(4, &params);
+
+        assert!(!level.rooms.is_empty());
+        assert!(level.rooms.len() < 50);
+        assert_eq!(level.boundary.len(), 1);
+
+        // too small to keep anything on its own
+        let params = LevelParams {
+            rooms: 4,
+            alpha: Some(0.0001),
+            ..Default::default()
+        };
+        let level = Level::generate(0, &params);
+
+        assert!(!level.rooms.is_empty());
+        assert_eq!(level.validate(), vec![]);
+    }
+
+    #[test]
+    fn corridors_between_rooms() {
+        let params = LevelParams {
+            rooms: 20,
+            corridors: Some(CorridorParams::default()),
+            ..Default::default()
+        };
+
+        let level = Level::generate(2, &params);
+
+        assert_eq!(level.halls.len(), level.doors.len());
+
+        for hall in &level.halls {
+            assert!(!hall.outlines.is_empty());
+
+            for outline in &hall.outlines {
+                for room in &level.rooms {
+                    let overlap: f64 =
+                        clip(&Polygon::from_points(outline.clone()), &room.polygon())
+                            .iter()
+                            .map(|p| signed_area(p.points()).abs())
+                            .sum();
+                    assert!(overlap < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generated_levels_are_valid() {
+        for params in [
+            LevelParams::default(),
+            LevelParams {
+                merge_area: Some(0.2),
+                doors: DoorParams {
+                    loop_ratio: 0.5,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            LevelParams {
+                rooms: 20,
+                corridors: Some(CorridorParams::default()),
+                ..Default::default()
+            },
+        ] {
+            for seed in 0..3 {
+                assert_eq!(Level::generate(seed, &params).validate(), vec![]);
+            }
+        }
+    }
+
+    #[test]
+    fn broken_level() {
+        let mut level = Level::generate(0, &LevelParams::default());
+
+        level.version = VERSION + 1;
+        level.rooms[2].outline.reverse();
+        level.doors[0].opening = level.doors[1].opening.clone();
+        level.walls[0].rooms.1 = RoomId(100);
+        let last = level.rooms.len() as u32 - 1;
+        level.doors.retain(|d| d.rooms.1 != RoomId(last));
+        level.rooms[3].outline = level.rooms[4].outline.clone();
+
+        let problems = level.validate();
+
+        assert!(problems.contains(&Problem::Version(VERSION + 1)));
+        assert!(problems.contains(&Problem::Outline(RoomId(2))));
+        assert!(problems.contains(&Problem::Overlap(RoomId(3), RoomId(4))));
+        assert!(problems.contains(&Problem::Wall(0)));
+        assert!(problems.contains(&Problem::Door(0)));
+        assert!(problems.contains(&Problem::Unreachable(RoomId(last))));
+
+        level.rooms.clear();
+        assert!(level.validate().contains(&Problem::Empty));
+    }
+
+    #[test]
+    fn level_from_cells() {
+        let cells = generate_voronoi(crate::random_points(10));
+        let level = Level::from_cells(&cells, 0, &LevelParams::default());
+
+        assert_eq!(level.rooms.len(), 10);
+        assert_eq!(level.walls.len(), cells.room_graph().walls().len());
+        assert_eq!(level.room(RoomId(3)).unwrap().id, RoomId(3));
+        assert!(level.room(RoomId(10)).is_none());
+
+        for room in &level.rooms {
+            assert!(level.room_walls(room.id).count() > 0);
+        }
+    }
+}
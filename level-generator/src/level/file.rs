@@ -0,0 +1,258 @@
+//! Saving levels to human-readable files and loading them back.
+//!
+//! Levels are written as JSON or RON, whole: rooms, walls, doors, halls, along with the seed and
+//! the parameters they came from, so that a level loaded from a file is exactly the one that was
+//! saved. Every file records the [`VERSION`] of the layout it was written with. Loading a file of
+//! another version goes through a migration, which turns the old layout into the current one.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use super::{Level, VERSION};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Ron,
+}
+
+impl Format {
+    /// Format matching the extension of `path`, `.json` or `.ron`
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            "ron" => Some(Format::Ron),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Ron(ron::Error),
+    /// The file has neither a `.json` nor a `.ron` extension
+    UnknownFormat(PathBuf),
+    /// No migration knows how to read this version of the layout
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Json(e) => write!(f, "invalid JSON level: {e}"),
+            Error::Ron(e) => write!(f, "invalid RON level: {e}"),
+            Error::UnknownFormat(path) => {
+                write!(f, "{} is neither a .json nor a .ron file", path.display())
+            }
+            Error::UnsupportedVersion(version) => write!(
+                f,
+                "level version {version} can't be read, the current one is {VERSION}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<ron::Error> for Error {
+    fn from(e: ron::Error) -> Self {
+        Error::Ron(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for Error {
+    fn from(e: ron::error::SpannedError) -> Self {
+        Error::Ron(e.code)
+    }
+}
+
+/// Just enough of a level file to know how to read the rest
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+pub fn to_string(level: &Level, format: Format) -> Result<String, Error> {
+    Ok(match format {
+        Format::Json => serde_json::to_string_pretty(level)?,
+        Format::Ron => ron::ser::to_string_pretty(level, ron::ser::PrettyConfig::new())?,
+    })
+}
+
+/// Reads a level, migrating it to the current version if needed
+pub fn from_str(text: &str, format: Format) -> Result<Level, Error> {
+    from_str_with(text, format, migrate)
+}
+
+/// Like [`from_str`], with `migration` reading the levels of any other version than the current
+/// one. It gets the version of the file, and the file itself.
+pub fn from_str_with(
+    text: &str,
+    format: Format,
+    migration: impl Fn(u32, &str, Format) -> Result<Level, Error>,
+) -> Result<Level, Error> {
+    let header: Header = parse(text, format)?;
+
+    if header.version == VERSION {
+        parse(text, format)
+    } else {
+        migration(header.version, text, format)
+    }
+}
+
+/// Saves `level` to `path`, in the format given by its extension
+pub fn save(level: &Level, path: impl AsRef<Path>) -> Result<(), Error> {
+    let path = path.as_ref();
+    let format = Format::from_path(path).ok_or_else(|| Error::UnknownFormat(path.into()))?;
+
+    fs::write(path, to_string(level, format)?)?;
+    Ok(())
+}
+
+/// Loads the level saved in `path`, in the format given by its extension
+pub fn load(path: impl AsRef<Path>) -> Result<Level, Error> {
+    let path = path.as_ref();
+    let format = Format::from_path(path).ok_or_else(|| Error::UnknownFormat(path.into()))?;
+
+    from_str(&fs::read_to_string(path)?, format)
+}
+
+/// Reads anything from a level file, such as the older layouts a migration starts from
+pub fn parse<'a, T: Deserialize<'a>>(text: &'a str, format: Format) -> Result<T, Error> {
+    Ok(match format {
+        Format::Json => serde_json::from_str(text)?,
+        Format::Ron => ron::from_str(text)?,
+    })
+}
+
+/// Brings a level of an older version up to date.
+///
+/// There has been a single version so far. When the layout changes, the previous `Level` goes
+/// here under another name, along with the conversion to the new one.
+fn migrate(version: u32, _text: &str, _format: Format) -> Result<Level, Error> {
+    Err(Error::UnsupportedVersion(version))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        graph::corridors::CorridorParams,
+        level::LevelParams,
+        sampling::{DensityMap, Strategy},
+    };
+
+    fn level() -> Level {
+        Level::generate(
+            5,
+            &LevelParams {
+                rooms: 20,
+                strategy: Strategy::Density(DensityMap::new(2, 1, vec![1.0, 3.0])),
+                merge_area: Some(0.3),
+                corridors: Some(CorridorParams::default()),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let level = level();
+
+        for format in [Format::Json, Format::Ron] {
+            let text = to_string(&level, format).unwrap();
+            assert_eq!(from_str(&text, format).unwrap(), level);
+        }
+    }
+
+    /// File in the temporary directory, only for this process, removed even if the test fails
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let name = format!("level-generator-test-{}-{name}", std::process::id());
+            Self(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let level = level();
+
+        for name in ["level.json", "level.ron"] {
+            let file = TempFile::new(name);
+            save(&level, &file.0).unwrap();
+            assert_eq!(load(&file.0).unwrap(), level);
+        }
+
+        let file = TempFile::new("level.txt");
+        assert!(matches!(
+            save(&level, &file.0),
+            Err(Error::UnknownFormat(_))
+        ));
+        assert!(!file.0.exists());
+    }
+
+    #[test]
+    fn migration() {
+        let level = level();
+        let old = Level {
+            version: 0,
+            ..level.clone()
+        };
+        let text = to_string(&old, Format::Ron).unwrap();
+
+        assert!(matches!(
+            from_str(&text, Format::Ron),
+            Err(Error::UnsupportedVersion(0))
+        ));
+
+        let migrated = from_str_with(&text, Format::Ron, |version, text, format| {
+            assert_eq!(version, 0);
+            let mut level: Level = parse(text, format)?;
+            level.version = VERSION;
+            Ok(level)
+        })
+        .unwrap();
+        assert_eq!(migrated, level);
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(matches!(
+            from_str("{ \"version\": 1 }", Format::Json),
+            Err(Error::Json(_))
+        ));
+        assert!(matches!(
+            from_str("(rooms: [])", Format::Ron),
+            Err(Error::Ron(_))
+        ));
+    }
+}
@@ -1,3 +1,9 @@
+pub mod evolution;
+pub mod geometry;
+pub mod graph;
+pub mod level;
+pub mod render;
+pub mod sampling;
 pub mod voronoi;
 
 pub use voronator::delaunator::Point;
@@ -5,6 +11,9 @@ pub use voronator::polygon::Polygon;
 
 use rand::{distributions::Uniform, rngs::SmallRng, Rng, SeedableRng};
 
+/// Always the same `number` uniform points in the [-1, 1] square, handy for tests and comparisons.
+///
+/// Use `sampling::sample` to get different points from a seed, or other distributions.
 pub fn random_points(number: usize) -> Vec<Point> {
     let distr = Uniform::new(-1.0, 1.0);
 
@@ -17,3 +26,23 @@ pub fn random_points(number: usize) -> Vec<Point> {
         .take(number)
         .collect()
 }
+
+/// Fixtures shared by the tests
+#[cfg(test)]
+pub(crate) mod test_util {
+    use voronator::{delaunator::Point, polygon::Polygon};
+
+    pub fn p(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    /// Counter-clockwise square of side `2 * size` around the origin
+    pub fn square(size: f64) -> Polygon<Point> {
+        Polygon::from_points(vec![
+            p(-size, -size),
+            p(size, -size),
+            p(size, size),
+            p(-size, size),
+        ])
+    }
+}
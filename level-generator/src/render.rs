@@ -0,0 +1,410 @@
+//! Pictures of levels, written to files instead of a window.
+//!
+//! A [`Scene`] gathers what to draw, in level coordinates: cells, sites, boundaries, alpha shapes,
+//! doors and labels, or a whole [`Level`] at once. It is then written out with a [`Style`] saying
+//! how each layer looks, as an SVG file with [`svg::write`] or as a PNG one with
+//! [`raster::rasterise`].
+
+pub mod raster;
+pub mod svg;
+
+use voronator::delaunator::Point;
+
+use crate::{
+    geometry::{bounding_box, Segment},
+    level::Level,
+    voronoi::alpha::AlphaShape,
+    voronoi::Cells,
+};
+
+/// Color with its opacity, 255 being opaque
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    pub const fn with_alpha(self, a: u8) -> Self {
+        Self { a, ..self }
+    }
+
+    /// `#rrggbb`, without the opacity
+    pub fn hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+/// How the shapes of a layer are filled and outlined, nothing being drawn for the parts left out
+#[derive(Clone, Debug, PartialEq)]
+pub struct Paint {
+    pub fill: Option<Color>,
+    pub stroke: Option<Color>,
+    /// Width of the outline, in pixels
+    pub width: f64,
+}
+
+impl Paint {
+    pub fn fill(color: Color) -> Self {
+        Self {
+            fill: Some(color),
+            stroke: None,
+            width: 0.0,
+        }
+    }
+
+    pub fn stroke(color: Color, width: f64) -> Self {
+        Self {
+            fill: None,
+            stroke: Some(color),
+            width,
+        }
+    }
+
+    pub fn with_stroke(self, color: Color, width: f64) -> Self {
+        Self {
+            stroke: Some(color),
+            width,
+            ..self
+        }
+    }
+}
+
+/// Kinds of shapes in a scene, each of them with its own paint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+    Cells,
+    Rooms,
+    Halls,
+    Boundary,
+    Alpha,
+    Doors,
+    Sites,
+    Labels,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style {
+    /// Width of the picture in pixels, its height following the proportions of the scene
+    pub width: u32,
+    /// Space left around the scene, in pixels
+    pub margin: u32,
+    pub background: Option<Color>,
+    pub cells: Paint,
+    pub rooms: Paint,
+    pub halls: Paint,
+    pub boundary: Paint,
+    pub alpha: Paint,
+    pub doors: Paint,
+    pub sites: Paint,
+    /// Radius of the dots of the sites, in pixels
+    pub site_radius: f64,
+    /// Color of the labels, filled in with `fill`
+    pub labels: Paint,
+    /// Height of the labels, in pixels
+    pub label_size: f64,
+}
+
+impl Style {
+    pub fn paint(&self, layer: Layer) -> &Paint {
+        match layer {
+            Layer::Cells => &self.cells,
+            Layer::Rooms => &self.rooms,
+            Layer::Halls => &self.halls,
+            Layer::Boundary => &self.boundary,
+            Layer::Alpha => &self.alpha,
+            Layer::Doors => &self.doors,
+            Layer::Sites => &self.sites,
+            Layer::Labels => &self.labels,
+        }
+    }
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        let ink = Color::rgb(0x33, 0x33, 0x33);
+
+        Self {
+            width: 800,
+            margin: 20,
+            background: Some(Color::WHITE),
+            cells: Paint::fill(Color::rgb(0xf4, 0xf1, 0xea)).with_stroke(ink, 1.0),
+            rooms: Paint::fill(Color::rgb(0xe8, 0xdf, 0xcc)).with_stroke(ink, 1.0),
+            halls: Paint::fill(Color::rgb(0xc9, 0xbc, 0xa3)),
+            boundary: Paint::stroke(Color::BLACK, 3.0),
+            alpha: Paint::stroke(Color::rgb(0x29, 0x80, 0xb9), 2.0),
+            doors: Paint::stroke(Color::rgb(0xc0, 0x39, 0x2b), 4.0),
+            sites: Paint::fill(ink),
+            site_radius: 2.0,
+            labels: Paint::fill(Color::rgb(0x55, 0x55, 0x55)),
+            label_size: 10.0,
+        }
+    }
+}
+
+/// Something to draw, in level coordinates
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// Area inside the rings, holes going the other way around than their outer ring
+    Area {
+        layer: Layer,
+        rings: Vec<Vec<Point>>,
+    },
+    Line {
+        layer: Layer,
+        segment: Segment,
+    },
+    Dot {
+        layer: Layer,
+        center: Point,
+    },
+    Label {
+        layer: Layer,
+        at: Point,
+        text: String,
+    },
+}
+
+impl Shape {
+    pub fn layer(&self) -> Layer {
+        match self {
+            Shape::Area { layer, .. }
+            | Shape::Line { layer, .. }
+            | Shape::Dot { layer, .. }
+            | Shape::Label { layer, .. } => *layer,
+        }
+    }
+}
+
+/// Shapes to draw, the ones added last on top
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scene {
+    shapes: Vec<Shape>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shapes(&self) -> &[Shape] {
+        &self.shapes
+    }
+
+    pub fn add(&mut self, shape: Shape) -> &mut Self {
+        self.shapes.push(shape);
+        self
+    }
+
+    pub fn cells(&mut self, cells: &Cells) -> &mut Self {
+        for cell in cells.iter_cells() {
+            self.area(Layer::Cells, vec![cell.points().to_vec()]);
+        }
+        self
+    }
+
+    /// Numbers each cell at its centroid
+    pub fn cell_labels(&mut self, cells: &Cells) -> &mut Self {
+        for (i, center) in cells.room_graph().centers().iter().enumerate() {
+            self.label(center, i.to_string());
+        }
+        self
+    }
+
+    pub fn sites(&mut self, sites: &[Point]) -> &mut Self {
+        for site in sites {
+            self.add(Shape::Dot {
+                layer: Layer::Sites,
+                center: site.clone(),
+            });
+        }
+        self
+    }
+
+    /// Outline of a level, such as [`Level::boundary`]
+    pub fn boundary(&mut self, rings: &[Vec<Point>]) -> &mut Self {
+        self.area(Layer::Boundary, rings.to_vec())
+    }
+
+    pub fn alpha_shape(&mut self, shape: &AlphaShape) -> &mut Self {
+        for component in shape.components() {
+            let rings = std::iter::once(&component.outer)
+                .chain(&component.holes)
+                .map(|ring| ring.points().to_vec())
+                .collect();
+
+            self.area(Layer::Alpha, rings);
+        }
+        self
+    }
+
+    pub fn doors<'a>(&mut self, openings: impl IntoIterator<Item = &'a Segment>) -> &mut Self {
+        for opening in openings {
+            self.add(Shape::Line {
+                layer: Layer::Doors,
+                segment: opening.clone(),
+            });
+        }
+        self
+    }
+
+    pub fn label(&mut self, at: &Point, text: impl Into<String>) -> &mut Self {
+        self.add(Shape::Label {
+            layer: Layer::Labels,
+            at: at.clone(),
+            text: text.into(),
+        })
+    }
+
+    /// Rooms and halls, the outline of the level, its doors and the ids of the rooms
+    pub fn level(&mut self, level: &Level) -> &mut Self {
+        for room in &level.rooms {
+            self.area(Layer::Rooms, vec![room.outline.clone()]);
+        }
+        for hall in &level.halls {
+            for outline in &hall.outlines {
+                self.area(Layer::Halls, vec![outline.clone()]);
+            }
+        }
+
+        self.boundary(&level.boundary);
+        self.doors(level.doors.iter().map(|door| &door.opening));
+
+        for room in &level.rooms {
+            self.label(&room.center, room.id.0.to_string());
+        }
+        self
+    }
+
+    /// Lowest and highest corners of the box around all the shapes, `None` for an empty scene
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        let mut points = self
+            .shapes
+            .iter()
+            .flat_map(|shape| match shape {
+                Shape::Area { rings, .. } => rings.iter().flatten().collect(),
+                Shape::Line { segment, .. } => vec![&segment.start, &segment.end],
+                Shape::Dot { center, .. } => vec![center],
+                Shape::Label { at, .. } => vec![at],
+            })
+            .peekable();
+
+        points.peek()?;
+        Some(bounding_box(points))
+    }
+
+    fn area(&mut self, layer: Layer, rings: Vec<Vec<Point>>) -> &mut Self {
+        self.add(Shape::Area { layer, rings })
+    }
+}
+
+/// Maps level coordinates to the pixels of a picture, the y axis going down
+pub(crate) struct Frame {
+    origin: Point,
+    scale: f64,
+    margin: f64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Frame {
+    /// Frame fitting the whole `scene` in the width of `style`
+    pub fn new(scene: &Scene, style: &Style) -> Self {
+        let (min, max) = scene
+            .bounds()
+            .unwrap_or((Point { x: -1.0, y: -1.0 }, Point { x: 1.0, y: 1.0 }));
+
+        let margin = style.margin as f64;
+        let inner = (style.width as f64 - 2.0 * margin).max(1.0);
+        // a scene without width still gets some room
+        let extent = if max.x > min.x {
+            max.x - min.x
+        } else {
+            (max.y - min.y).max(1.0)
+        };
+        let scale = inner / extent;
+
+        Self {
+            origin: Point { x: min.x, y: max.y },
+            scale,
+            margin,
+            width: style.width,
+            height: ((max.y - min.y) * scale + 2.0 * margin).ceil() as u32,
+        }
+    }
+
+    pub fn pixel(&self, p: &Point) -> (f64, f64) {
+        (
+            self.margin + (p.x - self.origin.x) * self.scale,
+            self.margin + (self.origin.y - p.y) * self.scale,
+        )
+    }
+
+    /// Point of the level at `pixel`, the other way around from [`Frame::pixel`]
+    pub fn point(&self, pixel: (f64, f64)) -> Point {
+        Point {
+            x: self.origin.x + (pixel.0 - self.margin) / self.scale,
+            y: self.origin.y - (pixel.1 - self.margin) / self.scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{level::LevelParams, test_util::p};
+
+    #[test]
+    fn scene_bounds() {
+        let mut scene = Scene::new();
+        assert_eq!(scene.bounds(), None);
+
+        scene
+            .sites(&[p(0.5, -1.0), p(2.0, 0.0)])
+            .label(&p(-1.0, 0.5), "a");
+        assert_eq!(scene.bounds(), Some((p(-1.0, -1.0), p(2.0, 0.5))));
+    }
+
+    #[test]
+    fn frame_keeps_proportions() {
+        let mut scene = Scene::new();
+        scene.boundary(&[vec![p(0.0, 0.0), p(2.0, 0.0), p(2.0, 1.0), p(0.0, 1.0)]]);
+
+        let style = Style {
+            width: 220,
+            margin: 10,
+            ..Default::default()
+        };
+        let frame = Frame::new(&scene, &style);
+
+        assert_eq!(frame.height, 120);
+        assert_eq!(frame.pixel(&p(0.0, 1.0)), (10.0, 10.0));
+        assert_eq!(frame.pixel(&p(2.0, 0.0)), (210.0, 110.0));
+        assert_eq!(frame.point((110.0, 60.0)), p(1.0, 0.5));
+    }
+
+    #[test]
+    fn whole_level() {
+        let level = Level::generate(0, &LevelParams::default());
+        let mut scene = Scene::new();
+        scene.level(&level);
+
+        let count = |layer| scene.shapes().iter().filter(|s| s.layer() == layer).count();
+
+        assert_eq!(count(Layer::Rooms), level.rooms.len());
+        assert_eq!(count(Layer::Labels), level.rooms.len());
+        assert_eq!(count(Layer::Doors), level.doors.len());
+        assert_eq!(count(Layer::Boundary), 1);
+    }
+}
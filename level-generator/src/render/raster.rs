@@ -0,0 +1,536 @@
+//! PNG pictures of scenes, drawn on the CPU.
+//!
+//! Shapes are filled scanline by scanline, with a few sub-scanlines per row of pixels and exact
+//! coverage along each of them for antialiasing. Outlines are made of a quad along each edge and
+//! a disc at each vertex, all of them merged before being painted so that their overlaps don't
+//! show through transparent colors. There is no font, so labels are left out.
+
+use std::{f64::consts::TAU, fs::File, io, io::BufWriter, path::Path};
+
+use voronator::delaunator::Point;
+
+use super::{Color, Frame, Paint, Scene, Shape, Style};
+use crate::{
+    geometry::containment::{locate, Location},
+    level::Room,
+};
+
+/// Sub-scanlines in each row of pixels
+const SUBSAMPLES: usize = 4;
+
+/// Sides of the polygons standing for discs
+const DISC_SIDES: usize = 16;
+
+/// Value at each point of the level, from 0 (cold) to 1 (hot), or `None` to leave the point as it
+/// is
+pub type Field<'a> = Box<dyn Fn(&Point) -> Option<f64> + 'a>;
+
+/// Colors over the level, drawn on top of the scene
+pub struct Heatmap<'a> {
+    pub value: Field<'a>,
+    pub cold: Color,
+    pub hot: Color,
+}
+
+impl<'a> Heatmap<'a> {
+    /// Half transparent, from blue to red
+    pub fn new(value: impl Fn(&Point) -> Option<f64> + 'a) -> Self {
+        Self {
+            value: Box::new(value),
+            cold: Color::rgb(0x30, 0x60, 0xe0).with_alpha(160),
+            hot: Color::rgb(0xe0, 0x30, 0x20).with_alpha(160),
+        }
+    }
+
+    /// One value per room, `values[i]` for `rooms[i]`, scaled between the lowest and highest ones
+    pub fn rooms(rooms: &'a [Room], values: &[f64]) -> Self {
+        assert_eq!(rooms.len(), values.len());
+
+        let low = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let high = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let values: Vec<f64> = values
+            .iter()
+            .map(|v| {
+                if high > low {
+                    (v - low) / (high - low)
+                } else {
+                    1.0
+                }
+            })
+            .collect();
+
+        Self::new(move |p| {
+            rooms
+                .iter()
+                .position(|room| locate(p, &room.outline) != Location::Outside)
+                .map(|i| values[i])
+        })
+    }
+
+    fn color(&self, value: f64) -> Color {
+        let t = value.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+
+        Color {
+            r: mix(self.cold.r, self.hot.r),
+            g: mix(self.cold.g, self.hot.g),
+            b: mix(self.cold.b, self.hot.b),
+            a: mix(self.cold.a, self.hot.a),
+        }
+    }
+}
+
+/// Picture made of RGBA pixels, row by row from the top left
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn write_png(&self, writer: impl io::Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|c| [c.r, c.g, c.b, c.a])
+            .collect();
+
+        encoder.write_header()?.write_image_data(&data)?;
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Draws `scene` with the heatmap on top, if any
+pub fn rasterise(scene: &Scene, style: &Style, heatmap: Option<&Heatmap>) -> Image {
+    let frame = Frame::new(scene, style);
+    let mut canvas = Canvas::new(frame.width as usize, frame.height as usize);
+
+    if let Some(background) = style.background {
+        canvas
+            .pixels
+            .iter_mut()
+            .for_each(|p| blend(p, background, 1.0));
+    }
+
+    for shape in scene.shapes() {
+        let paint = style.paint(shape.layer());
+        let to_pixels =
+            |ring: &[Point]| -> Vec<(f64, f64)> { ring.iter().map(|p| frame.pixel(p)).collect() };
+
+        match shape {
+            Shape::Area { rings, .. } => {
+                let rings: Vec<Vec<(f64, f64)>> = rings.iter().map(|r| to_pixels(r)).collect();
+
+                if let Some(fill) = paint.fill {
+                    canvas.cover(&rings);
+                    canvas.paint(fill);
+                }
+                for ring in &rings {
+                    canvas.cover_outline(ring, true, paint);
+                }
+                canvas.stroke(paint);
+            }
+            Shape::Line { segment, .. } => {
+                let line = to_pixels(&[segment.start.clone(), segment.end.clone()]);
+                canvas.cover_outline(&line, false, paint);
+                canvas.stroke(paint);
+            }
+            Shape::Dot { center, .. } => {
+                canvas.cover(&[disc(frame.pixel(center), style.site_radius)]);
+                if let Some(fill) = paint.fill {
+                    canvas.paint(fill);
+                }
+                canvas.clear();
+            }
+            Shape::Label { .. } => {}
+        }
+    }
+
+    if let Some(heatmap) = heatmap {
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                let p = frame.point((x as f64 + 0.5, y as f64 + 0.5));
+
+                if let Some(value) = (heatmap.value)(&p) {
+                    blend(
+                        &mut canvas.pixels[y * canvas.width + x],
+                        heatmap.color(value),
+                        1.0,
+                    );
+                }
+            }
+        }
+    }
+
+    Image {
+        width: frame.width,
+        height: frame.height,
+        pixels: canvas.pixels.iter().map(|p| unpremultiply(*p)).collect(),
+    }
+}
+
+/// Pixels with premultiplied alpha, along with how much of each of them the shape being drawn
+/// covers
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f64; 4]>,
+    coverage: Vec<f64>,
+    /// Rows and columns of the covered pixels, to only go through them when painting
+    covered: Option<(usize, usize, usize, usize)>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; width * height],
+            coverage: vec![0.0; width * height],
+            covered: None,
+        }
+    }
+
+    /// Covers the inside of `rings`, holes being the parts inside an even number of them
+    fn cover(&mut self, rings: &[Vec<(f64, f64)>]) {
+        let points = rings.iter().flatten();
+        let (mut x0, mut y0, mut x1, mut y1) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for &(x, y) in points {
+            (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+        }
+
+        if x1 < 0.0 || y1 < 0.0 || x0 >= self.width as f64 || y0 >= self.height as f64 {
+            return;
+        }
+
+        let (left, right) = (
+            x0.floor().max(0.0) as usize,
+            (x1.ceil() as usize).min(self.width),
+        );
+        let (top, bottom) = (
+            y0.floor().max(0.0) as usize,
+            (y1.ceil() as usize).min(self.height),
+        );
+
+        let edges: Vec<((f64, f64), (f64, f64))> = rings
+            .iter()
+            .flat_map(|ring| (0..ring.len()).map(|i| (ring[i], ring[(i + 1) % ring.len()])))
+            .filter(|(a, b)| a.1 != b.1)
+            .collect();
+
+        let mut row = vec![0.0; right - left];
+        let mut crossings = Vec::new();
+
+        for y in top..bottom {
+            row.iter_mut().for_each(|c| *c = 0.0);
+
+            for sub in 0..SUBSAMPLES {
+                let sample = y as f64 + (sub as f64 + 0.5) / SUBSAMPLES as f64;
+
+                crossings.clear();
+                for &(a, b) in &edges {
+                    if (a.1 <= sample) != (b.1 <= sample) {
+                        crossings.push(a.0 + (sample - a.1) * (b.0 - a.0) / (b.1 - a.1));
+                    }
+                }
+                crossings.sort_unstable_by(f64::total_cmp);
+
+                for span in crossings.chunks_exact(2) {
+                    add_span(&mut row, left, span[0], span[1], 1.0 / SUBSAMPLES as f64);
+                }
+            }
+
+            let line = &mut self.coverage[y * self.width + left..y * self.width + right];
+            for (covered, &c) in line.iter_mut().zip(&row) {
+                *covered = covered.max(c.min(1.0));
+            }
+        }
+
+        self.covered = Some(match self.covered {
+            Some((t, b, l, r)) => (t.min(top), b.max(bottom), l.min(left), r.max(right)),
+            None => (top, bottom, left, right),
+        });
+    }
+
+    /// Covers the line through `points` with the stroke of `paint`, going back to the first point
+    /// when `closed`
+    fn cover_outline(&mut self, points: &[(f64, f64)], closed: bool, paint: &Paint) {
+        if paint.stroke.is_none() || paint.width <= 0.0 || points.is_empty() {
+            return;
+        }
+
+        let half = paint.width / 2.0;
+        let count = if closed {
+            points.len()
+        } else {
+            points.len() - 1
+        };
+
+        for i in 0..count {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+            let length = dx.hypot(dy);
+
+            if length > 0.0 {
+                let (nx, ny) = (-dy / length * half, dx / length * half);
+                self.cover(&[vec![
+                    (a.0 + nx, a.1 + ny),
+                    (b.0 + nx, b.1 + ny),
+                    (b.0 - nx, b.1 - ny),
+                    (a.0 - nx, a.1 - ny),
+                ]]);
+            }
+        }
+
+        // round joins and caps
+        for &p in points {
+            self.cover(&[disc(p, half)]);
+        }
+    }
+
+    fn stroke(&mut self, paint: &Paint) {
+        if let Some(color) = paint.stroke {
+            self.paint(color);
+        }
+        self.clear();
+    }
+
+    /// Paints the covered pixels with `color`, and clears the coverage
+    fn paint(&mut self, color: Color) {
+        self.for_covered(|pixel, coverage| blend(pixel, color, coverage));
+    }
+
+    fn clear(&mut self) {
+        self.for_covered(|_, _| {});
+    }
+
+    fn for_covered(&mut self, mut f: impl FnMut(&mut [f64; 4], f64)) {
+        let Some((top, bottom, left, right)) = self.covered.take() else {
+            return;
+        };
+
+        for y in top..bottom {
+            for x in left..right {
+                let i = y * self.width + x;
+                if self.coverage[i] > 0.0 {
+                    f(&mut self.pixels[i], self.coverage[i]);
+                    self.coverage[i] = 0.0;
+                }
+            }
+        }
+    }
+}
+
+/// Adds `weight` times the part of each pixel of `row` between `from` and `to`, the row starting
+/// at column `left`
+fn add_span(row: &mut [f64], left: usize, from: f64, to: f64, weight: f64) {
+    let from = (from - left as f64).max(0.0);
+    let to = (to - left as f64).min(row.len() as f64);
+
+    if to <= from {
+        return;
+    }
+
+    let (first, last) = (from.floor() as usize, (to.ceil() as usize).min(row.len()));
+    for (x, c) in row.iter_mut().enumerate().take(last).skip(first) {
+        let overlap = (to.min(x as f64 + 1.0) - from.max(x as f64)).max(0.0);
+        *c += overlap * weight;
+    }
+}
+
+fn disc(center: (f64, f64), radius: f64) -> Vec<(f64, f64)> {
+    (0..DISC_SIDES)
+        .map(|i| {
+            let angle = TAU * i as f64 / DISC_SIDES as f64;
+            (
+                center.0 + radius * angle.cos(),
+                center.1 + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+/// Lays `color` over `pixel`, on `coverage` of its surface
+fn blend(pixel: &mut [f64; 4], color: Color, coverage: f64) {
+    let alpha = color.a as f64 / 255.0 * coverage;
+    let source = [color.r, color.g, color.b].map(|c| c as f64 / 255.0 * alpha);
+
+    for i in 0..3 {
+        pixel[i] = source[i] + pixel[i] * (1.0 - alpha);
+    }
+    pixel[3] = alpha + pixel[3] * (1.0 - alpha);
+}
+
+fn unpremultiply(pixel: [f64; 4]) -> Color {
+    let alpha = pixel[3];
+    let channel = |c: f64| {
+        if alpha > 0.0 {
+            (c / alpha * 255.0).round().clamp(0.0, 255.0) as u8
+        } else {
+            0
+        }
+    };
+
+    Color {
+        r: channel(pixel[0]),
+        g: channel(pixel[1]),
+        b: channel(pixel[2]),
+        a: (alpha * 255.0).round().clamp(0.0, 255.0) as u8,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        level::{Level, LevelParams},
+        render::Layer,
+        test_util::p,
+    };
+
+    /// The [0, 1] square on a 120 x 120 picture, 10 pixels from its sides
+    fn square_scene(layer: Layer) -> Scene {
+        let mut scene = Scene::new();
+        scene.add(Shape::Area {
+            layer,
+            rings: vec![vec![p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0), p(0.0, 1.0)]],
+        });
+        scene
+    }
+
+    fn style() -> Style {
+        Style {
+            width: 120,
+            margin: 10,
+            background: None,
+            rooms: Paint::fill(Color::rgb(200, 100, 0)),
+            boundary: Paint::stroke(Color::BLACK, 4.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn filled_square() {
+        let image = rasterise(&square_scene(Layer::Rooms), &style(), None);
+
+        assert_eq!((image.width, image.height), (120, 120));
+        assert_eq!(image.pixel(60, 60), Color::rgb(200, 100, 0));
+        assert_eq!(image.pixel(10, 10), Color::rgb(200, 100, 0));
+        assert_eq!(image.pixel(5, 60).a, 0);
+        assert_eq!(image.pixel(110, 60).a, 0);
+    }
+
+    #[test]
+    fn antialiased_edges() {
+        let mut scene = Scene::new();
+        // edge in the middle of the pixels of column 60
+        scene.add(Shape::Area {
+            layer: Layer::Rooms,
+            rings: vec![vec![p(0.0, 0.0), p(0.505, 0.0), p(0.505, 1.0), p(0.0, 1.0)]],
+        });
+        // keeps the frame the same as the square
+        scene.sites(&[p(1.0, 1.0)]);
+
+        let image = rasterise(&scene, &style(), None);
+        assert_eq!(image.pixel(59, 60).a, 255);
+        assert_eq!(image.pixel(60, 60).a, 128);
+        assert_eq!(image.pixel(61, 60).a, 0);
+    }
+
+    #[test]
+    fn outline() {
+        let image = rasterise(&square_scene(Layer::Boundary), &style(), None);
+
+        // 4 pixels wide around the side, nothing inside
+        assert_eq!(image.pixel(10, 60), Color::BLACK);
+        assert_eq!(image.pixel(8, 60), Color::BLACK);
+        assert_eq!(image.pixel(11, 60), Color::BLACK);
+        assert_eq!(image.pixel(6, 60).a, 0);
+        assert_eq!(image.pixel(60, 60).a, 0);
+        // round corners
+        assert_eq!(image.pixel(9, 9), Color::BLACK);
+        assert_eq!(image.pixel(7, 7).a, 0);
+    }
+
+    #[test]
+    fn transparent_strokes_dont_overlap() {
+        let style = Style {
+            boundary: Paint::stroke(Color::BLACK.with_alpha(100), 4.0),
+            ..style()
+        };
+        let image = rasterise(&square_scene(Layer::Boundary), &style, None);
+
+        // corners are covered by two edges and a disc
+        assert_eq!(image.pixel(10, 10).a, 100);
+        assert_eq!(image.pixel(10, 60).a, 100);
+    }
+
+    #[test]
+    fn heatmap() {
+        let heatmap = Heatmap {
+            value: Box::new(|p: &Point| (p.y > 0.5).then_some(if p.x < 0.5 { 0.0 } else { 1.0 })),
+            cold: Color::rgb(0, 0, 255),
+            hot: Color::rgb(255, 0, 0),
+        };
+        let image = rasterise(&square_scene(Layer::Rooms), &style(), Some(&heatmap));
+
+        assert_eq!(image.pixel(30, 30), Color::rgb(0, 0, 255));
+        assert_eq!(image.pixel(90, 30), Color::rgb(255, 0, 0));
+        assert_eq!(image.pixel(30, 90), Color::rgb(200, 100, 0));
+    }
+
+    #[test]
+    fn room_heatmap() {
+        let level = Level::generate(0, &LevelParams::default());
+        let values: Vec<f64> = (0..level.rooms.len()).map(|i| i as f64).collect();
+        let heatmap = Heatmap::rooms(&level.rooms, &values);
+
+        assert_eq!((heatmap.value)(&level.rooms[0].center), Some(0.0));
+        assert_eq!(
+            (heatmap.value)(&level.rooms.last().unwrap().center),
+            Some(1.0)
+        );
+        assert_eq!((heatmap.value)(&p(5.0, 5.0)), None);
+    }
+
+    #[test]
+    fn png_file() {
+        let level = Level::generate(0, &LevelParams::default());
+        let mut scene = Scene::new();
+        scene.level(&level);
+
+        let style = Style {
+            width: 200,
+            ..Default::default()
+        };
+        let image = rasterise(&scene, &style, None);
+        assert_eq!(image, rasterise(&scene, &style, None));
+
+        let mut file = Vec::new();
+        image.write_png(&mut file).unwrap();
+
+        let decoder = png::Decoder::new(file.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+
+        assert_eq!((info.width, info.height), (image.width, image.height));
+        let c = image.pixel(100, 50);
+        assert_eq!(&data[(50 * 200 + 100) * 4..][..4], &[c.r, c.g, c.b, c.a]);
+    }
+}
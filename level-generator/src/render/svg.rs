@@ -0,0 +1,248 @@
+//! SVG pictures of scenes.
+//!
+//! Every shape gets the class of its layer, and the style of the layers sits in a single
+//! `<style>` block at the top, so two pictures of the same scene only differ where the geometry
+//! does. Coordinates are rounded to a hundredth of a pixel for the same reason.
+
+use std::fmt::Write;
+
+use super::{Color, Frame, Layer, Paint, Scene, Shape, Style};
+
+const LAYERS: [Layer; 8] = [
+    Layer::Cells,
+    Layer::Rooms,
+    Layer::Halls,
+    Layer::Boundary,
+    Layer::Alpha,
+    Layer::Doors,
+    Layer::Sites,
+    Layer::Labels,
+];
+
+/// Draws `scene` as an SVG document
+pub fn write(scene: &Scene, style: &Style) -> String {
+    let frame = Frame::new(scene, style);
+    let mut svg = String::new();
+
+    // writing to a string never fails
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = frame.width,
+        h = frame.height
+    )
+    .unwrap();
+
+    svg.push_str("<style>\n");
+    for layer in LAYERS {
+        writeln!(svg, ".{} {{ {} }}", class(layer), css(style.paint(layer))).unwrap();
+    }
+    writeln!(
+        svg,
+        ".{} {{ font: {}px sans-serif; text-anchor: middle; dominant-baseline: central }}",
+        class(Layer::Labels),
+        style.label_size
+    )
+    .unwrap();
+    svg.push_str("</style>\n");
+
+    if let Some(background) = style.background {
+        writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="{}" fill-opacity="{:.3}"/>"#,
+            background.hex(),
+            opacity(background)
+        )
+        .unwrap();
+    }
+
+    for shape in scene.shapes() {
+        let class = class(shape.layer());
+
+        match shape {
+            Shape::Area { rings, .. } => {
+                let mut path = String::new();
+                for ring in rings.iter().filter(|ring| !ring.is_empty()) {
+                    for (i, p) in ring.iter().enumerate() {
+                        let (x, y) = frame.pixel(p);
+                        write!(path, "{}{x:.2} {y:.2} ", if i == 0 { "M" } else { "L" }).unwrap();
+                    }
+                    path.push('Z');
+                }
+
+                writeln!(svg, r#"<path class="{class}" d="{path}"/>"#).unwrap();
+            }
+            Shape::Line { segment, .. } => {
+                let (x1, y1) = frame.pixel(&segment.start);
+                let (x2, y2) = frame.pixel(&segment.end);
+
+                writeln!(
+                    svg,
+                    r#"<line class="{class}" x1="{x1:.2}" y1="{y1:.2}" x2="{x2:.2}" y2="{y2:.2}"/>"#
+                )
+                .unwrap();
+            }
+            Shape::Dot { center, .. } => {
+                let (x, y) = frame.pixel(center);
+
+                writeln!(
+                    svg,
+                    r#"<circle class="{class}" cx="{x:.2}" cy="{y:.2}" r="{}"/>"#,
+                    style.site_radius
+                )
+                .unwrap();
+            }
+            Shape::Label { at, text, .. } => {
+                let (x, y) = frame.pixel(at);
+
+                writeln!(
+                    svg,
+                    r#"<text class="{class}" x="{x:.2}" y="{y:.2}">{}</text>"#,
+                    escape(text)
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn class(layer: Layer) -> &'static str {
+    match layer {
+        Layer::Cells => "cells",
+        Layer::Rooms => "rooms",
+        Layer::Halls => "halls",
+        Layer::Boundary => "boundary",
+        Layer::Alpha => "alpha",
+        Layer::Doors => "doors",
+        Layer::Sites => "sites",
+        Layer::Labels => "labels",
+    }
+}
+
+fn css(paint: &Paint) -> String {
+    // holes are the rings inside other ones
+    let mut css =
+        String::from("fill-rule: evenodd; stroke-linecap: round; stroke-linejoin: round;");
+
+    match paint.fill {
+        Some(color) => write!(
+            css,
+            " fill: {}; fill-opacity: {:.3};",
+            color.hex(),
+            opacity(color)
+        ),
+        None => write!(css, " fill: none;"),
+    }
+    .unwrap();
+
+    match paint.stroke {
+        Some(color) if paint.width > 0.0 => write!(
+            css,
+            " stroke: {}; stroke-width: {}; stroke-opacity: {:.3};",
+            color.hex(),
+            paint.width,
+            opacity(color)
+        ),
+        _ => write!(css, " stroke: none;"),
+    }
+    .unwrap();
+
+    css
+}
+
+fn opacity(color: Color) -> f64 {
+    color.a as f64 / 255.0
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        level::{Level, LevelParams},
+        test_util::p,
+    };
+
+    #[test]
+    fn square_room() {
+        let mut scene = Scene::new();
+        scene
+            .boundary(&[vec![p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0), p(0.0, 1.0)]])
+            .label(&p(0.5, 0.5), "<1>");
+
+        let style = Style {
+            width: 120,
+            margin: 10,
+            background: None,
+            ..Default::default()
+        };
+        let svg = write(&scene, &style);
+
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="120" height="120""#)
+        );
+        assert!(svg.contains(
+            r#"<path class="boundary" d="M10.00 110.00 L110.00 110.00 L110.00 10.00 L10.00 10.00 Z"/>"#
+        ));
+        assert!(svg.contains(r#"<text class="labels" x="60.00" y="60.00">&lt;1&gt;</text>"#));
+        assert!(!svg.contains("<rect"));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn styling() {
+        let mut scene = Scene::new();
+        scene.sites(&[p(0.0, 0.0), p(1.0, 1.0)]);
+
+        let style = Style {
+            sites: Paint::fill(Color::rgb(255, 0, 0).with_alpha(51)),
+            site_radius: 5.0,
+            doors: Paint::stroke(Color::rgb(0, 0, 255), 0.0),
+            ..Default::default()
+        };
+        let svg = write(&scene, &style);
+
+        assert_eq!(svg.matches("<circle").count(), 2);
+        assert!(svg.contains(r#"r="5""#));
+        assert!(svg.contains(
+            ".sites { fill-rule: evenodd; stroke-linecap: round; stroke-linejoin: round; \
+             fill: #ff0000; fill-opacity: 0.200; stroke: none; }"
+        ));
+        assert!(svg.contains(
+            ".doors { fill-rule: evenodd; stroke-linecap: round; \
+             stroke-linejoin: round; fill: none; stroke: none; }"
+        ));
+        assert!(svg.contains(
+            r##"<rect width="100%" height="100%" fill="#ffffff" fill-opacity="1.000"/>"##
+        ));
+    }
+
+    #[test]
+    fn level_picture() {
+        let level = Level::generate(0, &LevelParams::default());
+        let mut scene = Scene::new();
+        scene.level(&level);
+
+        let svg = write(&scene, &Style::default());
+
+        assert_eq!(
+            svg.matches(r#"<path class="rooms""#).count(),
+            level.rooms.len()
+        );
+        assert_eq!(
+            svg.matches(r#"<line class="doors""#).count(),
+            level.doors.len()
+        );
+        assert_eq!(svg, write(&scene, &Style::default()));
+    }
+}
@@ -0,0 +1,657 @@
+//! Seedable point sampling inside a domain polygon.
+//!
+//! The sites fed to the Voronoi generation decide what the level looks like: uniform noise gives
+//! rooms of random sizes, blue noise (Poisson disk, jittered grid, low discrepancy sequences)
+//! gives even rooms, and clusters or density maps put many small rooms in some areas and a few
+//! large ones elsewhere. The same seed always gives the same points.
+
+use std::{collections::HashMap, f64::consts::TAU};
+
+use rand::{distributions::Uniform, rngs::SmallRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use crate::geometry::{
+    containment::{locate, Location},
+    distance, signed_area,
+};
+
+/// How many points are drawn for each one kept before giving up on a domain
+const MAX_TRIES: usize = 1000;
+
+/// Candidates tried around each point by Bridson's algorithm
+const POISSON_CANDIDATES: usize = 30;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Strategy {
+    /// Independent uniform points
+    Uniform,
+    /// Points at least `radius` apart from each other (Bridson's algorithm). Stops early when the
+    /// domain is full.
+    PoissonDisk { radius: f64 },
+    /// One point per cell of a square grid, moved randomly by up to `jitter` times the size of
+    /// the cell (0 gives the grid, 1 lets it go anywhere in the cell)
+    JitteredGrid { jitter: f64 },
+    /// Halton sequence in bases 2 and 3, randomly shifted
+    Halton,
+    /// Sobol sequence, randomly shifted
+    Sobol,
+    /// Gaussian blobs around `clusters` random centers, `spread` being their standard deviation
+    Clusters { clusters: usize, spread: f64 },
+    /// Points more likely where the density is higher
+    Density(DensityMap),
+}
+
+/// Grid of densities covering the bounding box of the domain, row by row from the bottom left.
+///
+/// Densities are relative to each other: a cell with a density of 2 gets twice as many points
+/// as a cell with a density of 1, and none if it is 0.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawDensityMap")]
+pub struct DensityMap {
+    columns: usize,
+    rows: usize,
+    values: Vec<f64>,
+}
+
+/// Density map as written in a file, before it is checked
+#[derive(Deserialize)]
+struct RawDensityMap {
+    columns: usize,
+    rows: usize,
+    values: Vec<f64>,
+}
+
+impl TryFrom<RawDensityMap> for DensityMap {
+    type Error = String;
+
+    fn try_from(raw: RawDensityMap) -> Result<Self, String> {
+        Self::try_new(raw.columns, raw.rows, raw.values)
+    }
+}
+
+impl DensityMap {
+    pub fn new(columns: usize, rows: usize, values: Vec<f64>) -> Self {
+        Self::try_new(columns, rows, values).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Same as `new`, with an error instead of a panic when the map is invalid
+    pub fn try_new(columns: usize, rows: usize, values: Vec<f64>) -> Result<Self, String> {
+        if values.len() != columns * rows {
+            return Err(format!(
+                "a {columns}x{rows} density map needs {} values, not {}",
+                columns * rows,
+                values.len()
+            ));
+        }
+        if !values.iter().all(|&v| v >= 0.0 && v.is_finite()) {
+            return Err("densities must be finite and not negative".into());
+        }
+        // this also rules out maps without rows or columns
+        if !values.iter().any(|&v| v > 0.0) {
+            return Err("a density map needs at least one density above 0".into());
+        }
+
+        Ok(Self {
+            columns,
+            rows,
+            values,
+        })
+    }
+
+    /// Density at `(u, v)`, both between 0 and 1 across the map
+    fn at(&self, u: f64, v: f64) -> f64 {
+        let column = ((u * self.columns as f64) as usize).min(self.columns - 1);
+        let row = ((v * self.rows as f64) as usize).min(self.rows - 1);
+
+        self.values[row * self.columns + column]
+    }
+
+    fn max(&self) -> f64 {
+        self.values.iter().copied().fold(0.0, f64::max)
+    }
+}
+
+/// Draws about `count` points strictly inside `domain` with the given `strategy`.
+///
+/// Most strategies give exactly `count` points; Poisson disk sampling gives fewer when the
+/// domain is full, and the jittered grid gives one point per grid cell in the domain, which is
+/// close to `count` but rarely exactly it.
+pub fn sample(seed: u64, domain: &Polygon<Point>, strategy: &Strategy, count: usize) -> Vec<Point> {
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let domain = Domain::new(domain);
+
+    match strategy {
+        Strategy::Uniform => domain.fill(count, || domain.uniform(&mut rng)),
+        Strategy::PoissonDisk { radius } => poisson_disk(&mut rng, &domain, *radius, count),
+        Strategy::JitteredGrid { jitter } => jittered_grid(&mut rng, &domain, *jitter, count),
+        Strategy::Halton => {
+            let shift = (rng.gen::<f64>(), rng.gen::<f64>());
+            let mut index = 0;
+
+            domain.fill(count, || {
+                index += 1;
+                domain.unit(shifted(
+                    (radical_inverse(index, 2), radical_inverse(index, 3)),
+                    shift,
+                ))
+            })
+        }
+        Strategy::Sobol => {
+            let shift = (rng.gen::<f64>(), rng.gen::<f64>());
+            let mut sobol = Sobol::new();
+
+            domain.fill(count, || domain.unit(shifted(sobol.next(), shift)))
+        }
+        Strategy::Clusters { clusters, spread } => {
+            assert!(*clusters > 0);
+
+            let centers: Vec<Point> = domain.fill(*clusters, || domain.uniform(&mut rng));
+
+            domain.fill(count, || {
+                let center = &centers[rng.gen_range(0..centers.len())];
+
+                // offsets landing outside are drawn again, a spread much wider than the domain
+                // hardly ever lands inside and gives uniform points instead
+                for _ in 0..MAX_TRIES {
+                    let (x, y) = gaussian(&mut rng);
+                    let p = Point {
+                        x: center.x + spread * x,
+                        y: center.y + spread * y,
+                    };
+
+                    if domain.contains(&p) {
+                        return p;
+                    }
+                }
+
+                domain.uniform(&mut rng)
+            })
+        }
+        Strategy::Density(map) => {
+            let max = map.max();
+
+            domain.fill(count, || {
+                // a draw is kept with a probability of at least one in `values.len()`
+                for _ in 0..MAX_TRIES * map.values.len() {
+                    let (u, v) = (rng.gen::<f64>(), rng.gen::<f64>());
+
+                    if rng.gen::<f64>() * max < map.at(u, v) {
+                        return domain.unit((u, v));
+                    }
+                }
+
+                panic!("Unable to sample points with the density map")
+            })
+        }
+    }
+}
+
+/// Domain polygon with its bounding box
+struct Domain {
+    points: Vec<Point>,
+    min: Point,
+    max: Point,
+}
+
+impl Domain {
+    fn new(polygon: &Polygon<Point>) -> Self {
+        let points = polygon.points().to_vec();
+        assert!(points.len() >= 3 && signed_area(&points) != 0.0);
+
+        let min = Point {
+            x: points.iter().map(|p| p.x).fold(f64::INFINITY, f64::min),
+            y: points.iter().map(|p| p.y).fold(f64::INFINITY, f64::min),
+        };
+        let max = Point {
+            x: points.iter().map(|p| p.x).fold(f64::NEG_INFINITY, f64::max),
+            y: points.iter().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max),
+        };
+
+        Self { points, min, max }
+    }
+
+    fn contains(&self, p: &Point) -> bool {
+        locate(p, &self.points) == Location::Inside
+    }
+
+    /// Point of the bounding box at `(u, v)`, both between 0 and 1
+    fn unit(&self, (u, v): (f64, f64)) -> Point {
+        Point {
+            x: self.min.x + u * (self.max.x - self.min.x),
+            y: self.min.y + v * (self.max.y - self.min.y),
+        }
+    }
+
+    fn uniform(&self, rng: &mut SmallRng) -> Point {
+        let distr = Uniform::new(0.0, 1.0);
+        self.unit((rng.sample(distr), rng.sample(distr)))
+    }
+
+    /// Keeps the candidates inside the domain until there are `count` of them
+    fn fill(&self, count: usize, mut candidate: impl FnMut() -> Point) -> Vec<Point> {
+        let mut points = Vec::with_capacity(count);
+        let mut tries = 0;
+
+        while points.len() < count {
+            let p = candidate();
+
+            if self.contains(&p) {
+                points.push(p);
+            }
+
+            tries += 1;
+            assert!(
+                tries <= MAX_TRIES * count.max(1),
+                "Unable to sample points inside the domain"
+            );
+        }
+
+        points
+    }
+}
+
+/// Bridson's algorithm: grows the set from a random point, trying candidates around the points
+/// that still have room next to them.
+fn poisson_disk(rng: &mut SmallRng, domain: &Domain, radius: f64, count: usize) -> Vec<Point> {
+    assert!(radius > 0.0);
+
+    // a cell is small enough to hold at most one point, only the occupied ones are stored so a
+    // tiny radius doesn't allocate a huge grid
+    let cell = radius / f64::sqrt(2.0);
+    let mut grid: HashMap<(usize, usize), usize> = HashMap::new();
+
+    let cell_of = |p: &Point| {
+        (
+            ((p.x - domain.min.x) / cell) as usize,
+            ((p.y - domain.min.y) / cell) as usize,
+        )
+    };
+
+    let mut points: Vec<Point> = Vec::new();
+    let mut active = Vec::new();
+
+    if count == 0 {
+        return points;
+    }
+
+    let first = domain.fill(1, || domain.uniform(rng)).remove(0);
+    grid.insert(cell_of(&first), 0);
+    points.push(first);
+    active.push(0);
+
+    while !active.is_empty() && points.len() < count {
+        let index = rng.gen_range(0..active.len());
+        let center = points[active[index]].clone();
+
+        let found = (0..POISSON_CANDIDATES)
+            .map(|_| {
+                let angle = rng.gen::<f64>() * TAU;
+                let r = radius * (1.0 + rng.gen::<f64>());
+
+                Point {
+                    x: center.x + r * angle.cos(),
+                    y: center.y + r * angle.sin(),
+                }
+            })
+            .find(|candidate| {
+                if !domain.contains(candidate) {
+                    return false;
+                }
+
+                let (column, row) = cell_of(candidate);
+
+                (row.saturating_sub(2)..=row.saturating_add(2)).all(|r| {
+                    (column.saturating_sub(2)..=column.saturating_add(2)).all(|c| {
+                        grid.get(&(c, r))
+                            .is_none_or(|&other| distance(&points[other], candidate) >= radius)
+                    })
+                })
+            });
+
+        match found {
+            Some(candidate) => {
+                grid.insert(cell_of(&candidate), points.len());
+                active.push(points.len());
+                points.push(candidate);
+            }
+            None => {
+                active.swap_remove(index);
+            }
+        }
+    }
+
+    points
+}
+
+fn jittered_grid(rng: &mut SmallRng, domain: &Domain, jitter: f64, count: usize) -> Vec<Point> {
+    assert!((0.0..=1.0).contains(&jitter));
+
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let area = signed_area(&domain.points).abs();
+    let size = f64::sqrt(area / count as f64);
+
+    let columns = ((domain.max.x - domain.min.x) / size).ceil() as usize;
+    let rows = ((domain.max.y - domain.min.y) / size).ceil() as usize;
+
+    // centers the grid on the bounding box
+    let origin = Point {
+        x: (domain.min.x + domain.max.x - columns as f64 * size) / 2.0,
+        y: (domain.min.y + domain.max.y - rows as f64 * size) / 2.0,
+    };
+
+    let mut points = Vec::new();
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let (dx, dy) = (rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5);
+
+            let p = Point {
+                x: origin.x + (column as f64 + 0.5 + jitter * dx) * size,
+                y: origin.y + (row as f64 + 0.5 + jitter * dy) * size,
+            };
+
+            if domain.contains(&p) {
+                points.push(p);
+            }
+        }
+    }
+
+    points
+}
+
+/// Adds `shift` to both coordinates, wrapping around the unit square
+fn shifted((u, v): (f64, f64), (du, dv): (f64, f64)) -> (f64, f64) {
+    ((u + du).fract(), (v + dv).fract())
+}
+
+/// `index` written in `base`, mirrored around the decimal point
+fn radical_inverse(mut index: u64, base: u64) -> f64 {
+    let mut result = 0.0;
+    let mut scale = 1.0 / base as f64;
+
+    while index > 0 {
+        result += (index % base) as f64 * scale;
+        index /= base;
+        scale /= base as f64;
+    }
+
+    result
+}
+
+/// Two dimensional Sobol sequence, generated in Gray code order
+struct Sobol {
+    index: u32,
+    x: u32,
+    y: u32,
+}
+
+impl Sobol {
+    fn new() -> Self {
+        Self {
+            index: 0,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    fn next(&mut self) -> (f64, f64) {
+        self.index += 1;
+        // the bit that changes between the Gray codes of index - 1 and index
+        let bit = self.index.trailing_zeros();
+
+        self.x ^= 1 << (31 - bit);
+        self.y ^= Self::direction(bit);
+
+        let scale = 1.0 / (1u64 << 32) as f64;
+        (self.x as f64 * scale, self.y as f64 * scale)
+    }
+
+    /// Direction numbers of the second dimension, from the primitive polynomial x + 1
+    fn direction(bit: u32) -> u32 {
+        (0..bit).fold(1 << 31, |v, _| v ^ (v >> 1))
+    }
+}
+
+/// Pair of independent standard normal values (Box-Muller transform)
+pub(crate) fn gaussian(rng: &mut SmallRng) -> (f64, f64) {
+    // avoids taking the log of 0
+    let u = 1.0 - rng.gen::<f64>();
+    let v = rng.gen::<f64>();
+
+    let r = f64::sqrt(-2.0 * u.ln());
+    (r * (TAU * v).cos(), r * (TAU * v).sin())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::test_util::{p, square};
+
+    fn l_shape() -> Polygon<Point> {
+        Polygon::from_points(vec![
+            p(-1.0, -1.0),
+            p(1.0, -1.0),
+            p(1.0, 0.0),
+            p(0.0, 0.0),
+            p(0.0, 1.0),
+            p(-1.0, 1.0),
+        ])
+    }
+
+    fn strategies() -> Vec<Strategy> {
+        vec![
+            Strategy::Uniform,
+            Strategy::PoissonDisk { radius: 0.1 },
+            Strategy::JitteredGrid { jitter: 0.8 },
+            Strategy::Halton,
+            Strategy::Sobol,
+            Strategy::Clusters {
+                clusters: 3,
+                spread: 0.2,
+            },
+            Strategy::Density(DensityMap::new(2, 2, vec![1.0, 2.0, 0.5, 0.0])),
+        ]
+    }
+
+    /// Number of points in each quarter of the square of size 1
+    fn quadrants(points: &[Point]) -> [usize; 4] {
+        let mut counts = [0; 4];
+        for q in points {
+            counts[(q.x > 0.0) as usize + 2 * (q.y > 0.0) as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn points_are_inside_the_domain() {
+        let domain = l_shape();
+
+        for strategy in strategies() {
+            let points = sample(1, &domain, &strategy, 50);
+
+            assert!(!points.is_empty(), "{:?}", strategy);
+            for q in &points {
+                assert_eq!(
+                    locate(q, domain.points()),
+                    Location::Inside,
+                    "{:?}",
+                    strategy
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn seeds_are_deterministic() {
+        for strategy in strategies() {
+            let a = sample(7, &square(1.0), &strategy, 30);
+
+            assert_eq!(a, sample(7, &square(1.0), &strategy, 30));
+            assert_ne!(a, sample(8, &square(1.0), &strategy, 30), "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn exact_counts() {
+        for strategy in [Strategy::Uniform, Strategy::Halton, Strategy::Sobol] {
+            assert_eq!(sample(3, &l_shape(), &strategy, 123).len(), 123);
+        }
+        assert!(sample(3, &l_shape(), &Strategy::Uniform, 0).is_empty());
+    }
+
+    #[test]
+    fn poisson_disk_spacing() {
+        let points = sample(
+            5,
+            &square(1.0),
+            &Strategy::PoissonDisk { radius: 0.2 },
+            1000,
+        );
+
+        for (i, a) in points.iter().enumerate() {
+            for b in &points[i + 1..] {
+                assert!(distance(a, b) >= 0.2);
+            }
+        }
+
+        // the square is full long before a thousand points
+        assert!(points.len() < 200);
+        assert!(points.len() > 40);
+    }
+
+    #[test]
+    fn poisson_disk_tiny_radius() {
+        let points = sample(5, &square(1.0), &Strategy::PoissonDisk { radius: 1e-9 }, 50);
+
+        assert_eq!(points.len(), 50);
+    }
+
+    #[test]
+    fn jittered_grid() {
+        let grid = sample(5, &square(1.0), &Strategy::JitteredGrid { jitter: 0.0 }, 16);
+
+        assert_eq!(grid.len(), 16);
+        for q in &grid {
+            // cell centers of a 4x4 grid
+            assert!(((q.x + 1.0) / 0.5 - 0.5).fract().abs() < 1e-12);
+            assert!(((q.y + 1.0) / 0.5 - 0.5).fract().abs() < 1e-12);
+        }
+
+        let jittered = sample(
+            5,
+            &square(1.0),
+            &Strategy::JitteredGrid { jitter: 1.0 },
+            100,
+        );
+        assert_eq!(jittered.len(), 100);
+        assert_eq!(quadrants(&jittered), [25; 4]);
+    }
+
+    #[test]
+    fn low_discrepancy_sequences() {
+        for strategy in [Strategy::Halton, Strategy::Sobol] {
+            for count in quadrants(&sample(11, &square(1.0), &strategy, 256)) {
+                assert!((60..=68).contains(&count), "{:?}", strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_first_points() {
+        let mut sobol = Sobol::new();
+        let points: Vec<_> = (0..4).map(|_| sobol.next()).collect();
+
+        assert_eq!(
+            points,
+            vec![(0.5, 0.5), (0.75, 0.25), (0.25, 0.75), (0.375, 0.375)]
+        );
+    }
+
+    #[test]
+    fn clusters_are_tight() {
+        let nearest = |points: &[Point]| {
+            points
+                .iter()
+                .enumerate()
+                .map(|(i, a)| {
+                    points
+                        .iter()
+                        .enumerate()
+                        .filter(|&(j, _)| j != i)
+                        .map(|(_, b)| distance(a, b))
+                        .fold(f64::INFINITY, f64::min)
+                })
+                .sum::<f64>()
+                / points.len() as f64
+        };
+
+        let uniform = sample(2, &square(1.0), &Strategy::Uniform, 100);
+        let clusters = sample(
+            2,
+            &square(1.0),
+            &Strategy::Clusters {
+                clusters: 2,
+                spread: 0.05,
+            },
+            100,
+        );
+
+        assert!(nearest(&clusters) < nearest(&uniform) / 2.0);
+    }
+
+    #[test]
+    fn wide_clusters() {
+        for spread in [50.0, 1e300] {
+            let strategy = Strategy::Clusters {
+                clusters: 3,
+                spread,
+            };
+            let points = sample(2, &l_shape(), &strategy, 50);
+
+            assert_eq!(points.len(), 50);
+            assert!(points
+                .iter()
+                .all(|q| locate(q, l_shape().points()) == Location::Inside));
+        }
+    }
+
+    #[test]
+    fn density_map() {
+        // nothing in the left column
+        let map = DensityMap::new(2, 1, vec![0.0, 1.0]);
+        let points = sample(4, &square(1.0), &Strategy::Density(map), 100);
+
+        assert!(points.iter().all(|q| q.x >= 0.0));
+
+        // three times as many points at the top
+        let map = DensityMap::new(1, 2, vec![1.0, 3.0]);
+        let points = sample(4, &square(1.0), &Strategy::Density(map), 1000);
+        let top = points.iter().filter(|q| q.y > 0.0).count();
+
+        assert!((700..800).contains(&top));
+    }
+
+    #[test]
+    fn invalid_density_maps() {
+        assert!(DensityMap::try_new(2, 2, vec![1.0; 3]).is_err());
+        assert!(DensityMap::try_new(1, 2, vec![1.0, -1.0]).is_err());
+        assert!(DensityMap::try_new(1, 1, vec![f64::NAN]).is_err());
+        assert!(DensityMap::try_new(1, 2, vec![0.0, 0.0]).is_err());
+        assert!(DensityMap::try_new(0, 0, vec![]).is_err());
+
+        // files get the same checks
+        let map: Result<DensityMap, _> =
+            serde_json::from_str(r#"{ "columns": 2, "rows": 1, "values": [0.0, 0.0] }"#);
+        assert!(map.unwrap_err().to_string().contains("above 0"));
+
+        let map: DensityMap =
+            serde_json::from_str(r#"{ "columns": 2, "rows": 1, "values": [0.0, 1.0] }"#).unwrap();
+        assert_eq!(map, DensityMap::new(2, 1, vec![0.0, 1.0]));
+    }
+}
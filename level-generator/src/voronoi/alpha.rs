@@ -0,0 +1,423 @@
+//! Alpha shapes (concave hulls) of point sets.
+//!
+//! The shape is the union of the Delaunay triangles whose circumradius is below alpha, so it can
+//! be made of several disconnected components, each of them possibly having holes.
+
+use std::collections::HashSet;
+
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use super::delaunay::Triangulation;
+use crate::geometry::{
+    containment::{locate, locate_polygon, Location},
+    fill::trace_rings,
+    signed_area,
+};
+
+/// One connected piece of an alpha shape
+pub struct Component {
+    /// Outer boundary, counter-clockwise
+    pub outer: Polygon<Point>,
+    /// Boundaries of the holes, clockwise
+    pub holes: Vec<Polygon<Point>>,
+}
+
+impl Component {
+    /// Area of the outer ring minus the holes
+    pub fn area(&self) -> f64 {
+        signed_area(self.outer.points())
+            + self
+                .holes
+                .iter()
+                .map(|hole| signed_area(hole.points()))
+                .sum::<f64>()
+    }
+
+    pub fn locate(&self, p: &Point) -> Location {
+        match locate(p, self.outer.points()) {
+            Location::Inside => {
+                for hole in &self.holes {
+                    match locate(p, hole.points()) {
+                        Location::Inside => return Location::Outside,
+                        Location::Boundary => return Location::Boundary,
+                        Location::Outside => {}
+                    }
+                }
+
+                Location::Inside
+            }
+            location => location,
+        }
+    }
+}
+
+pub struct AlphaShape {
+    components: Vec<Component>,
+}
+
+impl AlphaShape {
+    /// Components, from the largest to the smallest
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    pub fn into_components(self) -> Vec<Component> {
+        self.components
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    pub fn area(&self) -> f64 {
+        self.components.iter().map(Component::area).sum()
+    }
+
+    pub fn locate(&self, p: &Point) -> Location {
+        self.components
+            .iter()
+            .map(|c| c.locate(p))
+            .find(|&location| location != Location::Outside)
+            .unwrap_or(Location::Outside)
+    }
+}
+
+/// Computes the alpha shape of `points`.
+///
+/// An infinite alpha gives the convex hull; the smaller alpha gets, the deeper the shape digs into
+/// the point set, until it splits into several components and eventually vanishes.
+pub fn alpha_shape(points: &[Point], alpha: f64) -> AlphaShape {
+    shape(points, &triangles(points), alpha)
+}
+
+/// Smallest alpha giving a single component that covers all `points`.
+///
+/// This is the tightest silhouette that still keeps the level in one piece. Pass the result to
+/// [`alpha_shape`] to get the shape itself.
+pub fn connected_alpha(points: &[Point]) -> f64 {
+    let triangles = triangles(points);
+
+    // parts touching at a single vertex can split apart again as alpha grows, so unlike the
+    // area, connectivity can't be bisected and the candidates are tried in order
+    candidates(&triangles)
+        .into_iter()
+        .find(|&alpha| {
+            let shape = shape(points, &triangles, alpha);
+            let mut covered = vec![false; points.len()];
+
+            for (tri, radius) in &triangles {
+                if kept(*radius, alpha) {
+                    tri.iter().for_each(|&i| covered[i] = true);
+                }
+            }
+
+            shape.components().len() == 1 && covered.iter().all(|&c| c)
+        })
+        .unwrap_or(f64::INFINITY)
+}
+
+/// Smallest alpha whose shape covers at least `ratio` of the area of the convex hull.
+///
+/// The lower the ratio, the more concave the shape: 1 gives the convex hull back, while 0.7 lets
+/// the boundary dig into the emptier parts of the point set until 30% of the hull is left out.
+pub fn concavity_alpha(points: &[Point], ratio: f64) -> f64 {
+    assert!((0.0..=1.0).contains(&ratio));
+
+    let triangles = triangles(points);
+    let hull = shape(points, &triangles, f64::INFINITY).area();
+
+    smallest_alpha(&triangles, |alpha| {
+        shape(points, &triangles, alpha).area() >= ratio * hull
+    })
+}
+
+/// Counter-clockwise Delaunay triangles of `points`, with their circumradius
+fn triangles(points: &[Point]) -> Vec<([usize; 3], f64)> {
+    assert!(points.len() > 3);
+
+    let t = Triangulation::new(points).expect("Unable to perform Delaunay triangulation");
+
+    (0..t.triangle_count())
+        .map(|i| (t.triangle(i), t.circumradius(i)))
+        .collect()
+}
+
+fn kept(radius: f64, alpha: f64) -> bool {
+    alpha == f64::INFINITY || radius < alpha
+}
+
+fn shape(points: &[Point], triangles: &[([usize; 3], f64)], alpha: f64) -> AlphaShape {
+    let triangles: Vec<[usize; 3]> = triangles
+        .iter()
+        .filter(|(_, radius)| kept(*radius, alpha))
+        .map(|(tri, _)| *tri)
+        .collect();
+
+    AlphaShape {
+        components: components(points, &triangles),
+    }
+}
+
+/// Alphas giving distinct shapes, in increasing order.
+///
+/// The shape only changes when alpha goes past a circumradius, so those are the only candidates.
+fn candidates(triangles: &[([usize; 3], f64)]) -> Vec<f64> {
+    let mut candidates: Vec<f64> = triangles
+        .iter()
+        .map(|(_, radius)| radius.next_up())
+        .filter(|alpha| alpha.is_finite())
+        .collect();
+    candidates.sort_by(|a, b| a.total_cmp(b));
+    candidates.dedup();
+
+    candidates
+}
+
+/// Smallest alpha satisfying `accept`, which must keep accepting as alpha grows
+fn smallest_alpha(triangles: &[([usize; 3], f64)], accept: impl Fn(f64) -> bool) -> f64 {
+    let candidates = candidates(triangles);
+    let first = candidates.partition_point(|&alpha| !accept(alpha));
+
+    candidates.get(first).copied().unwrap_or(f64::INFINITY)
+}
+
+/// Assembles the boundary of the union of counter-clockwise `triangles` into components
+fn components(points: &[Point], triangles: &[[usize; 3]]) -> Vec<Component> {
+    let edges: HashSet<(usize, usize)> = triangles
+        .iter()
+        .flat_map(|tri| [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])])
+        .collect();
+
+    // the inside is on the left of boundary edges
+    let boundary = edges
+        .iter()
+        .filter(|&&(i, j)| !edges.contains(&(j, i)))
+        .copied();
+
+    let rings: Vec<_> = trace_rings(points, boundary)
+        .into_iter()
+        .map(Polygon::from_points)
+        .collect();
+
+    let (mut outers, holes): (Vec<_>, Vec<_>) = rings
+        .into_iter()
+        .partition(|ring| signed_area(ring.points()) > 0.0);
+
+    outers.sort_by(|a, b| signed_area(b.points()).total_cmp(&signed_area(a.points())));
+
+    let mut components: Vec<Component> = outers
+        .into_iter()
+        .map(|outer| Component {
+            outer,
+            holes: Vec::new(),
+        })
+        .collect();
+
+    for hole in holes {
+        // the smallest ring around the hole is the one it belongs to
+        let reversed = Polygon::from_points(hole.points().iter().rev().cloned().collect());
+
+        if let Some(component) = components
+            .iter_mut()
+            .rev()
+            .find(|c| locate_polygon(&reversed, &c.outer) == Location::Inside)
+        {
+            component.holes.push(hole);
+        }
+    }
+
+    components
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{random_points, test_util::p};
+
+    /// Points on a grid with unit spacing, `size` points wide
+    fn grid(size: usize, keep: impl Fn(usize, usize) -> bool) -> Vec<Point> {
+        let mut points = Vec::new();
+
+        for i in 0..size {
+            for j in 0..size {
+                if keep(i, j) {
+                    // tiny offsets avoid co-circular points, which makes the triangulation unique
+                    let jitter = ((i * 7 + j * 13) % 10) as f64 * 1e-4;
+                    points.push(p(i as f64 + jitter, j as f64 - jitter));
+                }
+            }
+        }
+
+        points
+    }
+
+    #[test]
+    fn infinite_alpha_gives_convex_hull() {
+        let points = random_points(50);
+        let shape = alpha_shape(&points, f64::INFINITY);
+
+        assert_eq!(shape.components().len(), 1);
+        assert!(shape.components()[0].holes.is_empty());
+
+        let hull = shape.components()[0].outer.points();
+        assert!(crate::geometry::clip::is_convex(hull));
+
+        for point in &points {
+            assert_ne!(shape.locate(point), Location::Outside);
+        }
+    }
+
+    #[test]
+    fn square_grid() {
+        let points = grid(5, |_, _| true);
+        let shape = alpha_shape(&points, 1.0);
+
+        assert_eq!(shape.components().len(), 1);
+        assert!((shape.area() - 16.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn courtyard() {
+        // a 7x7 grid with the middle 3x3 block removed
+        let points = grid(7, |i, j| !(2..=4).contains(&i) || !(2..=4).contains(&j));
+        let shape = alpha_shape(&points, 1.0);
+
+        assert_eq!(shape.components().len(), 1);
+
+        let component = &shape.components()[0];
+        assert_eq!(component.holes.len(), 1);
+        assert!(signed_area(component.holes[0].points()) < 0.0);
+
+        // the corners of the courtyard are cut diagonally
+        assert!((component.area() - (36.0 - 14.0)).abs() < 1e-2);
+        assert_eq!(shape.locate(&p(3.0, 3.0)), Location::Outside);
+        assert_eq!(shape.locate(&p(0.5, 3.0)), Location::Inside);
+        assert_eq!(shape.locate(&p(10.0, 3.0)), Location::Outside);
+    }
+
+    #[test]
+    fn islands() {
+        let mut points = grid(4, |_, _| true);
+        points.extend(grid(3, |_, _| true).into_iter().map(|q| p(q.x + 10.0, q.y)));
+
+        let shape = alpha_shape(&points, 1.0);
+
+        assert_eq!(shape.components().len(), 2);
+        // largest first
+        assert!((shape.components()[0].area() - 9.0).abs() < 1e-2);
+        assert!((shape.components()[1].area() - 4.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn touching_components() {
+        // two squares sharing a single corner, with their centers to keep the triangles small
+        let points = vec![
+            p(0.0, 0.0),
+            p(1.0, 0.0),
+            p(1.0, 1.0),
+            p(0.0, 1.0),
+            p(0.5, 0.5),
+            p(2.0, 1.0),
+            p(2.0, 2.0),
+            p(1.0, 2.0),
+            p(1.5, 1.5),
+        ];
+
+        let shape = alpha_shape(&points, 0.6);
+
+        assert_eq!(shape.components().len(), 2);
+        for component in shape.components() {
+            assert_eq!(component.outer.points().len(), 4);
+            assert!((component.area() - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn connected_alpha_covers_all_points() {
+        for n in [10, 50, 200] {
+            let points = random_points(n);
+            let alpha = connected_alpha(&points);
+
+            let shape = alpha_shape(&points, alpha);
+            assert_eq!(shape.components().len(), 1);
+            for point in &points {
+                assert_ne!(shape.locate(point), Location::Outside);
+            }
+
+            // it is the smallest one
+            let tighter = alpha_shape(&points, alpha.next_down());
+            assert!(
+                tighter.components().len() != 1
+                    || points
+                        .iter()
+                        .any(|point| tighter.locate(point) == Location::Outside)
+            );
+        }
+    }
+
+    #[test]
+    fn connected_alpha_of_islands() {
+        let mut points = grid(4, |_, _| true);
+        points.extend(grid(3, |_, _| true).into_iter().map(|q| p(q.x + 10.0, q.y)));
+
+        let alpha = connected_alpha(&points);
+
+        // the islands have to be bridged
+        assert!(alpha > 3.0);
+        assert_eq!(alpha_shape(&points, alpha).components().len(), 1);
+    }
+
+    #[test]
+    fn connected_alpha_of_touching_components() {
+        let points = vec![
+            p(0.0, 0.0),
+            p(1.0, 0.0),
+            p(1.0, 1.0),
+            p(0.0, 1.0),
+            p(0.5, 0.5),
+            p(2.0, 1.0),
+            p(2.0, 2.0),
+            p(1.0, 2.0),
+            p(1.5, 1.5),
+        ];
+
+        let alpha = connected_alpha(&points);
+
+        // the first candidate keeping the shape in one piece, whatever happens after it
+        let first = candidates(&triangles(&points))
+            .into_iter()
+            .find(|&alpha| alpha_shape(&points, alpha).components().len() == 1)
+            .unwrap();
+        assert_eq!(alpha, first);
+        assert!(alpha > 0.6);
+    }
+
+    #[test]
+    fn concavity_alpha_reaches_ratio() {
+        let points = random_points(100);
+        let hull = alpha_shape(&points, f64::INFINITY).area();
+
+        for ratio in [0.5, 0.8, 0.95] {
+            let alpha = concavity_alpha(&points, ratio);
+
+            assert!(alpha_shape(&points, alpha).area() >= ratio * hull);
+            assert!(alpha_shape(&points, alpha.next_down()).area() < ratio * hull);
+        }
+
+        assert_eq!(concavity_alpha(&points, 0.0), {
+            let mut radii: Vec<_> = triangles(&points).iter().map(|(_, r)| *r).collect();
+            radii.sort_by(|a, b| a.total_cmp(b));
+            radii[0].next_up()
+        });
+    }
+
+    #[test]
+    fn tiny_alpha_is_empty() {
+        let shape = alpha_shape(&random_points(20), 1e-6);
+
+        assert!(shape.is_empty());
+        assert_eq!(shape.area(), 0.0);
+    }
+}
@@ -0,0 +1,396 @@
+//! Delaunay triangulations, with half-edge navigation.
+//!
+//! Every triangle `t` is made of the half-edges `3 * t`, `3 * t + 1` and `3 * t + 2`, going
+//! counter-clockwise around it. A half-edge goes from its origin to the origin of the next one,
+//! and its twin is the same edge going the other way in the neighbouring triangle, if there is
+//! one. Half-edges without a twin make the convex hull.
+
+use std::collections::HashMap;
+
+use voronator::delaunator::{triangulate, Point};
+
+use crate::geometry::{circumcenter, distance, orientation, Orientation, Segment};
+
+pub struct Triangulation {
+    points: Vec<Point>,
+    /// Origin of each half-edge
+    origins: Vec<usize>,
+    twins: Vec<Option<usize>>,
+    /// One half-edge going out of each point, the first one counter-clockwise around it
+    outgoing: Vec<Option<usize>>,
+    /// Half-edges of the hull, counter-clockwise
+    hull: Vec<usize>,
+}
+
+impl Triangulation {
+    /// Triangulates `points`, or returns `None` if they are all aligned.
+    ///
+    /// Flat triangles are left out, and so are duplicate points, which get no triangle at all.
+    pub fn new(points: &[Point]) -> Option<Self> {
+        let t = triangulate(points)?;
+
+        let origins: Vec<usize> = t
+            .triangles
+            .chunks_exact(3)
+            .filter_map(|tri| {
+                match orientation(&points[tri[0]], &points[tri[1]], &points[tri[2]]) {
+                    Orientation::CounterClockwise => Some([tri[0], tri[1], tri[2]]),
+                    Orientation::Clockwise => Some([tri[0], tri[2], tri[1]]),
+                    Orientation::Collinear => None,
+                }
+            })
+            .flatten()
+            .collect();
+
+        if origins.is_empty() {
+            return None;
+        }
+
+        let edges: HashMap<(usize, usize), usize> = (0..origins.len())
+            .map(|e| ((origins[e], origins[next(e)]), e))
+            .collect();
+
+        let twins: Vec<Option<usize>> = (0..origins.len())
+            .map(|e| edges.get(&(origins[next(e)], origins[e])).copied())
+            .collect();
+
+        let mut outgoing = vec![None; points.len()];
+        for e in 0..origins.len() {
+            // on the hull, the edge without twin is where the turn around the point starts
+            if outgoing[origins[e]].is_none() || twins[e].is_none() {
+                outgoing[origins[e]] = Some(e);
+            }
+        }
+
+        let mut triangulation = Self {
+            points: points.to_vec(),
+            origins,
+            twins,
+            outgoing,
+            hull: Vec::new(),
+        };
+        triangulation.hull = triangulation.walk_hull();
+
+        Some(triangulation)
+    }
+
+    pub fn points(&self) -> &[Point] {
+        &self.points
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.origins.len() / 3
+    }
+
+    pub fn half_edge_count(&self) -> usize {
+        self.origins.len()
+    }
+
+    /// Corners of triangle `t`, counter-clockwise
+    pub fn triangle(&self, t: usize) -> [usize; 3] {
+        [
+            self.origins[3 * t],
+            self.origins[3 * t + 1],
+            self.origins[3 * t + 2],
+        ]
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        (0..self.triangle_count()).map(|t| self.triangle(t))
+    }
+
+    /// Point half-edge `e` starts from
+    pub fn origin(&self, e: usize) -> usize {
+        self.origins[e]
+    }
+
+    /// Point half-edge `e` goes to
+    pub fn target(&self, e: usize) -> usize {
+        self.origins[next(e)]
+    }
+
+    /// Following half-edge in the same triangle
+    pub fn next(&self, e: usize) -> usize {
+        next(e)
+    }
+
+    /// Previous half-edge in the same triangle
+    pub fn prev(&self, e: usize) -> usize {
+        prev(e)
+    }
+
+    /// Same edge going the other way, `None` on the hull
+    pub fn twin(&self, e: usize) -> Option<usize> {
+        self.twins[e]
+    }
+
+    /// Triangle half-edge `e` belongs to
+    pub fn triangle_of(&self, e: usize) -> usize {
+        e / 3
+    }
+
+    pub fn segment(&self, e: usize) -> Segment {
+        Segment::new(&self.points[self.origin(e)], &self.points[self.target(e)])
+    }
+
+    /// Half-edges going out of `point`, counter-clockwise around it
+    pub fn edges_around(&self, point: usize) -> Vec<usize> {
+        let mut edges = Vec::new();
+        let Some(start) = self.outgoing[point] else {
+            return edges;
+        };
+
+        let mut e = start;
+        loop {
+            edges.push(e);
+
+            match self.twins[prev(e)] {
+                Some(twin) if twin != start => e = twin,
+                _ => break,
+            }
+        }
+
+        edges
+    }
+
+    /// Triangles having `point` as a corner, counter-clockwise around it
+    pub fn incident_triangles(&self, point: usize) -> Vec<usize> {
+        self.edges_around(point)
+            .into_iter()
+            .map(|e| self.triangle_of(e))
+            .collect()
+    }
+
+    /// Points linked to `point` by an edge, counter-clockwise around it
+    pub fn neighbours(&self, point: usize) -> Vec<usize> {
+        let edges = self.edges_around(point);
+        let mut neighbours: Vec<usize> = edges.iter().map(|&e| self.target(e)).collect();
+
+        // on the hull, the last neighbour only comes in by the last triangle
+        if let Some(&last) = edges.last() {
+            if self.twins[prev(last)].is_none() {
+                neighbours.push(self.origin(prev(last)));
+            }
+        }
+
+        neighbours
+    }
+
+    /// Triangles sharing an edge with triangle `t`
+    pub fn adjacent_triangles(&self, t: usize) -> impl Iterator<Item = usize> + '_ {
+        (3 * t..3 * t + 3).filter_map(|e| self.twins[e].map(|twin| self.triangle_of(twin)))
+    }
+
+    /// Center of the circle going through the corners of triangle `t`
+    pub fn circumcenter(&self, t: usize) -> Point {
+        let [a, b, c] = self.triangle(t);
+
+        // flat triangles are left out, so there is always one
+        circumcenter(&self.points[a], &self.points[b], &self.points[c])
+            .expect("Delaunay triangle is flat")
+    }
+
+    pub fn circumcenters(&self) -> impl Iterator<Item = Point> + '_ {
+        (0..self.triangle_count()).map(|t| self.circumcenter(t))
+    }
+
+    pub fn circumradius(&self, t: usize) -> f64 {
+        distance(&self.circumcenter(t), &self.points[self.origins[3 * t]])
+    }
+
+    /// Voronoi edge crossing half-edge `e`, between the circumcenters of its two triangles.
+    ///
+    /// The edges of the hull have an infinite Voronoi edge, which gives `None`.
+    pub fn voronoi_edge(&self, e: usize) -> Option<Segment> {
+        let twin = self.twins[e]?;
+
+        Some(Segment::new(
+            &self.circumcenter(self.triangle_of(e)),
+            &self.circumcenter(self.triangle_of(twin)),
+        ))
+    }
+
+    /// Half-edges of the convex hull, counter-clockwise
+    pub fn hull_edges(&self) -> &[usize] {
+        &self.hull
+    }
+
+    /// Points of the convex hull, counter-clockwise
+    pub fn hull(&self) -> impl Iterator<Item = usize> + '_ {
+        self.hull.iter().map(|&e| self.origin(e))
+    }
+
+    fn walk_hull(&self) -> Vec<usize> {
+        let Some(start) = (0..self.origins.len()).find(|&e| self.twins[e].is_none()) else {
+            return Vec::new();
+        };
+
+        let mut hull = vec![start];
+        loop {
+            // the hull edge leaving the target of the last one
+            let e = self.outgoing[self.target(*hull.last().unwrap())]
+                .expect("hull point without triangle");
+
+            if e == start || hull.len() > self.origins.len() {
+                break;
+            }
+            hull.push(e);
+        }
+
+        hull
+    }
+}
+
+fn next(e: usize) -> usize {
+    e - e % 3 + (e + 1) % 3
+}
+
+fn prev(e: usize) -> usize {
+    e - e % 3 + (e + 2) % 3
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{geometry::signed_area, random_points, test_util::p};
+
+    #[test]
+    fn square_with_center() {
+        let points = vec![
+            p(0.0, 0.0),
+            p(1.0, 0.0),
+            p(1.0, 1.0),
+            p(0.0, 1.0),
+            p(0.5, 0.5),
+        ];
+        let t = Triangulation::new(&points).unwrap();
+
+        assert_eq!(t.triangle_count(), 4);
+        assert_eq!(t.hull_edges().len(), 4);
+
+        let mut around = t.incident_triangles(4);
+        around.sort_unstable();
+        assert_eq!(around, vec![0, 1, 2, 3]);
+
+        let mut neighbours = t.neighbours(4);
+        neighbours.sort_unstable();
+        assert_eq!(neighbours, vec![0, 1, 2, 3]);
+
+        // a corner only sees its two sides and the center
+        let mut neighbours = t.neighbours(0);
+        neighbours.sort_unstable();
+        assert_eq!(neighbours, vec![1, 3, 4]);
+        assert_eq!(t.incident_triangles(0).len(), 2);
+    }
+
+    #[test]
+    fn half_edges_are_consistent() {
+        let t = Triangulation::new(&random_points(200)).unwrap();
+
+        for e in 0..t.half_edge_count() {
+            assert_eq!(t.next(t.prev(e)), e);
+            assert_eq!(t.next(t.next(t.next(e))), e);
+            assert_eq!(t.target(e), t.origin(t.next(e)));
+
+            if let Some(twin) = t.twin(e) {
+                assert_eq!(t.twin(twin), Some(e));
+                assert_eq!(t.origin(twin), t.target(e));
+                assert_eq!(t.target(twin), t.origin(e));
+            }
+        }
+
+        for tri in 0..t.triangle_count() {
+            let corners = t.triangle(tri).map(|i| t.points()[i].clone());
+            assert!(signed_area(&corners) > 0.0);
+        }
+    }
+
+    #[test]
+    fn delaunay_property() {
+        let points = random_points(200);
+        let t = Triangulation::new(&points).unwrap();
+
+        for tri in 0..t.triangle_count() {
+            let center = t.circumcenter(tri);
+            let radius = t.circumradius(tri);
+
+            for q in &points {
+                let d = ((q.x - center.x).powi(2) + (q.y - center.y).powi(2)).sqrt();
+                assert!(d > radius - 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn every_point_goes_around() {
+        let points = random_points(200);
+        let t = Triangulation::new(&points).unwrap();
+
+        let mut seen = vec![0; t.triangle_count()];
+        for i in 0..points.len() {
+            let triangles = t.incident_triangles(i);
+            assert!(!triangles.is_empty());
+
+            for tri in triangles {
+                assert!(t.triangle(tri).contains(&i));
+                seen[tri] += 1;
+            }
+        }
+
+        // each triangle is met from its three corners
+        assert!(seen.iter().all(|&s| s == 3));
+    }
+
+    #[test]
+    fn hull_is_convex() {
+        let points = random_points(200);
+        let t = Triangulation::new(&points).unwrap();
+
+        let hull: Vec<usize> = t.hull().collect();
+        assert!(hull.len() >= 3);
+
+        for i in 0..hull.len() {
+            let (a, b) = (&points[hull[i]], &points[hull[(i + 1) % hull.len()]]);
+
+            for q in &points {
+                assert_ne!(orientation(a, b, q), Orientation::Clockwise);
+            }
+        }
+
+        let triangles: f64 = t
+            .triangles()
+            .map(|tri| signed_area(&tri.map(|i| points[i].clone())))
+            .sum();
+        let hull_points: Vec<Point> = hull.iter().map(|&i| points[i].clone()).collect();
+        assert!((triangles - signed_area(&hull_points)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn voronoi_edges_are_bisectors() {
+        let t = Triangulation::new(&random_points(100)).unwrap();
+
+        for e in 0..t.half_edge_count() {
+            match t.voronoi_edge(e) {
+                Some(edge) => {
+                    let (a, b) = (&t.points()[t.origin(e)], &t.points()[t.target(e)]);
+
+                    for q in [&edge.start, &edge.end] {
+                        let da = (q.x - a.x).powi(2) + (q.y - a.y).powi(2);
+                        let db = (q.x - b.x).powi(2) + (q.y - b.y).powi(2);
+                        assert!((da - db).abs() < 1e-9);
+                    }
+                }
+                None => assert!(t.hull_edges().contains(&e)),
+            }
+        }
+    }
+
+    #[test]
+    fn aligned_points() {
+        let points = vec![p(0.0, 0.0), p(1.0, 0.0), p(2.0, 0.0)];
+
+        assert!(Triangulation::new(&points).is_none());
+    }
+}
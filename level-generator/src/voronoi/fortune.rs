@@ -0,0 +1,753 @@
+//! Fortune's sweep-line algorithm for Voronoi diagrams.
+//!
+//! The sweep line moves upwards (increasing y). Everything below the beach line is final, so every
+//! breakpoint between two arcs traces a Voronoi edge as the sweep goes up.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+use voronator::{
+    delaunator::{Coord, Point},
+    polygon::Polygon,
+};
+
+use super::Cells;
+use crate::geometry::{circumcenter, orientation, Orientation};
+
+/// Distance under which two vertices are considered to be the same one
+const VERTEX_EPSILON: f64 = 1e-9;
+
+/// Half of a Voronoi edge, as seen from one of the two sites it separates.
+///
+/// Walking from `origin` to `target` keeps `site` on the left, so the half-edges of a cell turn
+/// counter-clockwise around it. A `None` end means the edge goes to infinity on that side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HalfEdge {
+    pub site: usize,
+    pub twin: usize,
+    pub origin: Option<usize>,
+    pub target: Option<usize>,
+    /// Following half-edge around the same cell, when both are connected by a finite vertex
+    pub next: Option<usize>,
+}
+
+pub struct Diagram {
+    sites: Vec<Point>,
+    vertices: Vec<Point>,
+    half_edges: Vec<HalfEdge>,
+    cell_edges: Vec<Vec<usize>>,
+}
+
+impl Diagram {
+    pub fn sites(&self) -> &[Point] {
+        &self.sites
+    }
+
+    pub fn vertices(&self) -> &[Point] {
+        &self.vertices
+    }
+
+    pub fn half_edges(&self) -> &[HalfEdge] {
+        &self.half_edges
+    }
+
+    /// Half-edges bounding the cell of `site`, in counter-clockwise order
+    pub fn cell_half_edges(&self, site: usize) -> &[usize] {
+        &self.cell_edges[site]
+    }
+
+    /// Sites sharing an edge with `site`, in counter-clockwise order
+    pub fn neighbours(&self, site: usize) -> impl Iterator<Item = usize> + '_ {
+        self.cell_edges[site]
+            .iter()
+            .map(|&e| self.half_edges[self.half_edges[e].twin].site)
+    }
+
+    /// Builds the cells of the diagram, clipped to `bounds`.
+    ///
+    /// Each cell is the bounding polygon cut by the bisector with each of its neighbours, so the
+    /// bounding polygon is expected to be convex and counter-clockwise, like the one given to
+    /// `VoronoiDiagram::with_bounding_polygon`. There is one cell per input point, in the same
+    /// order; duplicate points get an empty cell.
+    pub fn cells(&self, bounds: &Polygon<Point>) -> Cells {
+        Cells(
+            (0..self.sites.len())
+                .map(|i| {
+                    if self.cell_edges[i].is_empty() && self.is_duplicate(i) {
+                        return Polygon::new();
+                    }
+
+                    let site = &self.sites[i];
+                    let cell = self
+                        .neighbours(i)
+                        .fold(bounds.points().to_vec(), |cell, j| {
+                            clip_half_plane(&cell, site, &self.sites[j])
+                        });
+
+                    Polygon::from_points(cell)
+                })
+                .collect(),
+        )
+    }
+
+    fn is_duplicate(&self, site: usize) -> bool {
+        self.sites[..site].contains(&self.sites[site])
+    }
+}
+
+/// Computes the Voronoi diagram of `points` with Fortune's algorithm.
+///
+/// Degenerate configurations (cocircular or collinear sites, sites sharing the same y) are
+/// supported: vertices closer than `VERTEX_EPSILON` are merged, so no zero-length edge is output.
+pub fn compute(points: &[Point]) -> Diagram {
+    let mut sweep = Sweep {
+        sites: points,
+        beach: Vec::new(),
+        breakpoints: Vec::new(),
+        edges: Vec::new(),
+        vertices: Vec::new(),
+        queue: BinaryHeap::new(),
+        valid_circles: Vec::new(),
+        next_arc: 0,
+    };
+
+    let mut seen = HashSet::new();
+
+    for (i, p) in points.iter().enumerate() {
+        if !seen.insert((p.x.to_bits(), p.y.to_bits())) {
+            // duplicate point, it will not get any cell
+            continue;
+        }
+
+        sweep.queue.push(Event {
+            y: p.y,
+            x: p.x,
+            kind: EventKind::Site(i),
+        });
+    }
+
+    while let Some(event) = sweep.queue.pop() {
+        match event.kind {
+            EventKind::Site(site) => sweep.site_event(site),
+            EventKind::Circle { arc, id, center } => {
+                if sweep.valid_circles[id] {
+                    sweep.circle_event(arc, center, event.y)
+                }
+            }
+        }
+    }
+
+    let (vertices, edges) = merge_vertices(sweep.vertices, sweep.edges);
+    build_diagram(points, vertices, edges)
+}
+
+/// A Voronoi edge between `left` and `right`.
+///
+/// The edge is oriented along the bisector so that `left` is on its left side, going from `start`
+/// to `end`.
+struct Edge {
+    left: usize,
+    right: usize,
+    start: Option<usize>,
+    end: Option<usize>,
+}
+
+struct Arc {
+    id: usize,
+    site: usize,
+    circle: Option<usize>,
+}
+
+/// Breakpoint between two consecutive arcs, tracing `edge` either forwards (towards its end) or
+/// backwards (towards its start)
+struct Breakpoint {
+    edge: usize,
+    forward: bool,
+}
+
+enum EventKind {
+    Site(usize),
+    Circle {
+        arc: usize,
+        id: usize,
+        center: Point,
+    },
+}
+
+struct Event {
+    y: f64,
+    x: f64,
+    kind: EventKind,
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, we want the lowest event first
+        other
+            .y
+            .total_cmp(&self.y)
+            .then_with(|| other.x.total_cmp(&self.x))
+    }
+}
+
+struct Sweep<'a> {
+    sites: &'a [Point],
+    beach: Vec<Arc>,
+    /// `breakpoints[i]` is between `beach[i]` and `beach[i + 1]`
+    breakpoints: Vec<Breakpoint>,
+    edges: Vec<Edge>,
+    vertices: Vec<Point>,
+    queue: BinaryHeap<Event>,
+    valid_circles: Vec<bool>,
+    next_arc: usize,
+}
+
+impl Sweep<'_> {
+    fn new_arc(&mut self, site: usize) -> Arc {
+        self.next_arc += 1;
+        Arc {
+            id: self.next_arc,
+            site,
+            circle: None,
+        }
+    }
+
+    fn site_event(&mut self, site: usize) {
+        let sites = self.sites;
+        let s = &sites[site];
+
+        if self.beach.is_empty() {
+            let arc = self.new_arc(site);
+            self.beach.push(arc);
+            return;
+        }
+
+        let i = self.locate_arc(s);
+        let above = self.beach[i].site;
+
+        if sites[above].y == s.y {
+            // only happens while all the arcs are on the sweep line: the beach line is a set of
+            // vertical rays and the new one goes on the right of the last one
+            let last = self.beach.len() - 1;
+            let edge = self.add_edge(self.beach[last].site, site, None);
+            let arc = self.new_arc(site);
+
+            self.beach.push(arc);
+            self.breakpoints.push(Breakpoint {
+                edge,
+                forward: true,
+            });
+            return;
+        }
+
+        self.invalidate_circle(i);
+
+        let edge = self.add_edge(above, site, None);
+        let middle = self.new_arc(site);
+        let right = self.new_arc(above);
+
+        self.beach.insert(i + 1, middle);
+        self.beach.insert(i + 2, right);
+        self.breakpoints.insert(
+            i,
+            Breakpoint {
+                edge,
+                forward: false,
+            },
+        );
+        self.breakpoints.insert(
+            i,
+            Breakpoint {
+                edge,
+                forward: true,
+            },
+        );
+
+        self.check_circle(i, s.y);
+        self.check_circle(i + 2, s.y);
+    }
+
+    fn circle_event(&mut self, arc: usize, center: Point, sweep_y: f64) {
+        let i = self
+            .beach
+            .iter()
+            .position(|a| a.id == arc)
+            .expect("circle event for an arc not on the beach line");
+
+        // a valid circle event never concerns the first or last arc
+        self.invalidate_circle(i - 1);
+        self.invalidate_circle(i + 1);
+
+        let vertex = self.vertices.len();
+        self.vertices.push(center);
+
+        self.finish_breakpoint(i - 1, vertex);
+        self.finish_breakpoint(i, vertex);
+
+        self.beach.remove(i);
+        self.breakpoints.remove(i);
+
+        let edge = self.add_edge(self.beach[i - 1].site, self.beach[i].site, Some(vertex));
+        self.breakpoints[i - 1] = Breakpoint {
+            edge,
+            forward: true,
+        };
+
+        self.check_circle(i - 1, sweep_y);
+        self.check_circle(i, sweep_y);
+    }
+
+    fn add_edge(&mut self, left: usize, right: usize, start: Option<usize>) -> usize {
+        self.edges.push(Edge {
+            left,
+            right,
+            start,
+            end: None,
+        });
+
+        self.edges.len() - 1
+    }
+
+    fn finish_breakpoint(&mut self, i: usize, vertex: usize) {
+        let bp = &self.breakpoints[i];
+        let edge = &mut self.edges[bp.edge];
+
+        if bp.forward {
+            edge.end = Some(vertex);
+        } else {
+            edge.start = Some(vertex);
+        }
+    }
+
+    /// Index of the arc right above `site`
+    fn locate_arc(&self, site: &Point) -> usize {
+        // this is linear in the size of the beach line, which is fine for the amount of rooms we
+        // generate
+        for i in 0..self.breakpoints.len() {
+            let left = &self.sites[self.beach[i].site];
+            let right = &self.sites[self.beach[i + 1].site];
+
+            if site.x < breakpoint(left, right, site.y) {
+                return i;
+            }
+        }
+
+        self.beach.len() - 1
+    }
+
+    fn invalidate_circle(&mut self, i: usize) {
+        if let Some(id) = self.beach[i].circle.take() {
+            self.valid_circles[id] = false;
+        }
+    }
+
+    /// Adds a circle event for the arc at index `i`, if its breakpoints converge
+    fn check_circle(&mut self, i: usize, sweep_y: f64) {
+        if i == 0 || i + 1 >= self.beach.len() {
+            return;
+        }
+
+        let a = &self.sites[self.beach[i - 1].site];
+        let b = &self.sites[self.beach[i].site];
+        let c = &self.sites[self.beach[i + 1].site];
+
+        if self.beach[i - 1].site == self.beach[i + 1].site {
+            return;
+        }
+
+        // the breakpoints only converge if the sites turn counter-clockwise
+        if orientation(a, b, c) != Orientation::CounterClockwise {
+            return;
+        }
+
+        let center = match circumcenter(a, b, c) {
+            Some(center) => center,
+            None => return,
+        };
+
+        let radius = f64::sqrt((a.x - center.x).powi(2) + (a.y - center.y).powi(2));
+        let y = center.y + radius;
+
+        if y < sweep_y - VERTEX_EPSILON {
+            return;
+        }
+
+        let id = self.valid_circles.len();
+        self.valid_circles.push(true);
+        self.beach[i].circle = Some(id);
+
+        self.queue.push(Event {
+            y,
+            x: center.x,
+            kind: EventKind::Circle {
+                arc: self.beach[i].id,
+                id,
+                center,
+            },
+        });
+    }
+}
+
+/// x coordinate of the breakpoint between the arc of `left` and the arc of `right` when the sweep
+/// line is at `sweep_y`
+fn breakpoint(left: &Point, right: &Point, sweep_y: f64) -> f64 {
+    if left.y == sweep_y && right.y == sweep_y {
+        return (left.x + right.x) / 2.0;
+    } else if left.y == sweep_y {
+        return left.x;
+    } else if right.y == sweep_y {
+        return right.x;
+    } else if left.y == right.y {
+        return (left.x + right.x) / 2.0;
+    }
+
+    // both parabolas are y = ((x - fx)² + fy² - l²) / (2 (fy - l)), the difference between them
+    // is a x² + b x + c, and the breakpoint is the root where it goes from positive to negative
+    let dl = 2.0 * (left.y - sweep_y);
+    let dr = 2.0 * (right.y - sweep_y);
+
+    let a = 1.0 / dl - 1.0 / dr;
+    let b = -2.0 * (left.x / dl - right.x / dr);
+    let c = (left.x.powi(2) + left.y.powi(2) - sweep_y.powi(2)) / dl
+        - (right.x.powi(2) + right.y.powi(2) - sweep_y.powi(2)) / dr;
+
+    let sqrt_disc = f64::sqrt(f64::max(b * b - 4.0 * a * c, 0.0));
+
+    // pick the form of the root that does not suffer from cancellation
+    if b <= 0.0 {
+        2.0 * c / (-b + sqrt_disc)
+    } else {
+        (-b - sqrt_disc) / (2.0 * a)
+    }
+}
+
+/// Merges vertices linked by a zero-length edge (which appear with cocircular sites), and drops
+/// those edges
+fn merge_vertices(vertices: Vec<Point>, edges: Vec<Edge>) -> (Vec<Point>, Vec<Edge>) {
+    let mut parent: Vec<usize> = (0..vertices.len()).collect();
+
+    fn root(parent: &mut [usize], mut v: usize) -> usize {
+        while parent[v] != v {
+            parent[v] = parent[parent[v]];
+            v = parent[v];
+        }
+        v
+    }
+
+    for e in &edges {
+        if let (Some(start), Some(end)) = (e.start, e.end) {
+            let (a, b) = (&vertices[start], &vertices[end]);
+
+            if (a.x - b.x).abs() <= VERTEX_EPSILON && (a.y - b.y).abs() <= VERTEX_EPSILON {
+                let (ra, rb) = (root(&mut parent, start), root(&mut parent, end));
+                parent[rb] = ra;
+            }
+        }
+    }
+
+    let mut remap = vec![usize::MAX; vertices.len()];
+    let mut merged = Vec::new();
+
+    for v in 0..vertices.len() {
+        let r = root(&mut parent, v);
+
+        if remap[r] == usize::MAX {
+            remap[r] = merged.len();
+            merged.push(vertices[r].clone());
+        }
+
+        remap[v] = remap[r];
+    }
+
+    let edges = edges
+        .into_iter()
+        .map(|e| Edge {
+            start: e.start.map(|v| remap[v]),
+            end: e.end.map(|v| remap[v]),
+            ..e
+        })
+        .filter(|e| e.start.is_none() || e.start != e.end)
+        .collect();
+
+    (merged, edges)
+}
+
+fn build_diagram(points: &[Point], vertices: Vec<Point>, edges: Vec<Edge>) -> Diagram {
+    let mut half_edges = Vec::with_capacity(2 * edges.len());
+    let mut cell_edges = vec![Vec::new(); points.len()];
+
+    for (i, e) in edges.iter().enumerate() {
+        half_edges.push(HalfEdge {
+            site: e.left,
+            twin: 2 * i + 1,
+            origin: e.start,
+            target: e.end,
+            next: None,
+        });
+        half_edges.push(HalfEdge {
+            site: e.right,
+            twin: 2 * i,
+            origin: e.end,
+            target: e.start,
+            next: None,
+        });
+
+        cell_edges[e.left].push(2 * i);
+        cell_edges[e.right].push(2 * i + 1);
+    }
+
+    for (site, cell) in cell_edges.iter_mut().enumerate() {
+        // each half-edge is perpendicular to the direction of the neighbour, so sorting by that
+        // direction gives the counter-clockwise order
+        let s = &points[site];
+        let angle = |e: &usize| {
+            let other = &points[half_edges[half_edges[*e].twin].site];
+            f64::atan2(other.y - s.y, other.x - s.x)
+        };
+
+        cell.sort_by(|a, b| angle(a).total_cmp(&angle(b)));
+
+        for k in 0..cell.len() {
+            let (current, following) = (cell[k], cell[(k + 1) % cell.len()]);
+
+            if current != following
+                && half_edges[current].target.is_some()
+                && half_edges[current].target == half_edges[following].origin
+            {
+                half_edges[current].next = Some(following);
+            }
+        }
+    }
+
+    Diagram {
+        sites: points.to_vec(),
+        vertices,
+        half_edges,
+        cell_edges,
+    }
+}
+
+/// Keeps the part of `polygon` closer to `site` than to `other`
+fn clip_half_plane(polygon: &[Point], site: &Point, other: &Point) -> Vec<Point> {
+    clip_power_half_plane(polygon, (site, 0.0), (other, 0.0))
+}
+
+/// Keeps the part of `polygon` where the power distance to `site` is lower than to `other`, each
+/// of them coming with its weight
+pub(super) fn clip_power_half_plane(
+    polygon: &[Point],
+    (site, site_weight): (&Point, f64),
+    (other, other_weight): (&Point, f64),
+) -> Vec<Point> {
+    // points closer to site verify n.p <= k
+    let n = Point {
+        x: other.x - site.x,
+        y: other.y - site.y,
+    };
+    let k = (other.magnitude2() - site.magnitude2() - other_weight + site_weight) / 2.0;
+
+    let distance = |p: &Point| n.x * p.x + n.y * p.y - k;
+
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+
+    for i in 0..polygon.len() {
+        let current = &polygon[i];
+        let next = &polygon[(i + 1) % polygon.len()];
+
+        let (dc, dn) = (distance(current), distance(next));
+
+        if dc <= 0.0 {
+            clipped.push(current.clone());
+        }
+
+        if (dc < 0.0 && dn > 0.0) || (dc > 0.0 && dn < 0.0) {
+            let t = dc / (dc - dn);
+            clipped.push(Point {
+                x: current.x + t * (next.x - current.x),
+                y: current.y + t * (next.y - current.y),
+            });
+        }
+    }
+
+    clipped
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashSet;
+
+    use voronator::{delaunator::triangulate, VoronoiDiagram};
+
+    use super::*;
+
+    use crate::{random_points, test_util::square};
+
+    fn area(points: &[Point]) -> f64 {
+        (0..points.len())
+            .map(|i| {
+                let (a, b) = (&points[i], &points[(i + 1) % points.len()]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum::<f64>()
+            / 2.0
+    }
+
+    fn delaunay_edges(points: &[Point]) -> HashSet<(usize, usize)> {
+        let t = triangulate(points).unwrap();
+
+        t.triangles
+            .chunks(3)
+            .flat_map(|tri| [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])])
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect()
+    }
+
+    fn voronoi_edges(diagram: &Diagram) -> HashSet<(usize, usize)> {
+        (0..diagram.sites().len())
+            .flat_map(|i| diagram.neighbours(i).map(move |j| (i.min(j), i.max(j))))
+            .collect()
+    }
+
+    #[test]
+    fn same_cells_as_voronator() {
+        for n in [5, 20, 100, 500] {
+            let points = random_points(n);
+            let bounds = square(2.0);
+
+            let expected = VoronoiDiagram::with_bounding_polygon(points.clone(), &bounds).unwrap();
+            let cells = compute(&points).cells(&bounds);
+
+            assert_eq!(cells.iter_cells().count(), expected.cells().len());
+
+            for (cell, other) in cells.iter_cells().zip(expected.cells()) {
+                assert!((area(cell.points()) - area(other.points())).abs() < 1e-9);
+
+                for p in other.points() {
+                    assert!(cell
+                        .points()
+                        .iter()
+                        .any(|q| (p.x - q.x).abs() < 1e-9 && (p.y - q.y).abs() < 1e-9));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neighbours_are_dual_of_delaunay() {
+        for n in [5, 20, 100, 500] {
+            let points = random_points(n);
+
+            assert_eq!(voronoi_edges(&compute(&points)), delaunay_edges(&points));
+        }
+    }
+
+    #[test]
+    fn half_edges_are_consistent() {
+        let diagram = compute(&random_points(100));
+
+        for (i, e) in diagram.half_edges().iter().enumerate() {
+            let twin = &diagram.half_edges()[e.twin];
+            assert_eq!(twin.twin, i);
+            assert_eq!(twin.origin, e.target);
+            assert_eq!(twin.target, e.origin);
+
+            // finite half-edges turn counter-clockwise around their site
+            if let (Some(o), Some(t)) = (e.origin, e.target) {
+                let s = &diagram.sites()[e.site];
+                let (o, t) = (&diagram.vertices()[o], &diagram.vertices()[t]);
+                assert!((o.x - s.x) * (t.y - s.y) - (o.y - s.y) * (t.x - s.x) > 0.0);
+            }
+
+            if let Some(next) = e.next {
+                assert_eq!(diagram.half_edges()[next].site, e.site);
+            }
+        }
+
+        // every vertex is equidistant to the sites around it
+        for e in diagram.half_edges() {
+            if let Some(o) = e.origin {
+                let v = &diagram.vertices()[o];
+                let a = &diagram.sites()[e.site];
+                let b = &diagram.sites()[diagram.half_edges()[e.twin].site];
+                let da = (v.x - a.x).powi(2) + (v.y - a.y).powi(2);
+                let db = (v.x - b.x).powi(2) + (v.y - b.y).powi(2);
+                assert!((da - db).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_has_no_degenerate_edges() {
+        // every vertex of a grid is shared by four cocircular sites
+        let points: Vec<_> = (0..16)
+            .map(|i| Point {
+                x: (i % 4) as f64 - 1.5,
+                y: (i / 4) as f64 - 1.5,
+            })
+            .collect();
+
+        let diagram = compute(&points);
+        assert_eq!(diagram.vertices().len(), 9);
+
+        let cells = diagram.cells(&square(2.0));
+        for cell in cells.iter_cells() {
+            assert!((area(cell.points()) - 1.0).abs() < 1e-9);
+        }
+
+        // corner cells only have 2 neighbours, no diagonal
+        assert_eq!(diagram.neighbours(0).count(), 2);
+        assert_eq!(diagram.neighbours(5).count(), 4);
+    }
+
+    #[test]
+    fn collinear_sites() {
+        let horizontal: Vec<_> = (0..4)
+            .map(|i| Point {
+                x: i as f64 - 1.5,
+                y: 0.0,
+            })
+            .collect();
+        let vertical: Vec<_> = horizontal
+            .iter()
+            .map(|p| Point { x: p.y, y: p.x })
+            .collect();
+
+        for points in [horizontal, vertical] {
+            let diagram = compute(&points);
+
+            assert!(diagram.vertices().is_empty());
+            assert_eq!(
+                voronoi_edges(&diagram),
+                HashSet::from([(0, 1), (1, 2), (2, 3)])
+            );
+
+            for cell in diagram.cells(&square(2.0)).iter_cells() {
+                assert!((area(cell.points()).abs() - 4.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn duplicate_sites_have_empty_cells() {
+        let mut points = random_points(10);
+        points.push(points[3].clone());
+
+        let cells = compute(&points).cells(&square(2.0));
+        assert!(cells.iter_cells().last().unwrap().points().is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+//! Lloyd relaxation: moving every site to the centroid of its cell, over and over.
+//!
+//! Each iteration evens out the cells a bit more, so a few iterations are enough to get rid of
+//! slivers while keeping some randomness, and many of them converge to a regular, honeycomb-like
+//! layout (a centroidal Voronoi diagram).
+
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use super::{alpha::AlphaShape, fortune};
+use crate::geometry::{
+    centroid,
+    clip::{clip, is_convex},
+    containment::Location,
+    counter_clockwise, signed_area,
+};
+
+/// Runs `iterations` steps of Lloyd relaxation of `points` inside `bounds`.
+///
+/// `bounds` can be concave, in which case cells are cut to it before taking their centroid.
+pub fn relax(points: &[Point], bounds: &Polygon<Point>, iterations: usize) -> Vec<Point> {
+    relax_constrained(points, bounds, iterations, |_, moved| moved)
+}
+
+/// Like [`relax`], but every move goes through `constrain` first.
+///
+/// `constrain` gets the current position of a site and the centroid of its cell, and returns
+/// where the site actually goes.
+pub fn relax_constrained(
+    points: &[Point],
+    bounds: &Polygon<Point>,
+    iterations: usize,
+    mut constrain: impl FnMut(&Point, Point) -> Point,
+) -> Vec<Point> {
+    let bounds = counter_clockwise(bounds.points());
+    let convex = is_convex(&bounds);
+    let frame = if convex {
+        Polygon::from_points(bounds.clone())
+    } else {
+        bounding_box(&bounds)
+    };
+    let bounds = Polygon::from_points(bounds);
+
+    let mut points = points.to_vec();
+
+    for _ in 0..iterations {
+        let cells = fortune::compute(&points).cells(&frame);
+
+        points = points
+            .iter()
+            .zip(cells.0.iter())
+            .map(|(site, cell)| {
+                let center = if convex {
+                    centroid(cell.points())
+                } else {
+                    pieces_centroid(&clip(cell, &bounds))
+                };
+
+                match center {
+                    Some(center) => constrain(site, center),
+                    // duplicate sites and sites out of the bounds have nowhere to go
+                    None => site.clone(),
+                }
+            })
+            .collect();
+    }
+
+    points
+}
+
+/// Constraint for [`relax_constrained`] keeping the sites inside `shape`: a site whose centroid
+/// is out of the shape doesn't move.
+pub fn inside(shape: &AlphaShape) -> impl Fn(&Point, Point) -> Point + '_ {
+    move |site, moved| match shape.locate(&moved) {
+        Location::Outside => site.clone(),
+        _ => moved,
+    }
+}
+
+/// Centroid of several polygons taken together
+fn pieces_centroid(pieces: &[Polygon<Point>]) -> Option<Point> {
+    let (area, x, y) = pieces.iter().fold((0.0, 0.0, 0.0), |(area, x, y), piece| {
+        match centroid(piece.points()) {
+            Some(center) => {
+                let a = signed_area(piece.points()).abs();
+                (area + a, x + a * center.x, y + a * center.y)
+            }
+            None => (area, x, y),
+        }
+    });
+
+    (area > 0.0).then(|| Point {
+        x: x / area,
+        y: y / area,
+    })
+}
+
+/// Counter-clockwise rectangle slightly larger than `points`
+fn bounding_box(points: &[Point]) -> Polygon<Point> {
+    let (min_x, max_x) = points
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| {
+            (min.min(p.x), max.max(p.x))
+        });
+    let (min_y, max_y) = points
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| {
+            (min.min(p.y), max.max(p.y))
+        });
+
+    let margin = 0.1 * (max_x - min_x).max(max_y - min_y);
+
+    Polygon::from_points(vec![
+        Point {
+            x: min_x - margin,
+            y: min_y - margin,
+        },
+        Point {
+            x: max_x + margin,
+            y: min_y - margin,
+        },
+        Point {
+            x: max_x + margin,
+            y: max_y + margin,
+        },
+        Point {
+            x: min_x - margin,
+            y: max_y + margin,
+        },
+    ])
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        geometry::containment::locate_point,
+        random_points,
+        test_util::{p, square},
+        voronoi::{alpha::connected_alpha, alpha_shape},
+    };
+
+    /// Variance of the cell areas, relative to the mean area
+    fn area_spread(points: &[Point], bounds: &Polygon<Point>) -> f64 {
+        let areas: Vec<f64> = fortune::compute(points)
+            .cells(bounds)
+            .iter_cells()
+            .map(|c| signed_area(c.points()))
+            .collect();
+
+        let mean = areas.iter().sum::<f64>() / areas.len() as f64;
+        areas.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / areas.len() as f64 / mean.powi(2)
+    }
+
+    #[test]
+    fn no_iteration_keeps_points() {
+        let points = random_points(20);
+
+        assert_eq!(relax(&points, &square(1.0), 0), points);
+    }
+
+    #[test]
+    fn cells_get_more_even() {
+        let bounds = square(1.0);
+        let points = random_points(50);
+
+        let mut spread = area_spread(&points, &bounds);
+        for iterations in [1, 5, 20] {
+            let relaxed = relax(&points, &bounds, iterations);
+            let relaxed_spread = area_spread(&relaxed, &bounds);
+
+            assert!(relaxed_spread < spread);
+            spread = relaxed_spread;
+
+            for site in &relaxed {
+                assert_eq!(locate_point(site, &bounds), Location::Inside);
+            }
+        }
+
+        // far more regular than the raw noise
+        assert!(spread < area_spread(&points, &bounds) / 10.0);
+    }
+
+    #[test]
+    fn relaxation_is_deterministic() {
+        let points = random_points(30);
+
+        assert_eq!(
+            relax(&points, &square(1.0), 5),
+            relax(&points, &square(1.0), 5)
+        );
+    }
+
+    #[test]
+    fn concave_bounds() {
+        // L-shaped level
+        let bounds = Polygon::from_points(vec![
+            p(-1.0, -1.0),
+            p(1.0, -1.0),
+            p(1.0, 0.0),
+            p(0.0, 0.0),
+            p(0.0, 1.0),
+            p(-1.0, 1.0),
+        ]);
+        let points: Vec<_> = random_points(40)
+            .into_iter()
+            .filter(|q| locate_point(q, &bounds) == Location::Inside)
+            .collect();
+
+        for site in relax(&points, &bounds, 10) {
+            assert_ne!(locate_point(&site, &bounds), Location::Outside);
+        }
+    }
+
+    #[test]
+    fn constrained_to_alpha_shape() {
+        let points = random_points(40);
+        let shape = alpha_shape(&points, connected_alpha(&points));
+
+        let relaxed = relax_constrained(&points, &square(2.0), 10, inside(&shape));
+
+        // without the constraint, the sites spread over the whole square
+        assert!(relax(&points, &square(2.0), 10)
+            .iter()
+            .any(|site| shape.locate(site) == Location::Outside));
+
+        for site in &relaxed {
+            assert_ne!(shape.locate(site), Location::Outside);
+        }
+        assert_ne!(relaxed, points);
+    }
+
+    #[test]
+    fn duplicate_sites_split() {
+        let mut points = random_points(10);
+        points.push(points[3].clone());
+
+        // the duplicate has no cell, so it waits for the first one to move away
+        let relaxed = relax(&points, &square(1.0), 1);
+        assert_eq!(relaxed.len(), points.len());
+        assert_eq!(relaxed[10], points[3]);
+        assert_ne!(relaxed[3], points[3]);
+
+        let relaxed = relax(&points, &square(1.0), 3);
+        assert_ne!(relaxed[10], relaxed[3]);
+    }
+}
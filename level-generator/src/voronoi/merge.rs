@@ -0,0 +1,222 @@
+//! Merging adjacent cells into bigger rooms.
+//!
+//! Voronoi cells are all convex, so a level made of them only has convex rooms. Merging a few
+//! neighbouring cells removes the walls between them, and gives L-shaped, winding or irregular
+//! rooms.
+
+use rand::{rngs::SmallRng, seq::SliceRandom, SeedableRng};
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use super::Cells;
+use crate::{
+    geometry::{counter_clockwise, fill::positive_fill, signed_area},
+    graph::Wall,
+};
+
+/// Rooms made of merged cells
+pub struct Merged {
+    pub cells: Cells,
+    /// Index in `cells` of the room each original cell ended up in
+    pub room_of: Vec<usize>,
+}
+
+/// Merges the adjacent cells sharing the same label, `labels[i]` being the label of cell `i`.
+///
+/// Labels can come from a rule, a gene or anything else: cells with the same label that don't
+/// touch each other make separate rooms. A group whose union would have a hole, around a room
+/// of another label, is not merged and keeps its cells as they are, since rooms can't have holes.
+///
+/// Rooms come in the order of their first cell.
+pub fn merge(cells: &Cells, labels: &[usize]) -> Merged {
+    assert_eq!(cells.len(), labels.len());
+
+    let graph = cells.room_graph();
+    let groups = graph.connected_components_through(|w| labels[w.rooms.0] == labels[w.rooms.1]);
+
+    merge_groups(cells, groups)
+}
+
+/// Merges the cells on both sides of the walls accepted by `join`
+pub fn merge_by(cells: &Cells, join: impl Fn(&Wall) -> bool) -> Merged {
+    let groups = cells.room_graph().connected_components_through(join);
+
+    merge_groups(cells, groups)
+}
+
+/// Randomly merges neighbouring cells into rooms of about `target` area.
+///
+/// Starting from the cells in random order, each room grows by taking random free neighbours
+/// until it reaches the target, so cells bigger than the target stay alone.
+pub fn merge_random(cells: &Cells, seed: u64, target: f64) -> Merged {
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let graph = cells.room_graph();
+    let areas: Vec<f64> = cells
+        .iter_cells()
+        .map(|c| signed_area(c.points()).abs())
+        .collect();
+
+    let mut order: Vec<usize> = (0..cells.len()).collect();
+    order.shuffle(&mut rng);
+
+    let mut labels = vec![usize::MAX; cells.len()];
+
+    for start in order {
+        if labels[start] != usize::MAX {
+            continue;
+        }
+
+        labels[start] = start;
+        let mut group = vec![start];
+        let mut area = areas[start];
+
+        while area < target {
+            let mut free: Vec<usize> = group
+                .iter()
+                .flat_map(|&room| graph.neighbours(room))
+                .filter(|&n| labels[n] == usize::MAX)
+                .collect();
+            free.sort_unstable();
+            free.dedup();
+
+            let Some(&next) = free.choose(&mut rng) else {
+                break;
+            };
+
+            labels[next] = start;
+            group.push(next);
+            area += areas[next];
+        }
+    }
+
+    merge(cells, &labels)
+}
+
+/// Turns each group of connected cells into one room
+fn merge_groups(cells: &Cells, groups: Vec<Vec<usize>>) -> Merged {
+    let polygons: Vec<&Polygon<Point>> = cells.iter_cells().collect();
+    let mut rooms = Vec::new();
+    let mut room_of = vec![0; polygons.len()];
+
+    for group in groups {
+        if group.len() > 1 {
+            let rings: Vec<Vec<Point>> = group
+                .iter()
+                .map(|&i| counter_clockwise(polygons[i].points()))
+                .collect();
+
+            let mut union = positive_fill(&rings);
+
+            if union.len() == 1 {
+                group.iter().for_each(|&i| room_of[i] = rooms.len());
+                rooms.push(Polygon::from_points(union.swap_remove(0)));
+                continue;
+            }
+        }
+
+        // single cells, and groups around a hole
+        for i in group {
+            room_of[i] = rooms.len();
+            rooms.push(Polygon::from_points(polygons[i].points().to_vec()));
+        }
+    }
+
+    Merged {
+        cells: Cells(rooms),
+        room_of,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        random_points,
+        test_util::{p, square},
+        voronoi::fortune,
+    };
+
+    /// Cells of a 3 x 3 grid of unit squares, row by row
+    fn grid() -> Cells {
+        let sites: Vec<Point> = (0..9)
+            .map(|i| p((i % 3) as f64 - 1.0, (i / 3) as f64 - 1.0))
+            .collect();
+
+        fortune::compute(&sites).cells(&square(1.5))
+    }
+
+    fn areas(cells: &Cells) -> Vec<f64> {
+        cells
+            .iter_cells()
+            .map(|c| signed_area(c.points()))
+            .collect()
+    }
+
+    #[test]
+    fn l_shaped_room() {
+        let merged = merge(&grid(), &[0, 0, 1, 0, 2, 3, 4, 5, 6]);
+
+        assert_eq!(merged.cells.len(), 7);
+        assert_eq!(merged.room_of, vec![0, 0, 1, 0, 2, 3, 4, 5, 6]);
+
+        let room = merged.cells.iter_cells().next().unwrap();
+        assert_eq!(room.points().len(), 6);
+        assert!((signed_area(room.points()) - 3.0).abs() < 1e-9);
+
+        // the shared walls are gone, the others are still there
+        let graph = merged.cells.room_graph();
+        assert_eq!(graph.neighbours(0), vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn rooms_apart_stay_apart() {
+        let merged = merge(&grid(), &[0, 1, 0, 2, 3, 4, 5, 6, 7]);
+
+        assert_eq!(merged.cells.len(), 9);
+        assert_ne!(merged.room_of[0], merged.room_of[2]);
+    }
+
+    #[test]
+    fn no_room_around_another() {
+        let merged = merge(&grid(), &[0, 0, 0, 0, 1, 0, 0, 0, 0]);
+
+        assert_eq!(merged.cells.len(), 9);
+        assert_eq!(areas(&merged.cells), areas(&grid()));
+    }
+
+    #[test]
+    fn merge_rows() {
+        // walls between rooms next to each other in the same row
+        let merged = merge_by(&grid(), |w| w.rooms.1 - w.rooms.0 == 1);
+
+        assert_eq!(merged.cells.len(), 3);
+        assert_eq!(merged.room_of, vec![0, 0, 0, 1, 1, 1, 2, 2, 2]);
+
+        for room in merged.cells.iter_cells() {
+            assert_eq!(room.points().len(), 4);
+            assert!((signed_area(room.points()) - 3.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn random_merge() {
+        let cells = fortune::compute(&random_points(80)).cells(&square(1.0));
+        let total: f64 = areas(&cells).iter().sum();
+
+        let merged = merge_random(&cells, 3, 0.2);
+
+        assert!(merged.cells.len() < cells.len() / 2);
+        assert!((areas(&merged.cells).iter().sum::<f64>() - total).abs() < 1e-9);
+        assert!(merged.cells.room_graph().is_connected());
+
+        for (i, cell) in cells.iter_cells().enumerate() {
+            let room = merged.cells.iter_cells().nth(merged.room_of[i]).unwrap();
+            assert!(signed_area(room.points()) >= signed_area(cell.points()) - 1e-12);
+        }
+
+        let again = merge_random(&cells, 3, 0.2);
+        assert_eq!(again.room_of, merged.room_of);
+        assert_ne!(merge_random(&cells, 4, 0.2).room_of, merged.room_of);
+    }
+}
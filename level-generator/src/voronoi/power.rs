@@ -0,0 +1,143 @@
+//! Power diagrams (weighted Voronoi diagrams).
+//!
+//! The power distance from a point `p` to a site `s` of weight `w` is `|p - s|² - w`, and each
+//! cell holds the points whose power distance to its site is the lowest. A site with a weight of
+//! `r²` behaves like a circle of radius `r`: heavier sites push the walls away and get bigger
+//! rooms, while the walls stay straight lines.
+
+use voronator::{delaunator::Point, polygon::Polygon};
+
+use super::{fortune::clip_power_half_plane, Cells};
+
+/// Computes the power diagram of `points` with their `weights`, inside `bounds`.
+///
+/// Like `Diagram::cells`, the bounding polygon is expected to be convex and counter-clockwise, and
+/// there is one cell per input point, in the same order. Unlike Voronoi cells, a power cell does
+/// not always contain its site, and a site overshadowed by a much heavier neighbour gets an
+/// empty cell.
+pub fn compute(points: &[Point], weights: &[f64], bounds: &Polygon<Point>) -> Cells {
+    assert_eq!(points.len(), weights.len());
+
+    Cells(
+        (0..points.len())
+            .map(|i| {
+                let site = (&points[i], weights[i]);
+
+                let cell = (0..points.len())
+                    .filter(|&j| j != i)
+                    .try_fold(bounds.points().to_vec(), |cell, j| {
+                        if points[j] == points[i] {
+                            // the same site twice, the heaviest one takes the cell, or the
+                            // first one if they weigh the same
+                            let wins =
+                                weights[i] > weights[j] || (weights[i] == weights[j] && i < j);
+                            return wins.then_some(cell);
+                        }
+
+                        let clipped = clip_power_half_plane(&cell, site, (&points[j], weights[j]));
+                        (clipped.len() >= 3).then_some(clipped)
+                    })
+                    .unwrap_or_default();
+
+                Polygon::from_points(cell)
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        geometry::{containment::locate_point, containment::Location, signed_area},
+        random_points,
+        test_util::{p, square},
+        voronoi::fortune,
+    };
+
+    fn areas(cells: &Cells) -> Vec<f64> {
+        cells
+            .iter_cells()
+            .map(|c| signed_area(c.points()))
+            .collect()
+    }
+
+    #[test]
+    fn equal_weights_give_voronoi_cells() {
+        let points = random_points(50);
+        let bounds = square(1.0);
+
+        for weight in [0.0, 0.3] {
+            let power = areas(&compute(&points, &vec![weight; 50], &bounds));
+            let voronoi = areas(&fortune::compute(&points).cells(&bounds));
+
+            for (a, b) in power.iter().zip(voronoi.iter()) {
+                assert!((a - b).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn heavier_site_gets_bigger() {
+        let points = vec![p(-0.5, 0.0), p(0.5, 0.0)];
+        let bounds = square(1.0);
+
+        let even = areas(&compute(&points, &[0.0, 0.0], &bounds));
+        assert!((even[0] - 2.0).abs() < 1e-12);
+
+        // the wall moves to x = 0.25
+        let uneven = areas(&compute(&points, &[0.5, 0.0], &bounds));
+        assert!((uneven[0] - 2.5).abs() < 1e-12);
+        assert!((uneven[1] - 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn cells_cover_the_bounds() {
+        let points = random_points(40);
+        let weights: Vec<f64> = (0..40).map(|i| (i % 7) as f64 * 0.01).collect();
+        let bounds = square(1.0);
+
+        let cells = compute(&points, &weights, &bounds);
+        let total: f64 = areas(&cells).iter().sum();
+        assert!((total - 4.0).abs() < 1e-9);
+
+        // every point of the square is in the cell with the lowest power distance
+        for q in random_points(100).iter().map(|q| p(q.y * 0.9, q.x * 0.9)) {
+            let power =
+                |i: usize| (q.x - points[i].x).powi(2) + (q.y - points[i].y).powi(2) - weights[i];
+            let best = (0..40)
+                .min_by(|&a, &b| power(a).total_cmp(&power(b)))
+                .unwrap();
+
+            assert_ne!(
+                locate_point(&q, cells.iter_cells().nth(best).unwrap()),
+                Location::Outside
+            );
+        }
+    }
+
+    #[test]
+    fn overshadowed_site_is_empty() {
+        let points = vec![p(0.0, 0.0), p(0.1, 0.0), p(0.5, 0.5)];
+
+        let cells = compute(&points, &[1.0, 0.0, 0.0], &square(1.0));
+
+        assert!(cells.iter_cells().nth(1).unwrap().points().is_empty());
+        assert!(!cells.iter_cells().nth(2).unwrap().points().is_empty());
+    }
+
+    #[test]
+    fn duplicate_sites() {
+        let points = vec![p(0.0, 0.0), p(0.0, 0.0), p(0.5, 0.5)];
+
+        let cells = compute(&points, &[0.0, 0.0, 0.0], &square(1.0));
+        assert!(!cells.iter_cells().next().unwrap().points().is_empty());
+        assert!(cells.iter_cells().nth(1).unwrap().points().is_empty());
+
+        let cells = compute(&points, &[0.0, 0.1, 0.0], &square(1.0));
+        assert!(cells.iter_cells().next().unwrap().points().is_empty());
+        assert!(!cells.iter_cells().nth(1).unwrap().points().is_empty());
+    }
+}